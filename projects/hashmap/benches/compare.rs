@@ -0,0 +1,214 @@
+// compare.rs
+// Compare the open-addressing HashMap against the previous separately-chained
+// implementation and std::collections::HashMap.
+//
+// Run with `cargo bench`; timings are printed as nanoseconds per operation.
+
+extern crate hashmap;
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::hint::black_box;
+use std::time::Instant;
+
+const SIZES: [usize; 3] = [1_000, 100_000, 1_000_000];
+
+// The table from before the move to open addressing, kept here as a baseline.
+mod chained {
+    use super::*;
+
+    pub struct HashMap<K, V> {
+        buckets: Vec<Vec<(K, V)>>,
+        item_count: usize,
+        hash_builder: RandomState,
+    }
+
+    impl<K: Hash + Eq, V> HashMap<K, V> {
+        pub fn new() -> Self {
+            Self {
+                buckets: Vec::new(),
+                item_count: 0,
+                hash_builder: RandomState::new(),
+            }
+        }
+
+        fn bucket<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+            (self.hash_builder.hash_one(key) % self.buckets.len() as u64) as usize
+        }
+
+        fn resize(&mut self) {
+            let target_size = match self.buckets.len() {
+                0 => 1,
+                n => n * 2,
+            };
+            let mut new_buckets: Vec<Vec<(K, V)>> = (0..target_size).map(|_| Vec::new()).collect();
+            for (key, value) in self.buckets.iter_mut().flat_map(|bucket| bucket.drain(..)) {
+                let bucket = (self.hash_builder.hash_one(&key) % target_size as u64) as usize;
+                new_buckets[bucket].push((key, value));
+            }
+            self.buckets = new_buckets;
+        }
+
+        pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+            if self.buckets.is_empty() || self.item_count > 3 * self.buckets.len() / 4 {
+                self.resize();
+            }
+            let bucket = self.bucket(&key);
+            let bucket = &mut self.buckets[bucket];
+            for (ekey, evalue) in bucket.iter_mut() {
+                if *ekey == key {
+                    return Some(std::mem::replace(evalue, value));
+                }
+            }
+            bucket.push((key, value));
+            self.item_count += 1;
+            None
+        }
+
+        pub fn get<Q>(&self, key: &Q) -> Option<&V>
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            if self.buckets.is_empty() {
+                return None;
+            }
+            self.buckets[self.bucket(key)]
+                .iter()
+                .find(|(ekey, _)| ekey.borrow() == key)
+                .map(|(_, evalue)| evalue)
+        }
+
+        pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            if self.buckets.is_empty() {
+                return None;
+            }
+            let bucket = self.bucket(key);
+            let bucket = &mut self.buckets[bucket];
+            let i = bucket.iter().position(|(ekey, _)| ekey.borrow() == key)?;
+            self.item_count -= 1;
+            Some(bucket.swap_remove(i).1)
+        }
+    }
+}
+
+// The operations exercised by each benchmark, implemented for each map.
+trait Map {
+    fn name() -> &'static str;
+    fn new() -> Self;
+    fn insert(&mut self, key: u64, value: u64);
+    fn get(&self, key: &u64) -> Option<&u64>;
+    fn remove(&mut self, key: &u64) -> Option<u64>;
+}
+
+macro_rules! impl_map {
+    ($ty:ty, $name:expr) => {
+        impl Map for $ty {
+            fn name() -> &'static str {
+                $name
+            }
+            fn new() -> Self {
+                <$ty>::new()
+            }
+            fn insert(&mut self, key: u64, value: u64) {
+                <$ty>::insert(self, key, value);
+            }
+            fn get(&self, key: &u64) -> Option<&u64> {
+                <$ty>::get(self, key)
+            }
+            fn remove(&mut self, key: &u64) -> Option<u64> {
+                <$ty>::remove(self, key)
+            }
+        }
+    };
+}
+
+impl_map!(hashmap::HashMap<u64, u64>, "hashmap");
+impl_map!(chained::HashMap<u64, u64>, "chained");
+impl_map!(std::collections::HashMap<u64, u64>, "std");
+
+// a cheap deterministic sequence of distinct keys
+fn keys(n: usize) -> Vec<u64> {
+    (0..n as u64).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15)).collect()
+}
+
+fn report(name: &str, map: &str, n: usize, ops: usize, start: Instant) {
+    let nanos = start.elapsed().as_nanos() as f64 / ops as f64;
+    println!("{:<16} {:<8} n={:<8} {:>8.1} ns/op", name, map, n, nanos);
+}
+
+fn bench_insert<M: Map>(n: usize) {
+    let keys = keys(n);
+    let start = Instant::now();
+    let mut map = M::new();
+    for &k in &keys {
+        map.insert(k, k);
+    }
+    black_box(&map);
+    report("insert", M::name(), n, n, start);
+}
+
+fn bench_get_hit<M: Map>(n: usize) {
+    let keys = keys(n);
+    let mut map = M::new();
+    for &k in &keys {
+        map.insert(k, k);
+    }
+
+    let start = Instant::now();
+    for k in &keys {
+        black_box(map.get(k));
+    }
+    report("get (hit)", M::name(), n, n, start);
+}
+
+fn bench_get_miss<M: Map>(n: usize) {
+    let keys = keys(2 * n);
+    let mut map = M::new();
+    for &k in &keys[..n] {
+        map.insert(k, k);
+    }
+
+    let start = Instant::now();
+    for k in &keys[n..] {
+        black_box(map.get(k));
+    }
+    report("get (miss)", M::name(), n, n, start);
+}
+
+fn bench_remove_insert<M: Map>(n: usize) {
+    let keys = keys(2 * n);
+    let mut map = M::new();
+    for &k in &keys[..n] {
+        map.insert(k, k);
+    }
+
+    // churn: remove an old key and insert a new one, leaving tombstones behind
+    let start = Instant::now();
+    for i in 0..n {
+        black_box(map.remove(&keys[i]));
+        map.insert(keys[n + i], i as u64);
+    }
+    report("remove+insert", M::name(), n, 2 * n, start);
+}
+
+fn run<M: Map>(n: usize) {
+    bench_insert::<M>(n);
+    bench_get_hit::<M>(n);
+    bench_get_miss::<M>(n);
+    bench_remove_insert::<M>(n);
+}
+
+fn main() {
+    for &n in &SIZES {
+        run::<hashmap::HashMap<u64, u64>>(n);
+        run::<chained::HashMap<u64, u64>>(n);
+        run::<std::collections::HashMap<u64, u64>>(n);
+        println!();
+    }
+}
@@ -0,0 +1,58 @@
+// hashers.rs
+// Compare insert and lookup cost for each of the crate's hashers, along with
+// std's RandomState, on integer and string keys.
+//
+// Run with `cargo bench --bench hashers`; timings are printed as nanoseconds per operation.
+
+extern crate hashmap;
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::hint::black_box;
+use std::time::Instant;
+
+use hashmap::hash::{FxBuildHasher, IdentityBuildHasher, RandomSipState};
+use hashmap::HashMap;
+
+const N: usize = 100_000;
+
+fn report(op: &str, keys: &str, hasher: &str, start: Instant) {
+    let nanos = start.elapsed().as_nanos() as f64 / N as f64;
+    println!("{:<8} {:<8} {:<12} {:>8.1} ns/op", op, keys, hasher, nanos);
+}
+
+fn bench<K, S>(keys: &[K], key_kind: &str, hasher: &str)
+where
+    K: Hash + Eq + Clone,
+    S: BuildHasher + Default,
+{
+    let start = Instant::now();
+    let mut map: HashMap<K, usize, S> = HashMap::with_hasher(S::default());
+    for (i, k) in keys.iter().enumerate() {
+        map.insert(k.clone(), i);
+    }
+    black_box(&map);
+    report("insert", key_kind, hasher, start);
+
+    let start = Instant::now();
+    for k in keys {
+        black_box(map.get(k));
+    }
+    report("get", key_kind, hasher, start);
+}
+
+fn main() {
+    // scattered rather than sequential, as identity hashing relies on well-distributed keys
+    let ints: Vec<u64> = (0..N as u64).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15)).collect();
+    let strings: Vec<String> = (0..N).map(|i| format!("key-{:08}", i)).collect();
+
+    bench::<_, RandomState>(&ints, "u64", "std");
+    bench::<_, FxBuildHasher>(&ints, "u64", "fx");
+    bench::<_, RandomSipState>(&ints, "u64", "siphash-1-3");
+    bench::<_, IdentityBuildHasher>(&ints, "u64", "identity");
+    println!();
+
+    bench::<_, RandomState>(&strings, "String", "std");
+    bench::<_, FxBuildHasher>(&strings, "String", "fx");
+    bench::<_, RandomSipState>(&strings, "String", "siphash-1-3");
+}
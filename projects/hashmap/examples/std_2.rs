@@ -24,5 +24,10 @@ fn main() {
     // update a key, guarding against the key possibly not being set
     let stat = player_stats.entry("attack").or_insert(100);
     *stat += random_stat_buff();
+
+    // modify an entry before an insert with in-place mutation
+    player_stats.entry("mana").and_modify(|mana| *mana += 200).or_insert(100);
+
+    println!("{:?}", player_stats);
 }
 
@@ -0,0 +1,28 @@
+// std_4.rs
+
+extern crate hashmap;
+use hashmap::HashMap;
+
+fn main() {
+    // A HashMap with a known list of items can be initialized from an array.
+    let solar_distance = HashMap::from([
+        ("Mercury", 0.4),
+        ("Venus", 0.7),
+        ("Earth", 1.0),
+        ("Mars", 1.5),
+    ]);
+
+    // Or collected from any iterator of key-value pairs.
+    let timber_resources: HashMap<&str, i32> = [("Norway", 100), ("Denmark", 50), ("Iceland", 10)]
+        .iter()
+        .cloned()
+        .collect();
+
+    println!("{:?}", solar_distance);
+    println!("{:?}", timber_resources);
+
+    // Maps compare equal when they hold the same entries, in any order.
+    let copy = timber_resources.clone();
+    assert_eq!(copy, timber_resources);
+    println!("Iceland has {} timber resources", copy["Iceland"]);
+}
@@ -0,0 +1,166 @@
+// builder.rs
+// Builder for HashMaps that need more than the default configuration.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+use crate::raw::DEFAULT_MAX_LOAD_FACTOR;
+use crate::stats::{ProbeGuard, Reseed};
+use crate::table::Table;
+use crate::HashMap;
+
+/// Configures the capacity, maximum load factor and hasher of a new HashMap.
+pub struct Builder<S = RandomState> {
+    capacity: usize,
+    max_load_factor: f32,
+    incremental_resize: bool,
+    probe_guard: ProbeGuard<S>,
+    hash_builder: S
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self {
+            capacity: 0,
+            max_load_factor: DEFAULT_MAX_LOAD_FACTOR,
+            incremental_resize: false,
+            probe_guard: ProbeGuard::Off,
+            hash_builder: RandomState::new()
+        }
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Builder<S> 
+where
+    S: BuildHasher
+{
+    /// Allocate room for at least this many entries up front.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// The fraction of the table that may fill before it grows.
+    ///
+    /// Lower values trade memory for shorter probe sequences. Panics unless
+    /// the factor lies in (0, 1).
+    pub fn max_load_factor(mut self, max_load_factor: f32) -> Self {
+        assert!(
+            max_load_factor > 0.0 && max_load_factor < 1.0,
+            "max load factor must lie in (0, 1), got {}",
+            max_load_factor
+        );
+        self.max_load_factor = max_load_factor;
+        self
+    }
+
+    /// Spread the cost of growing the table over later operations.
+    ///
+    /// When the table fills, it is set aside and its entries are moved into
+    /// the new table a few at a time by each subsequent insert and remove,
+    /// rather than all at once. This bounds the latency of any one insert at
+    /// the cost of lookups checking both tables until the move completes.
+    pub fn incremental_resize(mut self, incremental: bool) -> Self {
+        self.incremental_resize = incremental;
+        self
+    }
+
+    /// In debug builds, panic when a new entry lands more than PROBE_LIMIT
+    /// groups from its home group, which signals a hasher unfit for the
+    /// keys. Off by default, since a valid if clustered hasher still gives
+    /// a working map; release builds never check. MapStats::is_pathological
+    /// reports the same condition after the fact.
+    pub fn check_probe_lengths(mut self, check: bool) -> Self {
+        self.probe_guard = if check { ProbeGuard::Panic } else { ProbeGuard::Off };
+        self
+    }
+
+    /// Rather than panicking on a pathological probe length, re-seed the
+    /// hasher with fresh random keys and rehash every entry.
+    ///
+    /// Set the hasher first; changing it afterwards keeps the check but
+    /// falls back to panicking.
+    pub fn reseed_on_long_probes(mut self) -> Self 
+    where
+        S: Reseed
+    {
+        self.probe_guard = ProbeGuard::reseeding();
+        self
+    }
+
+    pub fn hasher<T>(self, hash_builder: T) -> Builder<T> 
+    where
+        T: BuildHasher
+    {
+        let probe_guard = match self.probe_guard {
+            ProbeGuard::Off => ProbeGuard::Off,
+            _ => ProbeGuard::Panic,
+        };
+        Builder {
+            capacity: self.capacity,
+            max_load_factor: self.max_load_factor,
+            incremental_resize: self.incremental_resize,
+            probe_guard,
+            hash_builder
+        }
+    }
+
+    pub fn build<K, V>(self) -> HashMap<K, V, S> {
+        let mut table = Table::with_capacity(self.capacity, self.max_load_factor);
+        table.set_incremental(self.incremental_resize);
+        HashMap {
+            table,
+            hash_builder: self.hash_builder,
+            probe_guard: self.probe_guard
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::FxBuildHasher;
+
+    #[test]
+    fn load_factor() {
+        let mut map : HashMap<i32, i32> = Builder::new().max_load_factor(0.5).build();
+        assert_eq!(map.max_load_factor(), 0.5);
+
+        for i in 0..1000 {
+            map.insert(i, i);
+            // buckets are a power of two, so at most half are ever filled
+            assert!(map.capacity() <= 2 * map.len().max(8));
+        }
+        for i in 0..1000 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+
+        // the load factor survives clearing and shrinking
+        map.clear();
+        map.shrink_to_fit();
+        map.insert(0, 0);
+        assert_eq!(map.max_load_factor(), 0.5);
+    }
+
+    #[test]
+    fn capacity_and_hasher() {
+        let map : HashMap<i32, i32, FxBuildHasher> = Builder::new()
+            .capacity(100)
+            .hasher(FxBuildHasher::default())
+            .build();
+        assert!(map.capacity() >= 100);
+        assert_eq!(map.max_load_factor(), DEFAULT_MAX_LOAD_FACTOR);
+    }
+
+    #[test]
+    #[should_panic(expected = "max load factor")]
+    fn rejects_full_load() {
+        let _ = Builder::new().max_load_factor(1.0);
+    }
+}
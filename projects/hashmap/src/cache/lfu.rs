@@ -0,0 +1,458 @@
+// lfu.rs
+// Cache that evicts the least frequently used entries first.
+//
+// Entries with the same access count share a group, and the groups form a
+// list ordered by count, so the next victim is always at the tail of the
+// first group. An access moves an entry into the group for the next count,
+// creating it right after the current one if needed, which keeps every
+// operation O(1) (Shah, Mitra and Matani's "O(1) algorithm for implementing
+// the LFU cache eviction scheme"). Ties are broken by recency.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use super::{CacheStats, List, Node, Store, NIL};
+
+struct Group {
+    count: u64,
+    // entries with this count, most recently used at the head
+    list: List,
+    prev: usize,
+    next: usize,
+}
+
+pub struct LfuCache<K, V, S = RandomState> {
+    store: Store<K, V, S>,
+    // a slab of groups; indices of unused groups are kept in `free_groups`
+    groups: Vec<Group>,
+    free_groups: Vec<usize>,
+    // the group with the lowest count
+    first: usize,
+}
+
+impl<K, V> LfuCache<K, V> {
+    /// A cache holding at most `capacity` entries.
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, RandomState::new())
+    }
+
+    /// A cache whose entries, as measured by `weigher`, weigh at most
+    /// `max_weight` in total.
+    ///
+    /// Panics if `max_weight` is zero.
+    pub fn weighted<F>(max_weight: usize, weigher: F) -> Self
+    where
+        F: Fn(&K, &V) -> usize + Send + 'static
+    {
+        Self::weighted_with_hasher(max_weight, weigher, RandomState::new())
+    }
+}
+
+impl<K, V, S> LfuCache<K, V, S> {
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self::from_store(Store::new(capacity, None, hash_builder))
+    }
+
+    pub fn weighted_with_hasher<F>(max_weight: usize, weigher: F, hash_builder: S) -> Self
+    where
+        F: Fn(&K, &V) -> usize + Send + 'static
+    {
+        Self::from_store(Store::new(max_weight, Some(Box::new(weigher)), hash_builder))
+    }
+
+    fn from_store(store: Store<K, V, S>) -> Self {
+        Self { store, groups: Vec::new(), free_groups: Vec::new(), first: NIL }
+    }
+
+    /// Call `f` with every entry evicted to keep the cache within its bound.
+    ///
+    /// Entries removed explicitly, through pop, pop_lfu or clear, are
+    /// returned to the caller instead.
+    pub fn set_on_evict<F>(&mut self, f: F)
+    where
+        F: FnMut(K, V) + Send + 'static
+    {
+        self.store.on_evict = Some(Box::new(f));
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.len() == 0
+    }
+
+    /// The total weight of the entries; the same as len() without a weigher.
+    pub fn weight(&self) -> usize {
+        self.store.weight
+    }
+
+    pub fn max_weight(&self) -> usize {
+        self.store.max_weight
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.store.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.store.stats = CacheStats::default();
+    }
+
+    pub fn clear(&mut self) {
+        self.store.clear();
+        self.groups.clear();
+        self.free_groups.clear();
+        self.first = NIL;
+    }
+
+    /// The entry that would be evicted next, without touching it.
+    pub fn peek_lfu(&self) -> Option<(&K, &V)> {
+        if self.first == NIL {
+            return None;
+        }
+        let node = self.store.node(self.groups[self.first].list.tail);
+        Some((&node.key, &node.value))
+    }
+
+    /// Remove and return the least frequently used entry, breaking ties by
+    /// recency.
+    pub fn pop_lfu(&mut self) -> Option<(K, V)> {
+        self.remove_lfu().map(|node| (node.key, node.value))
+    }
+
+    fn remove_lfu(&mut self) -> Option<Node<K, V>> {
+        if self.first == NIL {
+            return None;
+        }
+        let i = self.groups[self.first].list.tail;
+        self.unlink(i);
+        Some(self.store.remove(i))
+    }
+
+    fn evict_excess(&mut self) {
+        // making room for a new entry may empty the cache without succeeding
+        while self.store.is_over() {
+            match self.remove_lfu() {
+                Some(node) => self.store.evicted(node),
+                None => break,
+            }
+        }
+    }
+
+    // Create an empty group for `count`, linked in after `prev` (or first if NIL).
+    fn new_group(&mut self, count: u64, prev: usize) -> usize {
+        let next = if prev == NIL { self.first } else { self.groups[prev].next };
+        let group = Group { count, list: List::EMPTY, prev, next };
+        let g = match self.free_groups.pop() {
+            Some(g) => {
+                self.groups[g] = group;
+                g
+            },
+            None => {
+                self.groups.push(group);
+                self.groups.len() - 1
+            },
+        };
+
+        if prev == NIL {
+            self.first = g;
+        } else {
+            self.groups[prev].next = g;
+        }
+        if next != NIL {
+            self.groups[next].prev = g;
+        }
+        g
+    }
+
+    fn push(&mut self, g: usize, i: usize) {
+        self.store.push_front(&mut self.groups[g].list, i);
+        self.store.node_mut(i).group = g;
+    }
+
+    // Take a node out of its group, dropping the group if that empties it.
+    fn unlink(&mut self, i: usize) {
+        let g = self.store.node(i).group;
+        self.store.unlink(&mut self.groups[g].list, i);
+        if !self.groups[g].list.is_empty() {
+            return;
+        }
+
+        let Group { prev, next, .. } = self.groups[g];
+        if prev == NIL {
+            self.first = next;
+        } else {
+            self.groups[prev].next = next;
+        }
+        if next != NIL {
+            self.groups[next].prev = prev;
+        }
+        self.free_groups.push(g);
+    }
+
+    // Count an access to a node, moving it to the group for the next count.
+    fn touch(&mut self, i: usize) {
+        let g = self.store.node(i).group;
+        let count = self.groups[g].count.saturating_add(1);
+        let next = self.groups[g].next;
+
+        let target = if next != NIL && self.groups[next].count == count {
+            next
+        } else if self.groups[g].list.head == self.groups[g].list.tail {
+            // the node is alone in its group, which can simply take the new count
+            self.groups[g].count = count;
+            self.store.unlink(&mut self.groups[g].list, i);
+            g
+        } else {
+            self.new_group(count, g)
+        };
+
+        if target != g {
+            self.unlink(i);
+        }
+        self.push(target, i);
+    }
+}
+
+impl<K, V, S> LfuCache<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher
+{
+    /// Insert or update an entry, counting it as an access, and return the
+    /// previous value for the key.
+    ///
+    /// Least frequently used entries are evicted until the cache is within
+    /// its bound. Room for a new entry is made before it is added, since with
+    /// a count of 1 it would otherwise be its own victim; an entry heavier
+    /// than the bound evicts everything, itself included.
+    pub fn put(&mut self, key: K, value: V) -> Option<V> {
+        let hash = self.store.hash(&key);
+        let weight = self.store.weigh(&key, &value);
+
+        let old = match self.store.find(hash, &key) {
+            Some(i) => {
+                let node = self.store.node_mut(i);
+                let old = std::mem::replace(&mut node.value, value);
+                let old_weight = std::mem::replace(&mut node.weight, weight);
+                self.store.weight = self.store.weight - old_weight + weight;
+                self.touch(i);
+                Some(old)
+            },
+            None => {
+                // make room first, so the new entry isn't its own victim
+                self.store.weight += weight;
+                self.evict_excess();
+                self.store.weight -= weight;
+
+                let i = self.store.insert(hash, key, value, weight);
+                let g = if self.first != NIL && self.groups[self.first].count == 1 {
+                    self.first
+                } else {
+                    self.new_group(1, NIL)
+                };
+                self.push(g, i);
+                None
+            },
+        };
+        self.evict_excess();
+        old
+    }
+
+    /// Look up an entry and count an access to it, as well as a hit or a miss.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.store.hash(key);
+        match self.store.find(hash, key) {
+            Some(i) => {
+                self.store.stats.hits += 1;
+                self.touch(i);
+                Some(&self.store.node(i).value)
+            },
+            None => {
+                self.store.stats.misses += 1;
+                None
+            },
+        }
+    }
+
+    /// Look up an entry without counting an access or the lookup.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.store.hash(key);
+        self.store.find(hash, key).map(|i| &self.store.node(i).value)
+    }
+
+    /// The number of accesses counted for an entry.
+    pub fn frequency<Q>(&self, key: &Q) -> Option<u64>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.store.hash(key);
+        self.store.find(hash, key).map(|i| self.groups[self.store.node(i).group].count)
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek(key).is_some()
+    }
+
+    /// Remove an entry, returning its value.
+    pub fn pop<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.store.hash(key);
+        let i = self.store.find(hash, key)?;
+        self.unlink(i);
+        Some(self.store.remove(i).value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn evicts_least_frequent() {
+        let mut cache = LfuCache::new(3);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+        cache.get("a");
+        cache.get("a");
+        cache.get("b");
+        assert_eq!(cache.frequency("a"), Some(3));
+        assert_eq!(cache.frequency("b"), Some(2));
+        assert_eq!(cache.frequency("c"), Some(1));
+
+        // the new entry evicts "c", the least used, rather than itself
+        cache.put("d", 4);
+        assert!(!cache.contains("c"));
+        assert_eq!(cache.frequency("d"), Some(1));
+
+        // peek doesn't count
+        cache.peek("d");
+        assert_eq!(cache.peek_lfu(), Some((&"d", &4)));
+        assert_eq!(cache.pop_lfu(), Some(("d", 4)));
+        assert_eq!(cache.pop_lfu(), Some(("b", 2)));
+        assert_eq!(cache.pop_lfu(), Some(("a", 1)));
+        assert_eq!(cache.pop_lfu(), None);
+    }
+
+    #[test]
+    fn ties_break_by_recency() {
+        let mut cache = LfuCache::new(3);
+        for i in 0..3 {
+            cache.put(i, i);
+        }
+        for i in (0..3).rev() {
+            cache.get(&i);
+        }
+        // all have count 2; 2 was touched longest ago
+        cache.put(3, 3);
+        assert!(!cache.contains(&2));
+        assert_eq!(cache.pop_lfu(), Some((3, 3)));
+        assert_eq!(cache.pop_lfu(), Some((1, 1)));
+        assert_eq!(cache.pop_lfu(), Some((0, 0)));
+    }
+
+    #[test]
+    fn weighted_and_callback() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let mut cache = LfuCache::weighted(10, |_: &i32, v: &Vec<u8>| v.len());
+        let log = evicted.clone();
+        cache.set_on_evict(move |k, _| log.lock().unwrap().push(k));
+
+        cache.put(1, vec![0; 4]);
+        cache.put(2, vec![0; 4]);
+        cache.get(&1);
+        cache.put(3, vec![0; 5]);
+        assert_eq!(*evicted.lock().unwrap(), vec![2]);
+        assert_eq!(cache.weight(), 9);
+
+        cache.put(4, vec![0; 11]);
+        assert!(cache.is_empty());
+        assert_eq!(*evicted.lock().unwrap(), vec![2, 3, 1, 4]);
+        assert_eq!(cache.stats().evictions, 4);
+
+        cache.pop(&1);
+        cache.clear();
+        assert_eq!(cache.stats().evictions, 4);
+    }
+
+    #[test]
+    fn stats() {
+        let mut cache = LfuCache::new(2);
+        cache.put(1, 1);
+        cache.get(&1);
+        cache.get(&2);
+        cache.peek(&2);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1, evictions: 0 });
+    }
+
+    #[test]
+    fn churn() {
+        // check against a model that scans for the victim
+        let mut cache = LfuCache::new(20);
+        // key -> (count, last use)
+        let mut model: Vec<(i32, u64, u64)> = Vec::new();
+        let mut x = 7u32;
+        for time in 0..20_000u64 {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let key = (x >> 16) as i32 % 40;
+            let present = model.iter().position(|&(k, _, _)| k == key);
+            if x.is_multiple_of(3) {
+                assert_eq!(cache.get(&key).is_some(), present.is_some());
+                if let Some(p) = present {
+                    model[p].1 += 1;
+                    model[p].2 = time;
+                }
+            } else {
+                cache.put(key, key);
+                match present {
+                    Some(p) => {
+                        model[p].1 += 1;
+                        model[p].2 = time;
+                    },
+                    None => {
+                        if model.len() == 20 {
+                            let victim = (0..model.len())
+                                .min_by_key(|&p| (model[p].1, model[p].2))
+                                .unwrap();
+                            model.remove(victim);
+                        }
+                        model.push((key, 1, time));
+                    },
+                }
+            }
+        }
+        assert_eq!(cache.len(), model.len());
+        for &(k, count, _) in &model {
+            assert_eq!(cache.frequency(&k), Some(count));
+        }
+    }
+
+    #[test]
+    fn send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<LfuCache<String, Vec<u8>>>();
+    }
+}
@@ -0,0 +1,396 @@
+// lru.rs
+// Cache that evicts the least recently used entries first.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use super::{CacheStats, List, Node, Store, NIL};
+
+pub struct LruCache<K, V, S = RandomState> {
+    store: Store<K, V, S>,
+    // most recently used at the head, next to be evicted at the tail
+    list: List,
+}
+
+impl<K, V> LruCache<K, V> {
+    /// A cache holding at most `capacity` entries.
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, RandomState::new())
+    }
+
+    /// A cache whose entries, as measured by `weigher`, weigh at most
+    /// `max_weight` in total.
+    ///
+    /// Panics if `max_weight` is zero.
+    pub fn weighted<F>(max_weight: usize, weigher: F) -> Self
+    where
+        F: Fn(&K, &V) -> usize + Send + 'static
+    {
+        Self::weighted_with_hasher(max_weight, weigher, RandomState::new())
+    }
+}
+
+impl<K, V, S> LruCache<K, V, S> {
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self { store: Store::new(capacity, None, hash_builder), list: List::EMPTY }
+    }
+
+    pub fn weighted_with_hasher<F>(max_weight: usize, weigher: F, hash_builder: S) -> Self
+    where
+        F: Fn(&K, &V) -> usize + Send + 'static
+    {
+        Self { store: Store::new(max_weight, Some(Box::new(weigher)), hash_builder), list: List::EMPTY }
+    }
+
+    /// Call `f` with every entry evicted to keep the cache within its bound.
+    ///
+    /// Entries removed explicitly, through pop, pop_lru or clear, are
+    /// returned to the caller instead.
+    pub fn set_on_evict<F>(&mut self, f: F)
+    where
+        F: FnMut(K, V) + Send + 'static
+    {
+        self.store.on_evict = Some(Box::new(f));
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.len() == 0
+    }
+
+    /// The total weight of the entries; the same as len() without a weigher.
+    pub fn weight(&self) -> usize {
+        self.store.weight
+    }
+
+    pub fn max_weight(&self) -> usize {
+        self.store.max_weight
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.store.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.store.stats = CacheStats::default();
+    }
+
+    pub fn clear(&mut self) {
+        self.store.clear();
+        self.list = List::EMPTY;
+    }
+
+    /// The entry that would be evicted next, without touching it.
+    pub fn peek_lru(&self) -> Option<(&K, &V)> {
+        if self.list.is_empty() {
+            return None;
+        }
+        let node = self.store.node(self.list.tail);
+        Some((&node.key, &node.value))
+    }
+
+    /// Remove and return the least recently used entry.
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        self.remove_lru().map(|node| (node.key, node.value))
+    }
+
+    /// Iterate the entries from most to least recently used.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { nodes: &self.store.nodes, next: self.list.head, len: self.len() }
+    }
+
+    fn remove_lru(&mut self) -> Option<Node<K, V>> {
+        if self.list.is_empty() {
+            return None;
+        }
+        let i = self.list.tail;
+        self.store.unlink(&mut self.list, i);
+        Some(self.store.remove(i))
+    }
+
+    fn evict_excess(&mut self) {
+        while self.store.is_over() {
+            let node = self.remove_lru().expect("an overweight cache is non-empty");
+            self.store.evicted(node);
+        }
+    }
+
+    fn touch(&mut self, i: usize) {
+        self.store.unlink(&mut self.list, i);
+        self.store.push_front(&mut self.list, i);
+    }
+}
+
+impl<K, V, S> LruCache<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher
+{
+    /// Insert or update an entry and mark it most recently used, returning
+    /// the previous value for the key.
+    ///
+    /// Least recently used entries are then evicted until the cache is back
+    /// within its bound; an entry heavier than the bound evicts everything,
+    /// itself included.
+    pub fn put(&mut self, key: K, value: V) -> Option<V> {
+        let hash = self.store.hash(&key);
+        let weight = self.store.weigh(&key, &value);
+
+        let old = match self.store.find(hash, &key) {
+            Some(i) => {
+                let node = self.store.node_mut(i);
+                let old = std::mem::replace(&mut node.value, value);
+                let old_weight = std::mem::replace(&mut node.weight, weight);
+                self.store.weight = self.store.weight - old_weight + weight;
+                self.touch(i);
+                Some(old)
+            },
+            None => {
+                let i = self.store.insert(hash, key, value, weight);
+                self.store.push_front(&mut self.list, i);
+                None
+            },
+        };
+        self.evict_excess();
+        old
+    }
+
+    /// Look up an entry and mark it most recently used, counting a hit or a miss.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.store.hash(key);
+        match self.store.find(hash, key) {
+            Some(i) => {
+                self.store.stats.hits += 1;
+                self.touch(i);
+                Some(&self.store.node(i).value)
+            },
+            None => {
+                self.store.stats.misses += 1;
+                None
+            },
+        }
+    }
+
+    /// Look up an entry without marking it used or counting the lookup.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.store.hash(key);
+        self.store.find(hash, key).map(|i| &self.store.node(i).value)
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek(key).is_some()
+    }
+
+    /// Remove an entry, returning its value.
+    pub fn pop<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.store.hash(key);
+        let i = self.store.find(hash, key)?;
+        self.store.unlink(&mut self.list, i);
+        Some(self.store.remove(i).value)
+    }
+}
+
+/// Iterator over the entries of an LruCache, most recently used first.
+pub struct Iter<'a, K, V> {
+    nodes: &'a [Option<Node<K, V>>],
+    next: usize,
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == NIL {
+            return None;
+        }
+        let node = self.nodes[self.next].as_ref().expect("linked node is occupied");
+        self.next = node.next;
+        self.len -= 1;
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn keys(cache: &LruCache<i32, i32>) -> Vec<i32> {
+        cache.iter().map(|(&k, _)| k).collect()
+    }
+
+    #[test]
+    fn evicts_least_recent() {
+        let mut cache = LruCache::new(3);
+        for i in 0..3 {
+            assert_eq!(cache.put(i, i * 10), None);
+        }
+        assert_eq!(keys(&cache), vec![2, 1, 0]);
+
+        // get promotes, peek doesn't
+        assert_eq!(cache.get(&0), Some(&0));
+        assert_eq!(cache.peek(&1), Some(&10));
+        assert_eq!(keys(&cache), vec![0, 2, 1]);
+
+        cache.put(3, 30);
+        assert_eq!(keys(&cache), vec![3, 0, 2]);
+        assert!(!cache.contains(&1));
+
+        // updating an entry also promotes it
+        assert_eq!(cache.put(2, 21), Some(20));
+        assert_eq!(keys(&cache), vec![2, 3, 0]);
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn pop() {
+        let mut cache = LruCache::new(4);
+        for i in 0..4 {
+            cache.put(i, i);
+        }
+        assert_eq!(cache.peek_lru(), Some((&0, &0)));
+        assert_eq!(cache.pop_lru(), Some((0, 0)));
+        assert_eq!(cache.pop(&2), Some(2));
+        assert_eq!(cache.pop(&2), None);
+        assert_eq!(keys(&cache), vec![3, 1]);
+
+        // freed slots are reused
+        cache.put(5, 5);
+        cache.put(6, 6);
+        assert_eq!(keys(&cache), vec![6, 5, 3, 1]);
+        assert_eq!(cache.store.nodes.len(), 4);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.pop_lru(), None);
+    }
+
+    #[test]
+    fn weighted() {
+        let mut cache = LruCache::weighted(10, |_: &&str, v: &String| v.len());
+        cache.put("a", "xxxx".to_string());
+        cache.put("b", "xxxx".to_string());
+        assert_eq!(cache.weight(), 8);
+
+        // makes room by evicting "a"
+        cache.put("c", "xxx".to_string());
+        assert_eq!(cache.weight(), 7);
+        assert!(!cache.contains("a"));
+
+        // growing an entry in place can evict others
+        cache.put("c", "xxxxxxxx".to_string());
+        assert_eq!(cache.weight(), 8);
+        assert_eq!(cache.len(), 1);
+
+        // too heavy to be cached at all
+        cache.put("d", "x".repeat(11));
+        assert!(cache.is_empty());
+        assert_eq!(cache.weight(), 0);
+        assert_eq!(cache.stats().evictions, 4);
+    }
+
+    #[test]
+    fn eviction_callback() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let mut cache = LruCache::new(2);
+        let log = evicted.clone();
+        cache.set_on_evict(move |k, v| log.lock().unwrap().push((k, v)));
+
+        for i in 0..5 {
+            cache.put(i, -i);
+        }
+        // explicit removals aren't evictions
+        cache.pop_lru();
+        cache.pop(&4);
+        assert_eq!(*evicted.lock().unwrap(), vec![(0, 0), (1, -1), (2, -2)]);
+    }
+
+    #[test]
+    fn stats() {
+        let mut cache = LruCache::new(2);
+        cache.put(1, 1);
+        cache.get(&1);
+        cache.get(&1);
+        cache.get(&2);
+        cache.peek(&2);
+        assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 1, evictions: 0 });
+        assert!((cache.stats().hit_ratio() - 2.0 / 3.0).abs() < 1e-9);
+
+        cache.reset_stats();
+        assert_eq!(cache.stats(), CacheStats::default());
+        assert_eq!(cache.stats().hit_ratio(), 0.0);
+    }
+
+    #[test]
+    fn churn() {
+        // check against a simple model of recency
+        let mut cache = LruCache::new(50);
+        let mut model: Vec<i32> = Vec::new();
+        let mut x = 1u32;
+        for _ in 0..20_000 {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let key = (x >> 16) as i32 % 80;
+            if x.is_multiple_of(3) {
+                let hit = cache.get(&key).is_some();
+                assert_eq!(hit, model.contains(&key));
+                if hit {
+                    model.retain(|&k| k != key);
+                    model.insert(0, key);
+                }
+            } else {
+                cache.put(key, key);
+                model.retain(|&k| k != key);
+                model.insert(0, key);
+                model.truncate(50);
+            }
+        }
+        assert_eq!(keys(&cache), model);
+    }
+
+    #[test]
+    fn send() {
+        // a cache can sit behind a Mutex shared between threads
+        fn assert_send<T: Send>() {}
+        assert_send::<LruCache<String, Vec<u8>>>();
+
+        let cache = Arc::new(Mutex::new(LruCache::weighted(8, |_: &i32, v: &i32| *v as usize)));
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let cache = Arc::clone(&cache);
+                std::thread::spawn(move || cache.lock().unwrap().put(t, 2))
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(cache.lock().unwrap().weight(), 8);
+    }
+}
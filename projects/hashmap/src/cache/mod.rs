@@ -0,0 +1,234 @@
+// cache/mod.rs
+// Bounded caches with least-recently-used and least-frequently-used eviction.
+//
+// Both caches keep their entries in a slab of nodes, linked into lists by
+// index, and find them through a RawTable of node indices keyed by each
+// node's cached hash. Lookups, promotions and evictions are all O(1). The
+// caches differ only in how they order the nodes: LruCache keeps a single
+// recency list, and LfuCache keeps one recency list per access count.
+//
+// A cache is bounded by total weight. Unless a weigher is given, every entry
+// weighs 1, so the bound is simply an entry count.
+
+mod lfu;
+mod lru;
+
+pub use lfu::LfuCache;
+pub use lru::LruCache;
+
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+
+use crate::make_hash;
+use crate::raw::RawTable;
+
+// marks the absence of a node or group in a link
+const NIL: usize = usize::MAX;
+
+/// Counters describing how well a cache is doing, for export as metrics.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups through `get` that found their key.
+    pub hits: u64,
+    /// Lookups through `get` that did not.
+    pub misses: u64,
+    /// Entries dropped to keep the cache within its bound.
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    /// The fraction of lookups that hit, or 0 before any lookups.
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+struct Node<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+    weight: usize,
+    prev: usize,
+    next: usize,
+    // the frequency group holding the node; only used by LfuCache
+    group: usize,
+}
+
+// A doubly linked list of nodes, threaded through the nodes themselves.
+#[derive(Clone, Copy)]
+struct List {
+    head: usize,
+    tail: usize,
+}
+
+impl List {
+    const EMPTY: List = List { head: NIL, tail: NIL };
+
+    fn is_empty(&self) -> bool {
+        self.head == NIL
+    }
+}
+
+type Weigher<K, V> = Box<dyn Fn(&K, &V) -> usize + Send>;
+type EvictionCallback<K, V> = Box<dyn FnMut(K, V) + Send>;
+
+// The storage and bookkeeping shared by both caches.
+struct Store<K, V, S> {
+    // the slab; None marks a free slot, whose index is kept in `free`
+    nodes: Vec<Option<Node<K, V>>>,
+    free: Vec<usize>,
+    // node indices, hashed by the hash cached in the node
+    index: RawTable<usize>,
+    hash_builder: S,
+    weigher: Option<Weigher<K, V>>,
+    max_weight: usize,
+    weight: usize,
+    stats: CacheStats,
+    on_evict: Option<EvictionCallback<K, V>>,
+}
+
+impl<K, V, S> Store<K, V, S> {
+    fn new(max_weight: usize, weigher: Option<Weigher<K, V>>, hash_builder: S) -> Self {
+        assert!(max_weight > 0, "cache capacity must be non-zero");
+        Self {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            index: RawTable::new(),
+            hash_builder,
+            weigher,
+            max_weight,
+            weight: 0,
+            stats: CacheStats::default(),
+            on_evict: None,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    fn node(&self, i: usize) -> &Node<K, V> {
+        self.nodes[i].as_ref().expect("linked node is occupied")
+    }
+
+    fn node_mut(&mut self, i: usize) -> &mut Node<K, V> {
+        self.nodes[i].as_mut().expect("linked node is occupied")
+    }
+
+    fn weigh(&self, key: &K, value: &V) -> usize {
+        self.weigher.as_ref().map_or(1, |weigher| weigher(key, value))
+    }
+
+    fn is_over(&self) -> bool {
+        self.weight > self.max_weight
+    }
+
+    fn push_front(&mut self, list: &mut List, i: usize) {
+        let head = list.head;
+        let node = self.node_mut(i);
+        node.prev = NIL;
+        node.next = head;
+        if head == NIL {
+            list.tail = i;
+        } else {
+            self.node_mut(head).prev = i;
+        }
+        list.head = i;
+    }
+
+    fn unlink(&mut self, list: &mut List, i: usize) {
+        let (prev, next) = {
+            let node = self.node(i);
+            (node.prev, node.next)
+        };
+        if prev == NIL {
+            list.head = next;
+        } else {
+            self.node_mut(prev).next = next;
+        }
+        if next == NIL {
+            list.tail = prev;
+        } else {
+            self.node_mut(next).prev = prev;
+        }
+    }
+
+    // Add an unlinked node to the slab and the index, returning its index.
+    fn insert(&mut self, hash: u64, key: K, value: V, weight: usize) -> usize {
+        let node = Node { hash, key, value, weight, prev: NIL, next: NIL, group: NIL };
+        let i = match self.free.pop() {
+            Some(i) => {
+                self.nodes[i] = Some(node);
+                i
+            },
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            },
+        };
+
+        let nodes = &self.nodes;
+        self.index.insert(hash, i, |&j| nodes[j].as_ref().expect("indexed node is occupied").hash);
+        self.weight += weight;
+        i
+    }
+
+    // Take a node, which must already be unlinked, out of the slab and the index.
+    fn remove(&mut self, i: usize) -> Node<K, V> {
+        let hash = self.node(i).hash;
+        let slot = self.index.find(hash, |&j| j == i).expect("every node is indexed");
+        // SAFETY: find returns a FULL slot
+        unsafe { self.index.remove(slot) };
+
+        let node = self.nodes[i].take().expect("linked node is occupied");
+        self.free.push(i);
+        self.weight -= node.weight;
+        node
+    }
+
+    // Report a node dropped to stay within the bound.
+    fn evicted(&mut self, node: Node<K, V>) {
+        self.stats.evictions += 1;
+        if let Some(on_evict) = &mut self.on_evict {
+            on_evict(node.key, node.value);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.index.clear();
+        self.nodes.clear();
+        self.free.clear();
+        self.weight = 0;
+    }
+}
+
+impl<K, V, S> Store<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher
+{
+    fn hash<Q>(&self, key: &Q) -> u64
+    where
+        Q: Hash + ?Sized,
+    {
+        make_hash(&self.hash_builder, key)
+    }
+
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let nodes = &self.nodes;
+        let slot = self.index.find(hash, |&i| {
+            nodes[i].as_ref().expect("indexed node is occupied").key.borrow() == key
+        })?;
+        // SAFETY: find returns a FULL slot
+        Some(unsafe { *self.index.get(slot) })
+    }
+}
@@ -0,0 +1,657 @@
+// codec.rs
+// Compact binary encoding of maps and the values stored in them.
+//
+// Values are written little-endian, and every variable-length value (strings,
+// vectors, maps) is preceded by its length as a u64. Top-level documents, as
+// written by to_bytes and write_to, start with a magic number and a format
+// version, so that a checkpoint from an incompatible build is rejected
+// rather than misread.
+//
+// Decoding works on an in-memory buffer, which lets it check every stored
+// length against the bytes actually remaining. However long a corrupted
+// length claims a collection is, the room allocated for it up front is
+// bounded by how many items the remaining input could actually hold, and
+// decoding it stops once the input runs out.
+
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Read, Write};
+use std::mem;
+
+use crate::HashMap;
+
+const MAGIC: [u8; 4] = *b"HMAP";
+const VERSION: u16 = 1;
+
+pub trait Encode {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()>;
+}
+
+pub trait Decode: Sized {
+    /// The fewest bytes any encoding of Self takes up. Collections use it to
+    /// bound how many items to allocate room for before decoding them.
+    const MIN_ENCODED_LEN: usize = 0;
+
+    fn decode(d: &mut Decoder<'_>) -> Result<Self, DecodeError>;
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The input ended in the middle of a value.
+    UnexpectedEof,
+    /// The input doesn't start with the expected magic number.
+    BadMagic,
+    /// The input was written by an unknown version of the format.
+    UnsupportedVersion(u16),
+    /// A value was decoded, but was followed by more input.
+    TrailingBytes(usize),
+    /// A length or integer doesn't fit the type being decoded into.
+    Overflow(u64),
+    InvalidBool(u8),
+    InvalidChar(u32),
+    InvalidUtf8,
+    /// An Option's tag byte was neither 0 nor 1.
+    InvalidTag(u8),
+    /// A map contained the same key twice.
+    DuplicateKey,
+    /// Reading the input failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEof => write!(f, "unexpected end of input"),
+            DecodeError::BadMagic => write!(f, "input is not an encoded document"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            DecodeError::TrailingBytes(n) => write!(f, "{} unexpected bytes after the value", n),
+            DecodeError::Overflow(n) => write!(f, "value {} is out of range", n),
+            DecodeError::InvalidBool(b) => write!(f, "invalid bool {}", b),
+            DecodeError::InvalidChar(c) => write!(f, "invalid char {:#x}", c),
+            DecodeError::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            DecodeError::InvalidTag(t) => write!(f, "invalid option tag {}", t),
+            DecodeError::DuplicateKey => write!(f, "duplicate key in map"),
+            DecodeError::Io(e) => write!(f, "read failed: {}", e),
+        }
+    }
+}
+
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DecodeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DecodeError {
+    fn from(e: io::Error) -> Self {
+        DecodeError::Io(e)
+    }
+}
+
+/// A cursor over the input being decoded.
+pub struct Decoder<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Self { input }
+    }
+
+    /// The number of bytes not yet consumed.
+    pub fn remaining(&self) -> usize {
+        self.input.len()
+    }
+
+    /// Consume the next `n` bytes.
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if n > self.input.len() {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        let (bytes, rest) = self.input.split_at(n);
+        self.input = rest;
+        Ok(bytes)
+    }
+
+    /// Decode a length prefix.
+    pub fn read_len(&mut self) -> Result<usize, DecodeError> {
+        usize::decode(self)
+    }
+
+    // How many items of type T a collection of `len` items may safely
+    // allocate room for up front: no more than the bytes left could encode,
+    // counting every item, even an empty one, as at least a byte.
+    fn capacity_hint<T: Decode>(&self, len: usize) -> usize {
+        len.min(self.remaining() / T::MIN_ENCODED_LEN.max(1))
+    }
+
+    // Decode the `len` items of a collection, calling `item` for each.
+    //
+    // Items that take up no input, like (), could be repeated endlessly by
+    // a corrupted length, so once one does, the rest may not outnumber the
+    // bytes left.
+    fn items<F>(&mut self, len: usize, mut item: F) -> Result<(), DecodeError>
+    where
+        F: FnMut(&mut Self) -> Result<(), DecodeError>
+    {
+        for i in 0..len {
+            let before = self.remaining();
+            item(self)?;
+            if self.remaining() == before && len - i - 1 > before {
+                return Err(DecodeError::UnexpectedEof);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Encode a value as a complete document, with a header.
+pub fn to_bytes<T: Encode + ?Sized>(value: &T) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_to(value, &mut out).expect("writing to a Vec never fails");
+    out
+}
+
+/// Write a value as a complete document, with a header.
+pub fn write_to<T: Encode + ?Sized, W: Write + ?Sized>(value: &T, w: &mut W) -> io::Result<()> {
+    w.write_all(&MAGIC)?;
+    VERSION.encode(w)?;
+    value.encode(w)
+}
+
+/// Decode a complete document written by to_bytes or write_to.
+pub fn from_bytes<T: Decode>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let mut d = Decoder::new(bytes);
+    if d.take(MAGIC.len()).map_err(|_| DecodeError::BadMagic)? != MAGIC {
+        return Err(DecodeError::BadMagic);
+    }
+    let version = u16::decode(&mut d)?;
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+
+    let value = T::decode(&mut d)?;
+    match d.remaining() {
+        0 => Ok(value),
+        n => Err(DecodeError::TrailingBytes(n)),
+    }
+}
+
+/// Read and decode a complete document written by to_bytes or write_to.
+pub fn read_from<T: Decode, R: Read + ?Sized>(r: &mut R) -> Result<T, DecodeError> {
+    let mut bytes = Vec::new();
+    r.read_to_end(&mut bytes)?;
+    from_bytes(&bytes)
+}
+
+macro_rules! integer {
+    ($($t:ty),*) => {$(
+        impl Encode for $t {
+            fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+                w.write_all(&self.to_le_bytes())
+            }
+        }
+
+        impl Decode for $t {
+            const MIN_ENCODED_LEN: usize = mem::size_of::<$t>();
+
+            fn decode(d: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+                let mut bytes = [0; mem::size_of::<$t>()];
+                bytes.copy_from_slice(d.take(mem::size_of::<$t>())?);
+                Ok(<$t>::from_le_bytes(bytes))
+            }
+        }
+    )*};
+}
+
+integer!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+// usize and isize are always written as 64 bits, so that documents are portable
+impl Encode for usize {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        (*self as u64).encode(w)
+    }
+}
+
+impl Decode for usize {
+    const MIN_ENCODED_LEN: usize = 8;
+
+    fn decode(d: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        let n = u64::decode(d)?;
+        if n > usize::MAX as u64 {
+            return Err(DecodeError::Overflow(n));
+        }
+        Ok(n as usize)
+    }
+}
+
+impl Encode for isize {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        (*self as i64).encode(w)
+    }
+}
+
+impl Decode for isize {
+    const MIN_ENCODED_LEN: usize = 8;
+
+    fn decode(d: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        let n = i64::decode(d)?;
+        if n < isize::MIN as i64 || n > isize::MAX as i64 {
+            return Err(DecodeError::Overflow(n as u64));
+        }
+        Ok(n as isize)
+    }
+}
+
+impl Encode for f32 {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.to_bits().encode(w)
+    }
+}
+
+impl Decode for f32 {
+    const MIN_ENCODED_LEN: usize = 4;
+
+    fn decode(d: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        u32::decode(d).map(f32::from_bits)
+    }
+}
+
+impl Encode for f64 {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.to_bits().encode(w)
+    }
+}
+
+impl Decode for f64 {
+    const MIN_ENCODED_LEN: usize = 8;
+
+    fn decode(d: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        u64::decode(d).map(f64::from_bits)
+    }
+}
+
+impl Encode for bool {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        (*self as u8).encode(w)
+    }
+}
+
+impl Decode for bool {
+    const MIN_ENCODED_LEN: usize = 1;
+
+    fn decode(d: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        match u8::decode(d)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(DecodeError::InvalidBool(b)),
+        }
+    }
+}
+
+impl Encode for char {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        (*self as u32).encode(w)
+    }
+}
+
+impl Decode for char {
+    const MIN_ENCODED_LEN: usize = 4;
+
+    fn decode(d: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        let c = u32::decode(d)?;
+        char::from_u32(c).ok_or(DecodeError::InvalidChar(c))
+    }
+}
+
+impl Encode for () {
+    fn encode<W: Write + ?Sized>(&self, _: &mut W) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Decode for () {
+    fn decode(_: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(())
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        (**self).encode(w)
+    }
+}
+
+impl Encode for str {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.len().encode(w)?;
+        w.write_all(self.as_bytes())
+    }
+}
+
+impl Encode for String {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.as_str().encode(w)
+    }
+}
+
+impl Decode for String {
+    const MIN_ENCODED_LEN: usize = 8;
+
+    fn decode(d: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        let len = d.read_len()?;
+        let bytes = d.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        match self {
+            None => 0u8.encode(w),
+            Some(value) => {
+                1u8.encode(w)?;
+                value.encode(w)
+            },
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    const MIN_ENCODED_LEN: usize = 1;
+
+    fn decode(d: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        match u8::decode(d)? {
+            0 => Ok(None),
+            1 => T::decode(d).map(Some),
+            t => Err(DecodeError::InvalidTag(t)),
+        }
+    }
+}
+
+macro_rules! tuple {
+    ($($name:ident),+) => {
+        impl<$($name: Encode),+> Encode for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+                let ($($name,)+) = self;
+                $($name.encode(w)?;)+
+                Ok(())
+            }
+        }
+
+        impl<$($name: Decode),+> Decode for ($($name,)+) {
+            const MIN_ENCODED_LEN: usize = 0 $(+ $name::MIN_ENCODED_LEN)+;
+
+            fn decode(d: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+                Ok(($($name::decode(d)?,)+))
+            }
+        }
+    };
+}
+
+tuple!(A);
+tuple!(A, B);
+tuple!(A, B, C);
+tuple!(A, B, C, D);
+
+impl<T: Encode> Encode for [T] {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.len().encode(w)?;
+        for item in self {
+            item.encode(w)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.as_slice().encode(w)
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    const MIN_ENCODED_LEN: usize = 8;
+
+    fn decode(d: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        let len = d.read_len()?;
+        let mut items = Vec::with_capacity(d.capacity_hint::<T>(len));
+        d.items(len, |d| {
+            items.push(T::decode(d)?);
+            Ok(())
+        })?;
+        Ok(items)
+    }
+}
+
+impl<K, V, S> Encode for HashMap<K, V, S>
+where
+    K: Encode,
+    V: Encode
+{
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.len().encode(w)?;
+        for (key, value) in self {
+            key.encode(w)?;
+            value.encode(w)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K, V, S> Decode for HashMap<K, V, S>
+where
+    K: Decode + Hash + Eq,
+    V: Decode,
+    S: BuildHasher + Default
+{
+    const MIN_ENCODED_LEN: usize = 8;
+
+    fn decode(d: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        let len = d.read_len()?;
+        // size the table once from the stored count, rather than growing it
+        let mut map = HashMap::with_capacity_and_hasher(d.capacity_hint::<(K, V)>(len), S::default());
+        d.items(len, |d| {
+            let key = K::decode(d)?;
+            let value = V::decode(d)?;
+            match map.insert(key, value) {
+                Some(_) => Err(DecodeError::DuplicateKey),
+                None => Ok(()),
+            }
+        })?;
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Debug;
+
+    // a small xorshift generator, so the property tests are reproducible
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+
+        fn string(&mut self) -> String {
+            let len = self.below(12);
+            (0..len).map(|_| char::from_u32(self.below(0x800) as u32).unwrap_or('?')).collect()
+        }
+
+        fn map(&mut self) -> HashMap<String, Vec<(i64, Option<bool>)>> {
+            let len = self.below(40);
+            (0..len)
+                .map(|_| {
+                    let values = (0..self.below(5))
+                        .map(|_| {
+                            let flag = match self.below(3) {
+                                0 => None,
+                                n => Some(n == 1),
+                            };
+                            (self.next() as i64, flag)
+                        })
+                        .collect();
+                    (self.string(), values)
+                })
+                .collect()
+        }
+    }
+
+    fn round_trip<T: Encode + Decode + PartialEq + Debug>(value: T) {
+        let bytes = to_bytes(&value);
+        assert_eq!(from_bytes::<T>(&bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn primitives() {
+        round_trip(0u8);
+        round_trip(u64::MAX);
+        round_trip(i128::MIN);
+        round_trip(-1isize);
+        round_trip(usize::MAX);
+        round_trip(1.5f32);
+        round_trip(f64::NEG_INFINITY);
+        round_trip(true);
+        round_trip('é');
+        round_trip(String::from("hello, world"));
+        round_trip(vec![Some(1u16), None, Some(3)]);
+        round_trip((1u8, String::new(), vec![()]));
+    }
+
+    #[test]
+    fn format() {
+        let mut map: HashMap<u8, bool> = HashMap::new();
+        map.insert(7, true);
+        let bytes = to_bytes(&map);
+        assert_eq!(bytes, [b'H', b'M', b'A', b'P', 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 7, 1]);
+    }
+
+    #[test]
+    fn maps_round_trip() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..200 {
+            round_trip(rng.map());
+        }
+    }
+
+    #[test]
+    fn presized() {
+        let map: HashMap<u32, u32> = (0..1000).map(|i| (i, i)).collect();
+        let loaded: HashMap<u32, u32> = from_bytes(&to_bytes(&map)).unwrap();
+        assert_eq!(loaded.capacity(), HashMap::<u32, u32>::with_capacity(1000).capacity());
+
+        // keys much smaller encoded than in memory don't shrink the hint
+        let map: HashMap<String, u32> = (0..1000).map(|i| (i.to_string(), i)).collect();
+        let loaded: HashMap<String, u32> = from_bytes(&to_bytes(&map)).unwrap();
+        assert_eq!(loaded.capacity(), HashMap::<String, u32>::with_capacity(1000).capacity());
+        let loaded: Vec<String> = from_bytes(&to_bytes(&vec![String::new(); 1000])).unwrap();
+        assert_eq!(loaded.capacity(), 1000);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..20 {
+            let bytes = to_bytes(&rng.map());
+            for len in 0..bytes.len() {
+                let result = from_bytes::<HashMap<String, Vec<(i64, Option<bool>)>>>(&bytes[..len]);
+                assert!(result.is_err(), "decoded a document truncated to {} bytes", len);
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted() {
+        // flipping bits may or may not yield a valid document, but must never panic
+        let mut rng = Rng(0xdead_beef_cafe_f00d);
+        let mut errors = 0;
+        for _ in 0..2000 {
+            let mut bytes = to_bytes(&rng.map());
+            for _ in 0..1 + rng.below(3) {
+                let i = rng.below(bytes.len() as u64) as usize;
+                bytes[i] ^= 1 << rng.below(8);
+            }
+            errors += from_bytes::<HashMap<String, Vec<(i64, Option<bool>)>>>(&bytes).is_err() as u32;
+        }
+        assert!(errors > 0);
+
+        // random garbage after a valid header, including absurd lengths
+        for _ in 0..2000 {
+            let mut bytes = to_bytes(&());
+            bytes.extend((0..rng.below(64)).map(|_| rng.next() as u8));
+            let _ = from_bytes::<HashMap<u64, String>>(&bytes);
+            let _ = from_bytes::<Vec<Vec<u8>>>(&bytes);
+        }
+    }
+
+    #[test]
+    fn huge_lengths() {
+        // a corrupted length on items that take no input fails rather than
+        // looping, while short runs of them still decode
+        let mut bytes = to_bytes(&());
+        (u64::MAX >> 1).encode(&mut bytes).unwrap();
+        assert!(from_bytes::<Vec<()>>(&bytes).is_err());
+        assert!(from_bytes::<Vec<(u8, ())>>(&bytes).is_err());
+        assert_eq!(from_bytes::<Vec<()>>(&to_bytes(&vec![()])).unwrap(), vec![()]);
+
+        // what is allocated up front is bounded by the input, not the length
+        let input = vec![0u8; 4096];
+        let d = Decoder::new(&input);
+        assert_eq!(d.capacity_hint::<(String, Vec<u8>)>(1 << 40), input.len() / 16);
+        assert_eq!(d.capacity_hint::<()>(1 << 40), input.len());
+
+        let mut bytes = to_bytes(&());
+        (1u64 << 40).encode(&mut bytes).unwrap();
+        bytes.extend(&input);
+        assert!(from_bytes::<HashMap<String, Vec<u8>>>(&bytes).is_err());
+        assert!(from_bytes::<Vec<String>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        let bytes = to_bytes(&7u32);
+        assert!(matches!(from_bytes::<u32>(b"HMA"), Err(DecodeError::BadMagic)));
+        assert!(matches!(from_bytes::<u32>(b"JSON{}"), Err(DecodeError::BadMagic)));
+
+        let mut future = bytes.clone();
+        future[4] = 2;
+        assert!(matches!(from_bytes::<u32>(&future), Err(DecodeError::UnsupportedVersion(2))));
+
+        assert!(matches!(from_bytes::<u16>(&bytes), Err(DecodeError::TrailingBytes(2))));
+        assert!(matches!(from_bytes::<bool>(&to_bytes(&2u8)), Err(DecodeError::InvalidBool(2))));
+        assert!(matches!(from_bytes::<char>(&to_bytes(&0xd800u32)), Err(DecodeError::InvalidChar(0xd800))));
+        assert!(matches!(from_bytes::<String>(&to_bytes(&vec![0xffu8])), Err(DecodeError::InvalidUtf8)));
+
+        // the same key twice
+        let pairs = vec![(1u8, 1u8), (1, 2)];
+        assert!(matches!(from_bytes::<HashMap<u8, u8>>(&to_bytes(&pairs)), Err(DecodeError::DuplicateKey)));
+
+        // a length that claims far more items than the input holds
+        let huge = to_bytes(&u64::MAX);
+        assert!(matches!(from_bytes::<Vec<u64>>(&huge), Err(DecodeError::UnexpectedEof)));
+    }
+
+    #[test]
+    fn streams() {
+        let map: HashMap<String, u64> = (0..100).map(|i| (i.to_string(), i)).collect();
+        let mut file = Vec::new();
+        write_to(&map, &mut file).unwrap();
+        let loaded: HashMap<String, u64> = read_from(&mut file.as_slice()).unwrap();
+        assert_eq!(loaded, map);
+    }
+}
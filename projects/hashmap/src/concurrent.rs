@@ -0,0 +1,412 @@
+// concurrent.rs
+// Thread-safe map that spreads its keys across independently locked shards.
+//
+// Each key is hashed once; the hash picks the shard and is then reused for the
+// lookup within it through the raw entry API. Threads working on keys in
+// different shards never contend for the same lock.
+//
+// A panic while a guard is held, say in a closure passed to and_modify,
+// poisons that shard's lock. The map ignores poisoning: a shard is a whole
+// HashMap whatever the panicking code was doing with one of its values.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::ops::{Deref, DerefMut};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread;
+
+use crate::{make_hash, HashMap, RawEntryMut};
+
+pub struct ConcurrentHashMap<K, V, S = RandomState> {
+    shards: Box<[RwLock<HashMap<K, V, S>>]>,
+    hash_builder: S
+}
+
+impl<K, V> ConcurrentHashMap<K, V> {
+    /// A map with a shard count suited to the machine's parallelism.
+    pub fn new() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_shards(threads * 4)
+    }
+
+    pub fn with_shards(shards: usize) -> Self {
+        Self::with_shards_and_hasher(shards, RandomState::new())
+    }
+}
+
+impl<K, V> Default for ConcurrentHashMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, S> ConcurrentHashMap<K, V, S>
+where
+    S: BuildHasher + Clone
+{
+    /// A map with `shards` shards, rounded up to a power of two.
+    pub fn with_shards_and_hasher(shards: usize, hash_builder: S) -> Self {
+        let shards = shards.max(1).next_power_of_two();
+        Self {
+            shards: (0..shards)
+                .map(|_| RwLock::new(HashMap::with_hasher(hash_builder.clone())))
+                .collect(),
+            hash_builder
+        }
+    }
+}
+
+impl<K, V, S> ConcurrentHashMap<K, V, S> {
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    // The tables within a shard probe from the low bits of the hash and keep
+    // the top bits as tags, so pick the shard from bits in between; otherwise
+    // every key in a shard would share the same tag.
+    fn shard(&self, hash: u64) -> &RwLock<HashMap<K, V, S>> {
+        &self.shards[(hash >> 32) as usize & (self.shards.len() - 1)]
+    }
+
+    /// The exact number of entries, taken while holding every shard's lock.
+    pub fn len(&self) -> usize {
+        let guards: Vec<_> = self.shards.iter().map(|shard| read(shard)).collect();
+        guards.iter().map(|map| map.len()).sum()
+    }
+
+    /// The number of entries, counting one shard at a time.
+    ///
+    /// Cheaper than len() and never blocks more than one shard, but concurrent
+    /// updates to shards already counted may make the result stale.
+    pub fn len_approx(&self) -> usize {
+        self.shards.iter().map(|shard| read(shard).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| read(shard).is_empty())
+    }
+
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            write(shard).clear();
+        }
+    }
+
+    /// Consume the map, merging the shards back into a single HashMap.
+    pub fn into_inner(self) -> HashMap<K, V, S>
+    where
+        K: Hash + Eq,
+        S: BuildHasher
+    {
+        let mut map = HashMap::with_hasher(self.hash_builder);
+        for shard in self.shards.into_vec() {
+            let shard = shard.into_inner().unwrap_or_else(PoisonError::into_inner);
+            map.reserve(shard.len());
+            for (k, v) in shard {
+                map.insert(k, v);
+            }
+        }
+        map
+    }
+}
+
+impl<K, V, S> ConcurrentHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher
+{
+    /// Look up a key, holding its shard's read lock for as long as the guard lives.
+    pub fn get<Q>(&self, key: &Q) -> Option<ReadGuard<'_, K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = make_hash(&self.hash_builder, key);
+        let guard = read(self.shard(hash));
+        let value: *const V = guard.raw_entry().from_key_hashed_nocheck(hash, key)?.1;
+        Some(ReadGuard { _guard: guard, value })
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let hash = make_hash(&self.hash_builder, &key);
+        let mut guard = write(self.shard(hash));
+        match guard.raw_entry_mut().from_key_hashed_nocheck(hash, &key) {
+            RawEntryMut::Occupied(mut e) => Some(e.insert(value)),
+            RawEntryMut::Vacant(e) => {
+                e.insert_hashed_nocheck(hash, key, value);
+                None
+            },
+        }
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = make_hash(&self.hash_builder, key);
+        let mut guard = write(self.shard(hash));
+        match guard.raw_entry_mut().from_key_hashed_nocheck(hash, key) {
+            RawEntryMut::Occupied(e) => Some(e.remove()),
+            RawEntryMut::Vacant(_) => None,
+        }
+    }
+
+    /// The entry for a key, holding its shard's write lock until the entry
+    /// (or the guard it resolves to) is dropped.
+    pub fn entry(&self, key: K) -> Entry<'_, K, V, S> {
+        let hash = make_hash(&self.hash_builder, &key);
+        let guard = write(self.shard(hash));
+        Entry { guard, key, hash }
+    }
+}
+
+// Lock a shard, recovering it if a panic poisoned the lock.
+fn read<T>(shard: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    shard.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<T>(shard: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    shard.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A shared reference to a value, holding its shard's read lock.
+pub struct ReadGuard<'a, K, V, S> {
+    _guard: RwLockReadGuard<'a, HashMap<K, V, S>>,
+    value: *const V
+}
+
+impl<'a, K, V, S> Deref for ReadGuard<'a, K, V, S> {
+    type Target = V;
+    fn deref(&self) -> &V {
+        // SAFETY: value points into the shard, which cannot change while its read lock is held
+        unsafe { &*self.value }
+    }
+}
+
+/// A mutable reference to a value, holding its shard's write lock.
+pub struct WriteGuard<'a, K, V, S> {
+    _guard: RwLockWriteGuard<'a, HashMap<K, V, S>>,
+    value: *mut V
+}
+
+impl<'a, K, V, S> Deref for WriteGuard<'a, K, V, S> {
+    type Target = V;
+    fn deref(&self) -> &V {
+        // SAFETY: value points into the shard, which only this guard can access
+        unsafe { &*self.value }
+    }
+}
+
+impl<'a, K, V, S> DerefMut for WriteGuard<'a, K, V, S> {
+    fn deref_mut(&mut self) -> &mut V {
+        // SAFETY: as for deref(), and the guard is borrowed mutably
+        unsafe { &mut *self.value }
+    }
+}
+
+/// An entry in one shard of a ConcurrentHashMap, holding that shard's write lock.
+pub struct Entry<'a, K, V, S> {
+    guard: RwLockWriteGuard<'a, HashMap<K, V, S>>,
+    key: K,
+    hash: u64
+}
+
+impl<'a, K, V, S> Entry<'a, K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn or_insert(self, value: V) -> WriteGuard<'a, K, V, S> {
+        self.or_insert_with(|| value)
+    }
+
+    pub fn or_insert_with<F>(mut self, maker: F) -> WriteGuard<'a, K, V, S>
+    where
+        F: FnOnce() -> V
+    {
+        let hash = self.hash;
+        let value: *mut V = match self.guard.raw_entry_mut().from_key_hashed_nocheck(hash, &self.key) {
+            RawEntryMut::Occupied(e) => e.into_mut(),
+            RawEntryMut::Vacant(e) => e.insert_hashed_nocheck(hash, self.key, maker()).1,
+        };
+        WriteGuard { _guard: self.guard, value }
+    }
+
+    pub fn or_default(self) -> WriteGuard<'a, K, V, S>
+    where
+        V: Default
+    {
+        self.or_insert_with(Default::default)
+    }
+
+    /// Modify the value in place if the key is present.
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut V)
+    {
+        if let RawEntryMut::Occupied(mut e) = self.guard.raw_entry_mut().from_key_hashed_nocheck(self.hash, &self.key) {
+            f(e.get_mut());
+        }
+        self
+    }
+
+    /// Remove the entry's current value, if any.
+    pub fn remove(mut self) -> Option<V> {
+        match self.guard.raw_entry_mut().from_key_hashed_nocheck(self.hash, &self.key) {
+            RawEntryMut::Occupied(e) => Some(e.remove()),
+            RawEntryMut::Vacant(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    const THREADS: usize = 8;
+    const OPS: usize = 20_000;
+
+    #[test]
+    fn single_threaded() {
+        let map = ConcurrentHashMap::with_shards(3);
+        assert_eq!(map.shard_count(), 4);
+        assert!(map.is_empty());
+
+        assert_eq!(map.insert("a", 1), None);
+        assert_eq!(map.insert("a", 2), Some(1));
+        assert_eq!(*map.get("a").unwrap(), 2);
+        assert!(map.get("b").is_none());
+
+        *map.entry("b").or_insert(10) += 1;
+        *map.entry("b").and_modify(|v| *v *= 2).or_insert(0) += 1;
+        assert_eq!(*map.get("b").unwrap(), 23);
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(map.remove("a"), Some(2));
+        assert_eq!(map.entry("b").remove(), Some(23));
+        assert_eq!(map.len(), 0);
+    }
+
+    #[test]
+    fn into_inner() {
+        let map = ConcurrentHashMap::new();
+        for i in 0..1000 {
+            map.insert(i, i * 2);
+        }
+
+        let map = map.into_inner();
+        assert_eq!(map.len(), 1000);
+        for i in 0..1000 {
+            assert_eq!(map.get(&i), Some(&(i * 2)));
+        }
+    }
+
+    #[test]
+    fn poisoned_shard() {
+        let map = ConcurrentHashMap::with_shards(1);
+        map.insert("a", 1);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            map.entry("a").and_modify(|_| panic!("closure panicked"));
+        }));
+        assert!(result.is_err());
+
+        // the shard's lock is poisoned, but the map carries on
+        assert_eq!(map.len(), 1);
+        assert!(!map.is_empty());
+        assert_eq!(map.insert("b", 2), None);
+        assert_eq!(*map.get("a").unwrap(), 1);
+        assert_eq!(map.into_inner().len(), 2);
+    }
+
+    // threads work on disjoint key ranges with a mix of operations; the final
+    // contents must match what each thread did on its own
+    #[test]
+    fn stress_disjoint_keys() {
+        let map = Arc::new(ConcurrentHashMap::with_shards(16));
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let map = Arc::clone(&map);
+                thread::spawn(move || {
+                    let base = t * OPS;
+                    for i in base..base + OPS {
+                        map.insert(i, i);
+                        if i % 4 == 0 {
+                            assert_eq!(map.remove(&i), Some(i));
+                        }
+                        if i % 4 == 1 {
+                            *map.entry(i).or_insert(0) += 1;
+                        }
+                        if i % 4 == 2 {
+                            assert_eq!(map.get(&i).map(|v| *v), Some(i));
+                        }
+                        // lengths are only a consistency check under contention
+                        if i % 1000 == 0 {
+                            assert!(map.len_approx() <= THREADS * OPS);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(map.len(), THREADS * OPS * 3 / 4);
+        for i in 0..THREADS * OPS {
+            let expected = match i % 4 {
+                0 => None,
+                1 => Some(i + 1),
+                _ => Some(i),
+            };
+            assert_eq!(map.get(&i).map(|v| *v), expected);
+        }
+    }
+
+    // threads contend on a small set of shared counters
+    #[test]
+    fn stress_shared_counters() {
+        let map = Arc::new(ConcurrentHashMap::with_shards(4));
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let map = Arc::clone(&map);
+                thread::spawn(move || {
+                    for i in 0..OPS {
+                        *map.entry(i % 64).or_default() += 1;
+                        // churn a per-thread key alongside the shared ones
+                        map.insert(1000 + t, i);
+                        if i % 2 == 0 {
+                            map.remove(&(1000 + t));
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let total: usize = (0..64).map(|k| *map.get(&k).unwrap()).sum();
+        assert_eq!(total, THREADS * OPS);
+        for t in 0..THREADS {
+            assert_eq!(map.get(&(1000 + t)).map(|v| *v), Some(OPS - 1));
+        }
+    }
+}
@@ -0,0 +1,98 @@
+// fx.rs
+// Multiply-rotate hasher in the style of rustc's FxHasher.
+//
+// Very cheap to compute, especially for integer keys, but trivially
+// attackable: do not use it for keys an adversary controls.
+
+use std::convert::TryInto;
+use std::hash::{BuildHasherDefault, Hasher};
+
+const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+pub type FxBuildHasher = BuildHasherDefault<FxHasher>;
+
+#[derive(Clone, Copy, Default)]
+pub struct FxHasher {
+    hash: u64
+}
+
+impl FxHasher {
+    fn add_to_hash(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(SEED);
+    }
+}
+
+impl Hasher for FxHasher {
+    fn write(&mut self, mut bytes: &[u8]) {
+        while bytes.len() >= 8 {
+            let (word, rest) = bytes.split_at(8);
+            self.add_to_hash(u64::from_le_bytes(word.try_into().unwrap()));
+            bytes = rest;
+        }
+        if bytes.len() >= 4 {
+            let (word, rest) = bytes.split_at(4);
+            self.add_to_hash(u32::from_le_bytes(word.try_into().unwrap()) as u64);
+            bytes = rest;
+        }
+        for &byte in bytes {
+            self.add_to_hash(byte as u64);
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.add_to_hash(i as u64);
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.add_to_hash(i as u64);
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.add_to_hash(i as u64);
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.add_to_hash(i);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.add_to_hash(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::BuildHasher;
+
+    #[test]
+    fn deterministic() {
+        let a = FxBuildHasher::default();
+        let b = FxBuildHasher::default();
+        assert_eq!(a.hash_one("hello"), b.hash_one("hello"));
+        assert_eq!(a.hash_one(42u64), b.hash_one(42u64));
+        assert_ne!(a.hash_one("hello"), a.hash_one("world"));
+    }
+
+    #[test]
+    fn all_bytes_contribute() {
+        let hasher = FxBuildHasher::default();
+        let base = [0u8; 15];
+        let hashes: Vec<u64> = (0..base.len())
+            .map(|i| {
+                let mut bytes = base;
+                bytes[i] = 1;
+                hasher.hash_one(bytes)
+            })
+            .collect();
+
+        for (i, a) in hashes.iter().enumerate() {
+            assert!(hashes[i + 1..].iter().all(|b| a != b));
+            assert_ne!(*a, hasher.hash_one(base));
+        }
+    }
+}
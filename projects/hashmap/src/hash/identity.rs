@@ -0,0 +1,105 @@
+// identity.rs
+// Hasher that uses an integer key as its own hash.
+//
+// Only suitable for integer keys that are already well distributed, such as
+// random ids. The table takes its control byte from the top bits of the hash,
+// so small integers all share one and every probe falls back to comparing keys.
+
+use std::hash::{BuildHasherDefault, Hasher};
+
+pub type IdentityBuildHasher = BuildHasherDefault<IdentityHasher>;
+
+#[derive(Clone, Copy, Default)]
+pub struct IdentityHasher {
+    hash: u64
+}
+
+impl Hasher for IdentityHasher {
+    fn write(&mut self, _bytes: &[u8]) {
+        panic!("IdentityHasher only supports integer keys");
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.hash = i as u64;
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.hash = i as u64;
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.hash = i as u64;
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.hash = i;
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.hash = (i as u64) ^ ((i >> 64) as u64);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.hash = i as u64;
+    }
+
+    fn write_i8(&mut self, i: i8) {
+        self.write_u8(i as u8);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_usize(i as usize);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashMap;
+    use std::hash::BuildHasher;
+
+    #[test]
+    fn passes_integers_through() {
+        let hasher = IdentityBuildHasher::default();
+        assert_eq!(hasher.hash_one(42u64), 42);
+        assert_eq!(hasher.hash_one(7u8), 7);
+        assert_eq!(hasher.hash_one(-1i32), u32::MAX as u64);
+    }
+
+    #[test]
+    fn integer_map() {
+        let mut map : HashMap<u32, u32, _> = HashMap::with_hasher(IdentityBuildHasher::default());
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+        for i in 0..1000 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "only supports integer keys")]
+    fn rejects_strings() {
+        IdentityBuildHasher::default().hash_one("foo");
+    }
+}
@@ -0,0 +1,20 @@
+// mod.rs
+// Hashers shipped with the crate, for use with HashMap::with_hasher.
+//
+// - fx: a fast, non-cryptographic multiply-rotate hasher; not DoS-resistant
+// - sip: keyed SipHash-1-3; with random keys it is DoS-resistant, like std's RandomState
+// - identity: passes integer keys through unchanged; for keys that are already well distributed
+
+pub mod fx;
+pub mod identity;
+pub mod sip;
+
+pub use fx::{FxBuildHasher, FxHasher};
+pub use identity::{IdentityBuildHasher, IdentityHasher};
+pub use sip::{RandomSipState, SipHasher13};
+
+use crate::HashMap;
+
+pub type FxHashMap<K, V> = HashMap<K, V, FxBuildHasher>;
+pub type SipHashMap<K, V> = HashMap<K, V, RandomSipState>;
+pub type IdentityHashMap<K, V> = HashMap<K, V, IdentityBuildHasher>;
@@ -0,0 +1,223 @@
+// sip.rs
+// Keyed SipHash, the same family of hash that std's RandomState uses.
+//
+// With secret random keys (RandomSipState::new) an attacker who can choose keys
+// still cannot predict which ones collide, so this is the safe choice for
+// untrusted input. SipHash-1-3 trades some of SipHash-2-4's security margin
+// for speed, as std does.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+pub type SipHasher13 = SipHasher<1, 3>;
+pub type SipHasher24 = SipHasher<2, 4>;
+
+#[derive(Clone, Copy)]
+struct State {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64
+}
+
+impl State {
+    fn round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13);
+        self.v1 ^= self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16);
+        self.v3 ^= self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21);
+        self.v3 ^= self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17);
+        self.v1 ^= self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+}
+
+// load up to 8 bytes as a little-endian integer
+fn load_le(bytes: &[u8]) -> u64 {
+    debug_assert!(bytes.len() <= 8);
+    bytes
+        .iter()
+        .enumerate()
+        .fold(0, |word, (i, &byte)| word | (byte as u64) << (8 * i))
+}
+
+/// SipHash with C compression rounds and D finalization rounds.
+#[derive(Clone, Copy)]
+pub struct SipHasher<const C: usize, const D: usize> {
+    state: State,
+    // total bytes written, of which the last `ntail` are buffered in `tail`
+    length: usize,
+    tail: u64,
+    ntail: usize
+}
+
+impl<const C: usize, const D: usize> SipHasher<C, D> {
+    pub fn new_with_keys(k0: u64, k1: u64) -> Self {
+        Self {
+            state: State {
+                v0: k0 ^ 0x736f_6d65_7073_6575,
+                v1: k1 ^ 0x646f_7261_6e64_6f6d,
+                v2: k0 ^ 0x6c79_6765_6e65_7261,
+                v3: k1 ^ 0x7465_6462_7974_6573
+            },
+            length: 0,
+            tail: 0,
+            ntail: 0
+        }
+    }
+
+    fn compress(&mut self, m: u64) {
+        self.state.v3 ^= m;
+        for _ in 0..C {
+            self.state.round();
+        }
+        self.state.v0 ^= m;
+    }
+}
+
+impl<const C: usize, const D: usize> Hasher for SipHasher<C, D> {
+    fn write(&mut self, mut msg: &[u8]) {
+        self.length += msg.len();
+
+        // top up a partially filled word from a previous write
+        if self.ntail != 0 {
+            let needed = (8 - self.ntail).min(msg.len());
+            let (head, rest) = msg.split_at(needed);
+            self.tail |= load_le(head) << (8 * self.ntail);
+            self.ntail += needed;
+            msg = rest;
+
+            if self.ntail < 8 {
+                return;
+            }
+            self.compress(self.tail);
+            self.tail = 0;
+            self.ntail = 0;
+        }
+
+        let mut words = msg.chunks_exact(8);
+        for word in &mut words {
+            self.compress(load_le(word));
+        }
+
+        let rest = words.remainder();
+        self.tail = load_le(rest);
+        self.ntail = rest.len();
+    }
+
+    fn finish(&self) -> u64 {
+        let b = ((self.length as u64 & 0xff) << 56) | self.tail;
+
+        let mut state = self.state;
+        state.v3 ^= b;
+        for _ in 0..C {
+            state.round();
+        }
+        state.v0 ^= b;
+
+        state.v2 ^= 0xff;
+        for _ in 0..D {
+            state.round();
+        }
+
+        state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+    }
+}
+
+/// Builds SipHash-1-3 hashers sharing a pair of keys.
+#[derive(Clone, Copy)]
+pub struct RandomSipState {
+    k0: u64,
+    k1: u64
+}
+
+impl RandomSipState {
+    /// Create a state with fresh random keys.
+    pub fn new() -> Self {
+        // std already seeds RandomState from the OS; borrow its randomness
+        // rather than pulling in a dependency for it
+        let k0 = RandomState::new().build_hasher().finish();
+        let k1 = RandomState::new().build_hasher().finish();
+        Self { k0, k1 }
+    }
+
+    /// Create a state with fixed keys, for reproducible hashes.
+    pub fn with_keys(k0: u64, k1: u64) -> Self {
+        Self { k0, k1 }
+    }
+}
+
+impl Default for RandomSipState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildHasher for RandomSipState {
+    type Hasher = SipHasher13;
+    fn build_hasher(&self) -> SipHasher13 {
+        SipHasher13::new_with_keys(self.k0, self.k1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // std's own SipHash implementations make good reference vectors
+    #[test]
+    #[allow(deprecated)]
+    fn matches_std_siphash24() {
+        let msg: Vec<u8> = (0..64).collect();
+        for len in 0..msg.len() {
+            let mut ours = SipHasher24::new_with_keys(0x0706_0504_0302_0100, 0x0f0e_0d0c_0b0a_0908);
+            let mut theirs = std::hash::SipHasher::new_with_keys(0x0706_0504_0302_0100, 0x0f0e_0d0c_0b0a_0908);
+            ours.write(&msg[..len]);
+            theirs.write(&msg[..len]);
+            assert_eq!(ours.finish(), theirs.finish(), "length {}", len);
+        }
+    }
+
+    #[test]
+    fn reference_vector() {
+        // first entries of the SipHash-2-4 reference vectors, key 00..0f, message 00..len-1
+        let hash = |len: u8| {
+            let mut hasher = SipHasher24::new_with_keys(0x0706_0504_0302_0100, 0x0f0e_0d0c_0b0a_0908);
+            hasher.write(&(0..len).collect::<Vec<_>>());
+            hasher.finish()
+        };
+        assert_eq!(hash(0), 0x726f_db47_dd0e_0e31);
+        assert_eq!(hash(1), 0x74f8_39c5_93dc_67fd);
+    }
+
+    #[test]
+    fn split_writes() {
+        let msg: Vec<u8> = (0..40).collect();
+        let mut whole = SipHasher13::new_with_keys(1, 2);
+        whole.write(&msg);
+
+        for split in 0..msg.len() {
+            let mut parts = SipHasher13::new_with_keys(1, 2);
+            let (a, b) = msg.split_at(split);
+            parts.write(a);
+            parts.write(b);
+            assert_eq!(parts.finish(), whole.finish());
+        }
+    }
+
+    #[test]
+    fn keys_matter() {
+        let a = RandomSipState::with_keys(1, 2);
+        let b = RandomSipState::with_keys(1, 3);
+        assert_eq!(a.hash_one("hello"), a.hash_one("hello"));
+        assert_ne!(a.hash_one("hello"), b.hash_one("hello"));
+        assert_ne!(RandomSipState::new().hash_one(0u64), RandomSipState::new().hash_one(0u64));
+    }
+}
@@ -0,0 +1,781 @@
+// index_map.rs
+// Hash map that remembers insertion order.
+//
+// Entries live densely in a Vec, in order, each with its cached hash. A
+// RawTable of positions into that Vec serves as the hash index. Iterating
+// just walks the Vec, so the order is deterministic and independent of the
+// hasher. Any operation that moves entries around in the Vec has to patch the
+// positions stored in the index.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::ops::Index;
+use std::slice;
+use std::vec;
+
+use crate::make_hash;
+use crate::raw::{RawTable, DEFAULT_MAX_LOAD_FACTOR};
+
+#[derive(Clone)]
+struct Bucket<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+pub struct IndexMap<K, V, S = RandomState> {
+    entries: Vec<Bucket<K, V>>,
+    // positions into `entries`, hashed by the entry's hash
+    indices: RawTable<usize>,
+    hash_builder: S
+}
+
+// rehashes a position by the hash cached in the entry it refers to
+fn position_hasher<K, V>(entries: &[Bucket<K, V>]) -> impl Fn(&usize) -> u64 + '_ {
+    move |&i| entries[i].hash
+}
+
+impl<K, V> IndexMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V, S> IndexMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            entries: Vec::new(),
+            indices: RawTable::new(),
+            hash_builder
+        }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            entries: Vec::with_capacity(capacity),
+            indices: RawTable::with_capacity(capacity, DEFAULT_MAX_LOAD_FACTOR),
+            hash_builder
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.indices.capacity().min(self.entries.capacity())
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn clear(&mut self) {
+        self.indices.clear();
+        self.entries.clear();
+    }
+
+    /// The key and value at a position in insertion order.
+    pub fn get_index(&self, index: usize) -> Option<(&K, &V)> {
+        self.entries.get(index).map(|b| (&b.key, &b.value))
+    }
+
+    pub fn get_index_mut(&mut self, index: usize) -> Option<(&K, &mut V)> {
+        self.entries.get_mut(index).map(|b| (&b.key, &mut b.value))
+    }
+
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.get_index(0)
+    }
+
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.entries.last().map(|b| (&b.key, &b.value))
+    }
+
+    /// Remove and return the most recently inserted entry.
+    pub fn pop(&mut self) -> Option<(K, V)> {
+        let bucket = self.entries.last()?;
+        let last = self.entries.len() - 1;
+        let slot = self.find_position(bucket.hash, last);
+        // SAFETY: find_position returns a FULL slot
+        unsafe { self.indices.remove(slot) };
+        self.entries.pop().map(|b| (b.key, b.value))
+    }
+
+    /// Move the entry at position `from` to position `to`, shifting the
+    /// entries in between by one.
+    ///
+    /// Panics if either position is out of bounds.
+    pub fn move_index(&mut self, from: usize, to: usize) {
+        let len = self.entries.len();
+        assert!(from < len && to < len, "move_index out of bounds: the len is {} but the positions are {} and {}", len, from, to);
+        if from == to {
+            return;
+        }
+
+        let (lo, hi) = if from < to { (from, to) } else { (to, from) };
+        // find every affected slot before rewriting any of them
+        let slots: Vec<usize> = (lo..=hi)
+            .map(|i| self.find_position(self.entries[i].hash, i))
+            .collect();
+
+        if from < to {
+            self.entries[from..=to].rotate_left(1);
+        } else {
+            self.entries[to..=from].rotate_right(1);
+        }
+
+        for (i, slot) in (lo..=hi).zip(slots) {
+            let new = if i == from {
+                to
+            } else if from < to {
+                i - 1
+            } else {
+                i + 1
+            };
+            // SAFETY: the slot is FULL
+            unsafe { *self.indices.get_mut(slot) = new };
+        }
+    }
+
+    /// Sort the entries by key, in place.
+    pub fn sort_keys(&mut self)
+    where
+        K: Ord
+    {
+        self.sort_by(|k1, _, k2, _| k1.cmp(k2));
+    }
+
+    /// Sort the entries with a comparison function, in place.
+    ///
+    /// The sort is stable.
+    pub fn sort_by<F>(&mut self, mut cmp: F)
+    where
+        F: FnMut(&K, &V, &K, &V) -> std::cmp::Ordering
+    {
+        let guard = RebuildIndices(self);
+        guard.0.entries.sort_by(|a, b| cmp(&a.key, &a.value, &b.key, &b.value));
+    }
+
+    /// Reverse the order of the entries, in place.
+    pub fn reverse(&mut self) {
+        self.entries.reverse();
+        self.rebuild_indices();
+    }
+
+    /// Keep only the entries for which `f` returns true, preserving order.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool
+    {
+        let len = self.entries.len();
+        let guard = RebuildIndices(self);
+        guard.0.entries.retain_mut(|b| f(&b.key, &mut b.value));
+        if guard.0.entries.len() == len {
+            // nothing moved, so the index is still right
+            std::mem::forget(guard);
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { inner: self.entries.iter() }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut { inner: self.entries.iter_mut() }
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.entries.iter() }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.entries.iter() }
+    }
+
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut { inner: self.entries.iter_mut() }
+    }
+
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        self.indices.clear();
+        Drain { inner: self.entries.drain(..) }
+    }
+
+    // the slot in the index holding a given position
+    fn find_position(&self, hash: u64, position: usize) -> usize {
+        self.indices
+            .find(hash, |&i| i == position)
+            .expect("every entry has a slot in the index")
+    }
+
+    // Re-point the index after entries have been reordered; the hashes are
+    // cached, so this never calls the hasher.
+    fn rebuild_indices(&mut self) {
+        self.indices.clear();
+        if self.indices.capacity() < self.entries.len() {
+            self.indices = RawTable::with_capacity(self.entries.len(), DEFAULT_MAX_LOAD_FACTOR);
+        }
+        for (i, bucket) in self.entries.iter().enumerate() {
+            self.indices.insert_no_grow(bucket.hash, i);
+        }
+    }
+
+    // Remove the entry at a position by swapping the last entry into its place.
+    fn swap_remove_position(&mut self, slot: usize, position: usize) -> (K, V) {
+        // SAFETY: the caller passes the FULL slot holding `position`
+        unsafe { self.indices.remove(slot) };
+        let bucket = self.entries.swap_remove(position);
+
+        if let Some(moved) = self.entries.get(position) {
+            // the former last entry now lives at `position`
+            let last = self.entries.len();
+            let slot = self.find_position(moved.hash, last);
+            // SAFETY: the slot is FULL
+            unsafe { *self.indices.get_mut(slot) = position };
+        }
+        (bucket.key, bucket.value)
+    }
+
+    // Remove the entry at a position, shifting all later entries down by one.
+    fn shift_remove_position(&mut self, slot: usize, position: usize) -> (K, V) {
+        // SAFETY: the caller passes the FULL slot holding `position`
+        unsafe { self.indices.remove(slot) };
+        let bucket = self.entries.remove(position);
+
+        // patch each moved entry when few moved, otherwise sweep every slot
+        if self.entries.len() - position < self.entries.len() / 2 {
+            for i in position..self.entries.len() {
+                let slot = self.find_position(self.entries[i].hash, i + 1);
+                // SAFETY: the slot is FULL
+                unsafe { *self.indices.get_mut(slot) = i };
+            }
+        } else {
+            for slot in self.indices.iter() {
+                // SAFETY: slot comes from iterating FULL slots
+                let i = unsafe { self.indices.get_mut(slot) };
+                if *i > position {
+                    *i -= 1;
+                }
+            }
+        }
+        (bucket.key, bucket.value)
+    }
+
+    /// Remove the entry at a position by swapping the last entry into its
+    /// place. O(1), but perturbs the order.
+    pub fn swap_remove_index(&mut self, index: usize) -> Option<(K, V)> {
+        let hash = self.entries.get(index)?.hash;
+        let slot = self.find_position(hash, index);
+        Some(self.swap_remove_position(slot, index))
+    }
+
+    /// Remove the entry at a position, shifting later entries down. Preserves
+    /// the order, but is O(n).
+    pub fn shift_remove_index(&mut self, index: usize) -> Option<(K, V)> {
+        let hash = self.entries.get(index)?.hash;
+        let slot = self.find_position(hash, index);
+        Some(self.shift_remove_position(slot, index))
+    }
+}
+
+// Rebuilds the index when dropped, so it matches the entries again even if
+// a user closure panics while they are being reordered or removed.
+struct RebuildIndices<'a, K, V, S>(&'a mut IndexMap<K, V, S>);
+
+impl<'a, K, V, S> Drop for RebuildIndices<'a, K, V, S> {
+    fn drop(&mut self) {
+        self.0.rebuild_indices();
+    }
+}
+
+impl<K, V, S> IndexMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher
+{
+    // the slot in the index and the position of a key, if present
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<(usize, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let entries = &self.entries;
+        let slot = self.indices.find(hash, |&i| entries[i].key.borrow() == key)?;
+        // SAFETY: find returns a FULL slot
+        Some((slot, unsafe { *self.indices.get(slot) }))
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.indices.reserve(additional, position_hasher(&self.entries));
+        self.entries.reserve(additional);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.indices.shrink_to(0, position_hasher(&self.entries));
+        self.entries.shrink_to_fit();
+    }
+
+    /// Insert a key-value pair, returning the old value if the key was
+    /// present. An existing key keeps its position.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.insert_full(key, value).1
+    }
+
+    /// Like insert, but also returns the position of the key.
+    pub fn insert_full(&mut self, key: K, value: V) -> (usize, Option<V>) {
+        let hash = make_hash(&self.hash_builder, &key);
+        if let Some((_, i)) = self.find(hash, &key) {
+            let old = std::mem::replace(&mut self.entries[i].value, value);
+            return (i, Some(old));
+        }
+
+        let i = self.entries.len();
+        self.indices.insert(hash, i, position_hasher(&self.entries));
+        self.entries.push(Bucket { hash, key, value });
+        (i, None)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_full(key).map(|(_, _, v)| v)
+    }
+
+    /// The position, key and value of an entry.
+    pub fn get_full<Q>(&self, key: &Q) -> Option<(usize, &K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = make_hash(&self.hash_builder, key);
+        let (_, i) = self.find(hash, key)?;
+        let bucket = &self.entries[i];
+        Some((i, &bucket.key, &bucket.value))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = make_hash(&self.hash_builder, key);
+        let (_, i) = self.find(hash, key)?;
+        Some(&mut self.entries[i].value)
+    }
+
+    /// The position of a key in insertion order.
+    pub fn get_index_of<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = make_hash(&self.hash_builder, key);
+        self.find(hash, key).map(|(_, i)| i)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_index_of(key).is_some()
+    }
+
+    /// Remove a key by swapping the last entry into its place. O(1), but
+    /// perturbs the order.
+    pub fn swap_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = make_hash(&self.hash_builder, key);
+        let (slot, i) = self.find(hash, key)?;
+        Some(self.swap_remove_position(slot, i).1)
+    }
+
+    /// Remove a key, shifting later entries down. Preserves the order, but
+    /// is O(n).
+    pub fn shift_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = make_hash(&self.hash_builder, key);
+        let (slot, i) = self.find(hash, key)?;
+        Some(self.shift_remove_position(slot, i).1)
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    inner: slice::Iter<'a, Bucket<K, V>>
+}
+
+pub struct IterMut<'a, K, V> {
+    inner: slice::IterMut<'a, Bucket<K, V>>
+}
+
+pub struct IntoIter<K, V> {
+    inner: vec::IntoIter<Bucket<K, V>>
+}
+
+pub struct Drain<'a, K, V> {
+    inner: vec::Drain<'a, Bucket<K, V>>
+}
+
+pub struct Keys<'a, K, V> {
+    inner: slice::Iter<'a, Bucket<K, V>>
+}
+
+pub struct Values<'a, K, V> {
+    inner: slice::Iter<'a, Bucket<K, V>>
+}
+
+pub struct ValuesMut<'a, K, V> {
+    inner: slice::IterMut<'a, Bucket<K, V>>
+}
+
+// every iterator is a projection of the entries vector's own iterator
+macro_rules! iterator {
+    ($name:ident<$($lt:lifetime,)? $($param:ident),*>, $item:ty, |$b:ident| $project:expr) => {
+        impl<$($lt,)? $($param),*> Iterator for $name<$($lt,)? $($param),*> {
+            type Item = $item;
+            fn next(&mut self) -> Option<Self::Item> {
+                self.inner.next().map(|$b| $project)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.inner.size_hint()
+            }
+        }
+
+        impl<$($lt,)? $($param),*> DoubleEndedIterator for $name<$($lt,)? $($param),*> {
+            fn next_back(&mut self) -> Option<Self::Item> {
+                self.inner.next_back().map(|$b| $project)
+            }
+        }
+
+        impl<$($lt,)? $($param),*> ExactSizeIterator for $name<$($lt,)? $($param),*> {}
+    };
+}
+
+iterator!(Iter<'a, K, V>, (&'a K, &'a V), |b| (&b.key, &b.value));
+iterator!(IterMut<'a, K, V>, (&'a K, &'a mut V), |b| (&b.key, &mut b.value));
+iterator!(IntoIter<K, V>, (K, V), |b| (b.key, b.value));
+iterator!(Drain<'a, K, V>, (K, V), |b| (b.key, b.value));
+iterator!(Keys<'a, K, V>, &'a K, |b| &b.key);
+iterator!(Values<'a, K, V>, &'a V, |b| &b.value);
+iterator!(ValuesMut<'a, K, V>, &'a mut V, |b| &mut b.value);
+
+impl<'a, K, V, S> IntoIterator for &'a IndexMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut IndexMap<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<K, V, S> IntoIterator for IndexMap<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { inner: self.entries.into_iter() }
+    }
+}
+
+impl<K, V, S> Default for IndexMap<K, V, S>
+where
+    S: Default
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> Clone for IndexMap<K, V, S>
+where
+    K: Clone,
+    V: Clone,
+    S: Clone
+{
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+            indices: self.indices.clone(),
+            hash_builder: self.hash_builder.clone()
+        }
+    }
+}
+
+impl<K, V, S> fmt::Debug for IndexMap<K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+// Two maps are equal if they hold the same entries, whatever their order.
+impl<K, V, S> PartialEq for IndexMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: PartialEq,
+    S: BuildHasher
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<K, V, S> Eq for IndexMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Eq,
+    S: BuildHasher
+{
+}
+
+impl<K, Q, V, S> Index<&Q> for IndexMap<K, V, S>
+where
+    K: Hash + Eq + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    S: BuildHasher
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("key not found in IndexMap")
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for IndexMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V, const N: usize> From<[(K, V); N]> for IndexMap<K, V>
+where
+    K: Hash + Eq
+{
+    fn from(entries: [(K, V); N]) -> Self {
+        IntoIterator::into_iter(entries).collect()
+    }
+}
+
+impl<K, V, S> Extend<(K, V)> for IndexMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        // as with HashMap, assume half the keys are duplicates of existing ones
+        let additional = if self.is_empty() {
+            iter.size_hint().0
+        } else {
+            iter.size_hint().0.div_ceil(2)
+        };
+        self.reserve(additional);
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(map: &IndexMap<i32, i32>) -> Vec<i32> {
+        map.keys().copied().collect()
+    }
+
+    // every key is found at the position iteration reports for it
+    fn check(map: &IndexMap<i32, i32>) {
+        for (i, (k, _)) in map.iter().enumerate() {
+            assert_eq!(map.get_index_of(k), Some(i));
+        }
+    }
+
+    #[test]
+    fn insertion_order() {
+        let mut map = IndexMap::new();
+        for i in (0..1000).rev() {
+            assert_eq!(map.insert(i, i * 10), None);
+        }
+        let expected: Vec<i32> = (0..1000).rev().collect();
+        assert_eq!(keys(&map), expected);
+
+        // overwriting keeps the original position
+        assert_eq!(map.insert(500, 0), Some(5000));
+        assert_eq!(map.get_full(&500), Some((499, &500, &0)));
+        assert_eq!(map.get_index(0), Some((&999, &9990)));
+        assert_eq!(map.last(), Some((&0, &0)));
+        check(&map);
+    }
+
+    #[test]
+    fn swap_remove() {
+        let mut map: IndexMap<i32, i32> = (0..5).map(|i| (i, i)).collect();
+        assert_eq!(map.swap_remove(&1), Some(1));
+        assert_eq!(keys(&map), vec![0, 4, 2, 3]);
+        assert_eq!(map.swap_remove(&3), Some(3));
+        assert_eq!(keys(&map), vec![0, 4, 2]);
+        assert_eq!(map.swap_remove(&3), None);
+        assert_eq!(map.swap_remove_index(0), Some((0, 0)));
+        assert_eq!(keys(&map), vec![2, 4]);
+        check(&map);
+    }
+
+    #[test]
+    fn shift_remove() {
+        let mut map: IndexMap<i32, i32> = (0..5).map(|i| (i, i)).collect();
+        assert_eq!(map.shift_remove(&1), Some(1));
+        assert_eq!(keys(&map), vec![0, 2, 3, 4]);
+        assert_eq!(map.shift_remove_index(3), Some((4, 4)));
+        assert_eq!(keys(&map), vec![0, 2, 3]);
+        check(&map);
+
+        // exercise both ways of patching the index
+        let mut map: IndexMap<i32, i32> = (0..1000).map(|i| (i, i)).collect();
+        map.shift_remove(&0);
+        map.shift_remove(&990);
+        check(&map);
+        assert_eq!(map.len(), 998);
+        assert_eq!(map.pop(), Some((999, 999)));
+        check(&map);
+    }
+
+    #[test]
+    fn move_index() {
+        let mut map: IndexMap<i32, i32> = (0..5).map(|i| (i, i)).collect();
+        map.move_index(0, 3);
+        assert_eq!(keys(&map), vec![1, 2, 3, 0, 4]);
+        check(&map);
+        map.move_index(4, 1);
+        assert_eq!(keys(&map), vec![1, 4, 2, 3, 0]);
+        check(&map);
+        map.move_index(2, 2);
+        assert_eq!(keys(&map), vec![1, 4, 2, 3, 0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn move_index_out_of_bounds() {
+        let mut map = IndexMap::from([(1, 1)]);
+        map.move_index(0, 1);
+    }
+
+    #[test]
+    fn sorting() {
+        let mut map: IndexMap<i32, i32> = [5, 3, 9, 1].iter().map(|&i| (i, -i)).collect();
+        map.sort_keys();
+        assert_eq!(keys(&map), vec![1, 3, 5, 9]);
+        check(&map);
+
+        map.sort_by(|_, v1, _, v2| v1.cmp(v2));
+        assert_eq!(keys(&map), vec![9, 5, 3, 1]);
+        check(&map);
+
+        map.reverse();
+        assert_eq!(keys(&map), vec![1, 3, 5, 9]);
+        check(&map);
+    }
+
+    #[test]
+    fn retain_and_drain() {
+        let mut map: IndexMap<i32, i32> = (0..10).map(|i| (i, i)).collect();
+        map.retain(|k, v| {
+            *v *= 2;
+            k % 3 == 0
+        });
+        assert_eq!(keys(&map), vec![0, 3, 6, 9]);
+        assert_eq!(map[&6], 12);
+        check(&map);
+
+        let drained: Vec<_> = map.drain().collect();
+        assert_eq!(drained, vec![(0, 0), (3, 6), (6, 12), (9, 18)]);
+        assert!(map.is_empty());
+        map.insert(1, 1);
+        assert_eq!(map.get(&1), Some(&1));
+    }
+
+    #[test]
+    fn panicking_closures() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        // the index is rebuilt over whatever state the entries were left in
+        let mut map: IndexMap<i32, i32> = (0..100).rev().map(|i| (i, i)).collect();
+        let mut calls = 0;
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            map.sort_by(|k1, _, k2, _| {
+                calls += 1;
+                assert!(calls < 50, "comparison panicked");
+                k1.cmp(k2)
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(map.len(), 100);
+        check(&map);
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            map.retain(|&k, _| {
+                assert!(k != 50, "predicate panicked");
+                k % 2 == 0
+            })
+        }));
+        assert!(result.is_err());
+        check(&map);
+        for k in 0..100 {
+            if map.contains_key(&k) {
+                assert_eq!(map.swap_remove(&k), Some(k));
+            }
+        }
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn traits() {
+        let a = IndexMap::from([("b", 2), ("a", 1)]);
+        let b = IndexMap::from([("a", 1), ("b", 2)]);
+        assert_eq!(format!("{:?}", a), r#"{"b": 2, "a": 1}"#);
+        assert_eq!(a, b);
+        assert_eq!(a.clone(), a);
+
+        let pairs: Vec<_> = a.into_iter().rev().collect();
+        assert_eq!(pairs, vec![("a", 1), ("b", 2)]);
+    }
+}
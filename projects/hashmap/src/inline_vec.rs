@@ -0,0 +1,186 @@
+// inline_vec.rs
+// Vector that holds up to N items inline, moving them to the heap only once
+// it outgrows that.
+
+use std::mem::{self, MaybeUninit};
+use std::ptr;
+use std::slice;
+
+pub(crate) enum InlineVec<T, const N: usize> {
+    // the first `len` items are initialized
+    Inline { len: usize, items: [MaybeUninit<T>; N] },
+    Heap(Vec<T>),
+}
+
+impl<T, const N: usize> InlineVec<T, N> {
+    pub(crate) fn new() -> Self {
+        InlineVec::Inline {
+            len: 0,
+            // SAFETY: an array of MaybeUninit needs no initialization
+            items: unsafe { MaybeUninit::<[MaybeUninit<T>; N]>::uninit().assume_init() },
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn spilled(&self) -> bool {
+        matches!(self, InlineVec::Heap(_))
+    }
+
+    pub(crate) fn as_slice(&self) -> &[T] {
+        match self {
+            // SAFETY: the first len items are initialized
+            InlineVec::Inline { len, items } => unsafe { slice::from_raw_parts(items.as_ptr() as *const T, *len) },
+            InlineVec::Heap(vec) => vec,
+        }
+    }
+
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [T] {
+        match self {
+            // SAFETY: the first len items are initialized
+            InlineVec::Inline { len, items } => unsafe { slice::from_raw_parts_mut(items.as_mut_ptr() as *mut T, *len) },
+            InlineVec::Heap(vec) => vec,
+        }
+    }
+
+    pub(crate) fn push(&mut self, item: T) {
+        match self {
+            InlineVec::Inline { len, items } if *len < N => {
+                items[*len] = MaybeUninit::new(item);
+                *len += 1;
+            },
+            InlineVec::Inline { len, items } => {
+                let mut vec = Vec::with_capacity(N * 2 + 1);
+                // SAFETY: the first len items are initialized, and are
+                // forgotten here by zeroing len before anything can panic
+                unsafe {
+                    vec.extend(items[..*len].iter().map(|item| item.as_ptr().read()));
+                }
+                *len = 0;
+                vec.push(item);
+                *self = InlineVec::Heap(vec);
+            },
+            InlineVec::Heap(vec) => vec.push(item),
+        }
+    }
+
+    /// Remove and return the item at `index`, shifting later items down.
+    pub(crate) fn remove(&mut self, index: usize) -> T {
+        match self {
+            InlineVec::Inline { len, items } => {
+                assert!(index < *len, "removal index {} out of bounds for length {}", index, len);
+                // SAFETY: index < len, so the item is initialized; the items
+                // after it are shifted over the hole it leaves
+                unsafe {
+                    let base = items.as_mut_ptr() as *mut T;
+                    let item = base.add(index).read();
+                    ptr::copy(base.add(index + 1), base.add(index), *len - index - 1);
+                    *len -= 1;
+                    item
+                }
+            },
+            InlineVec::Heap(vec) => vec.remove(index),
+        }
+    }
+
+    /// Keep only the items for which `f` returns true, in order. If `f`
+    /// panics, the items not yet removed are all kept.
+    pub(crate) fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool
+    {
+        match self {
+            // inline there are only a few items, so shifting is cheap
+            InlineVec::Inline { .. } => {
+                let mut i = 0;
+                while i < self.len() {
+                    if f(&self.as_slice()[i]) {
+                        i += 1;
+                    } else {
+                        drop(self.remove(i));
+                    }
+                }
+            },
+            InlineVec::Heap(vec) => vec.retain(f),
+        }
+    }
+
+    pub(crate) fn into_vec(mut self) -> Vec<T> {
+        match &mut self {
+            InlineVec::Inline { len, items } => {
+                let count = mem::replace(len, 0);
+                // SAFETY: the first count items were initialized, and with len
+                // zeroed, dropping self no longer touches them
+                items[..count].iter().map(|item| unsafe { item.as_ptr().read() }).collect()
+            },
+            InlineVec::Heap(vec) => mem::take(vec),
+        }
+    }
+}
+
+impl<T, const N: usize> Drop for InlineVec<T, N> {
+    fn drop(&mut self) {
+        if let InlineVec::Inline { .. } = self {
+            // SAFETY: as_mut_slice covers exactly the initialized items
+            unsafe { ptr::drop_in_place(self.as_mut_slice()) };
+        }
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for InlineVec<T, N> {
+    fn clone(&self) -> Self {
+        match self {
+            InlineVec::Inline { .. } => {
+                let mut clone = Self::new();
+                for item in self.as_slice() {
+                    clone.push(item.clone());
+                }
+                clone
+            },
+            InlineVec::Heap(vec) => InlineVec::Heap(vec.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn spills_to_heap() {
+        let mut v: InlineVec<String, 2> = InlineVec::new();
+        v.push("a".to_string());
+        v.push("b".to_string());
+        assert!(!v.spilled());
+        v.push("c".to_string());
+        assert!(v.spilled());
+        assert_eq!(v.as_slice(), ["a", "b", "c"]);
+        assert_eq!(v.clone().into_vec(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn remove_and_drop() {
+        let token = Rc::new(());
+        let mut v: InlineVec<Rc<()>, 4> = InlineVec::new();
+        for _ in 0..3 {
+            v.push(token.clone());
+        }
+        assert_eq!(Rc::strong_count(&token), 4);
+        drop(v.remove(1));
+        assert_eq!(v.len(), 2);
+        assert_eq!(Rc::strong_count(&token), 3);
+
+        let w = v.clone();
+        assert_eq!(Rc::strong_count(&token), 5);
+        drop(v);
+        drop(w.into_vec());
+        assert_eq!(Rc::strong_count(&token), 1);
+    }
+}
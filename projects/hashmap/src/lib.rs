@@ -4,25 +4,62 @@
 
 use std::default::Default;
 use std::borrow::Borrow;
-use std::hash::{Hash, Hasher, BuildHasher};
+use std::fmt;
+use std::hash::{Hash, BuildHasher};
+use std::iter::FromIterator;
+use std::ops::Index;
 use std::collections::hash_map::RandomState;
 
-const INITIAL_N_BUCKETS : usize = 1;
+mod builder;
+mod index_map;
+mod inline_vec;
+mod multi_map;
+mod persistent;
+mod raw;
+mod raw_entry;
+mod set;
+mod stats;
+mod table;
+
+pub mod cache;
+pub mod codec;
+pub mod concurrent;
+pub mod hash;
+
+use stats::ProbeGuard;
+use table::{Table, TableIter};
+
+pub use builder::Builder;
+pub use cache::{CacheStats, LfuCache, LruCache};
+pub use codec::{Decode, DecodeError, Encode};
+pub use concurrent::ConcurrentHashMap;
+pub use index_map::IndexMap;
+pub use multi_map::MultiMap;
+pub use persistent::{DiffItem, PersistentHashMap};
+pub use raw::TryReserveError;
+pub use set::HashSet;
+pub use stats::{MapStats, ResizeCost, Reseed, PROBE_LIMIT};
+
+pub use raw_entry::{RawEntryBuilder, RawEntryBuilderMut, RawEntryMut, RawOccupiedEntryMut, RawVacantEntryMut};
 
 pub struct HashMap<K, V, S = RandomState> {
-    buckets: Vec<Vec<(K, V)>>,
-    item_count: usize,
-    hash_builder: S
+    table: Table<(K, V)>,
+    hash_builder: S,
+    probe_guard: ProbeGuard<S>
 }
 
 impl<K, V> HashMap<K, V> {
     pub fn new() -> Self {
         Self {
-            buckets: Vec::new(),
-            item_count: 0,
-            hash_builder: RandomState::new()
+            table: Table::new(),
+            hash_builder: RandomState::new(),
+            probe_guard: ProbeGuard::Off
         }
     }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::new())
+    }
 }
 
 impl<K, V, S> HashMap<K, V, S> 
@@ -31,29 +68,134 @@ where
 {
     pub fn with_hasher(hash_builder: S) -> Self {
         Self {
-            buckets: Vec::new(),
-            item_count: 0,
-            hash_builder
+            table: Table::new(),
+            hash_builder,
+            probe_guard: ProbeGuard::Off
+        }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            table: Table::with_capacity(capacity, raw::DEFAULT_MAX_LOAD_FACTOR),
+            hash_builder,
+            probe_guard: ProbeGuard::Off
         }
     }
 }
 
+impl<K, V, S> HashMap<K, V, S> 
+where
+    K: Hash,
+    S: BuildHasher
+{
+    /// Make room for at least `additional` more entries without reallocating.
+    pub fn reserve(&mut self, additional: usize) {
+        self.table.reserve(additional, make_hasher(&self.hash_builder));
+    }
+
+    /// As reserve(), but report failure to allocate rather than panicking or aborting.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.table.try_reserve(additional, make_hasher(&self.hash_builder))
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.shrink_to(0);
+    }
+
+    /// Shrink the table as far as possible while keeping room for `min_capacity` entries.
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        self.table.shrink_to(min_capacity, make_hasher(&self.hash_builder));
+    }
+}
+
+pub(crate) fn make_hash<Q, S>(hash_builder: &S, key: &Q) -> u64
+where
+    Q: Hash + ?Sized,
+    S: BuildHasher
+{
+    hash_builder.hash_one(key)
+}
+
+// hashes a stored entry by its key, for use when the table moves entries around
+fn make_hasher<K, V, S>(hash_builder: &S) -> impl Fn(&(K, V)) -> u64 + '_
+where
+    K: Hash,
+    S: BuildHasher
+{
+    move |(key, _)| make_hash(hash_builder, key)
+}
+
 pub struct OccupiedEntry<'a, K, V> {
-    entry: &'a mut (K, V)
+    table: &'a mut Table<(K, V)>,
+    index: usize
 }
 
 pub struct VacantEntry<'a, K, V, S> {
     map: &'a mut HashMap<K, V, S>,
     key: K,
-    bucket: usize
+    hash: u64
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    fn pair(&self) -> &(K, V) {
+        // SAFETY: the entry was found in the table and the table has not been modified since
+        unsafe { self.table.get(self.index) }
+    }
+
+    fn pair_mut(&mut self) -> &mut (K, V) {
+        // SAFETY: as for pair()
+        unsafe { self.table.get_mut(self.index) }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.pair().0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.pair().1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.pair_mut().1
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        // SAFETY: as for pair(), and the table stays borrowed for 'a
+        unsafe { &mut self.table.get_mut(self.index).1 }
+    }
+
+    /// Replace the value, returning the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove_entry(self) -> (K, V) {
+        // SAFETY: as for pair(); the entry is consumed so the slot is never read again
+        unsafe { self.table.remove(self.index) }
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
 }
 
 impl<'a, K, V, S> VacantEntry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
     pub fn insert(self, value: V) -> &'a mut V {
-        let bucket = &mut self.map.buckets[self.bucket];
-        bucket.push((self.key, value));
-        self.map.item_count += 1;
-        &mut bucket.last_mut().unwrap().1
+        self.insert_entry(value).into_mut()
+    }
+
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V> {
+        let table = &mut self.map.table;
+        let index = table.insert_no_grow(self.hash, (self.key, value));
+        OccupiedEntry { table, index }
     }
 }
 
@@ -63,9 +205,16 @@ pub enum Entry<'a, K, V, S> {
 }
 
 impl<'a, K, V, S> Entry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => e.key(),
+        }
+    }
+
     pub fn or_insert(self, value: V) -> &'a mut V {
         match self {
-            Entry::Occupied(e) => &mut e.entry.1,
+            Entry::Occupied(e) => e.into_mut(),
             Entry::Vacant(e) => e.insert(value),
         }
     }
@@ -75,17 +224,55 @@ impl<'a, K, V, S> Entry<'a, K, V, S> {
         F: FnOnce() -> V
     {
         match self {
-            Entry::Occupied(e) => &mut e.entry.1,
+            Entry::Occupied(e) => e.into_mut(),
             Entry::Vacant(e) => e.insert(maker()),
         }
     }
 
+    pub fn or_insert_with_key<F>(self, maker: F) -> &'a mut V 
+    where
+        F: FnOnce(&K) -> V
+    {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
+                let value = maker(e.key());
+                e.insert(value)
+            },
+        }
+    }
+
     pub fn or_default(self) -> &'a mut V 
     where
         V: Default
     {
         self.or_insert_with(Default::default)
     }
+
+    /// Modify the value in place if the entry is occupied.
+    pub fn and_modify<F>(self, f: F) -> Self 
+    where
+        F: FnOnce(&mut V)
+    {
+        match self {
+            Entry::Occupied(mut e) => {
+                f(e.get_mut());
+                Entry::Occupied(e)
+            },
+            Entry::Vacant(e) => Entry::Vacant(e),
+        }
+    }
+
+    /// Set the value of the entry, occupied or not.
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V> {
+        match self {
+            Entry::Occupied(mut e) => {
+                e.insert(value);
+                e
+            },
+            Entry::Vacant(e) => e.insert_entry(value),
+        }
+    }
 }
 
 impl<K, V, S> HashMap<K, V, S> 
@@ -93,39 +280,12 @@ where
     K: Hash + Eq,
     S: BuildHasher
 {
-    fn bucket<Q>(&self, key: &Q) -> Option<usize> 
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<usize> 
     where
         K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        Q: Eq + ?Sized,
     {
-        if self.buckets.is_empty() {
-            return None;
-        }
-
-        let mut hasher = self.hash_builder.build_hasher();
-        key.hash(&mut hasher);
-        Some((hasher.finish() % self.buckets.len() as u64) as usize)
-    }
-
-    fn resize(&mut self) {
-        use std::mem;
-
-        let target_size = match self.buckets.len() {
-            0 => INITIAL_N_BUCKETS,
-            n => n*2
-        };
-
-        let mut new_buckets = Vec::with_capacity(target_size);
-        new_buckets.extend((0..target_size).map(|_| Vec::new()));
-
-        for (key, value) in self.buckets.iter_mut().flat_map(|bucket| bucket.drain(..)) {
-            let mut hasher = self.hash_builder.build_hasher();
-            key.hash(&mut hasher);
-            let bucket = (hasher.finish() % new_buckets.len() as u64) as usize;
-            new_buckets[bucket].push((key, value));
-        }
-
-        mem::replace(&mut self.buckets, new_buckets);
+        self.table.find(hash, |(ekey, _)| ekey.borrow() == key)
     }
 }
 
@@ -134,18 +294,20 @@ where
     K: Hash + Eq,
     S: BuildHasher
 {
-    pub fn entry(&mut self, key: K) -> Entry<K, V, S> {
-        if self.buckets.is_empty() || self.item_count > 3*self.buckets.len()/4 {
-            self.resize();
-        }
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        let hash = make_hash(&self.hash_builder, &key);
 
-        let bucket = self.bucket(&key).expect("buckets.is_empty() handled above");
-
-        match self.buckets[bucket].iter().position(|&(ref ekey, _)| ekey == &key) {
+        match self.find(hash, &key) {
             Some(index) => Entry::Occupied(OccupiedEntry{
-                entry: &mut self.buckets[bucket][index]
+                table: &mut self.table,
+                index
             }),
-            None => Entry::Vacant(VacantEntry{ map: self, key, bucket }),
+            None => {
+                // make room up front so that VacantEntry::insert never needs to rehash
+                self.table.reserve(1, make_hasher(&self.hash_builder));
+                let hash = self.guard_vacant(hash, &key);
+                Entry::Vacant(VacantEntry{ map: self, key, hash })
+            }
         }
     }
 }
@@ -156,23 +318,19 @@ where
     S: BuildHasher
 {
     pub fn insert(&mut self, key: K, value: V) -> Option<V> {
-        if self.buckets.is_empty() || self.item_count > 3*self.buckets.len()/4 {
-            self.resize();
-        }
+        use std::mem;
 
-        let bucket = self.bucket(&key).expect("buckets.is_empty() handled above");
-        let bucket = &mut self.buckets[bucket];
+        let hash = make_hash(&self.hash_builder, &key);
 
-        for &mut (ref ekey, ref mut evalue) in bucket.iter_mut() {
-            if key == *ekey {
-                use std::mem;
-                return Some(mem::replace(evalue, value));
-            }
+        if let Some(index) = self.find(hash, &key) {
+            // SAFETY: index was just returned by find
+            let (_, evalue) = unsafe { self.table.get_mut(index) };
+            return Some(mem::replace(evalue, value));
         }
 
         // key not found, insert new
-        bucket.push((key, value));
-        self.item_count += 1;
+        let index = self.table.insert(hash, (key, value), make_hasher(&self.hash_builder));
+        self.guard_inserted(hash, index);
         None
     }
 
@@ -181,11 +339,11 @@ where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        let bucket = self.bucket(&key)?;
-        self.buckets[bucket]
-            .iter()
-            .find(|&(ref ekey, _)| { ekey.borrow() == key })
-            .map(|&(_, ref evalue)| evalue)        
+        let hash = make_hash(&self.hash_builder, key);
+        let index = self.find(hash, key)?;
+        // SAFETY: index was just returned by find
+        let (_, evalue) = unsafe { self.table.get(index) };
+        Some(evalue)
     }
 
     pub fn contains_key<Q>(&self, key: &Q) -> bool 
@@ -201,173 +359,451 @@ where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        let bucket = self.bucket(key)?;
-        let bucket = &mut self.buckets[bucket];
-        let i = bucket.iter().position(|&(ref ekey, _)| ekey.borrow() == key)?;
-        
-        self.item_count -= 1;
-        Some(bucket.swap_remove(i).1)
+        self.table.migrate_step(&make_hasher(&self.hash_builder));
+
+        let hash = make_hash(&self.hash_builder, key);
+        let index = self.find(hash, key)?;
+        // SAFETY: index was just returned by find
+        let (_, evalue) = unsafe { self.table.remove(index) };
+        Some(evalue)
     }
+}
 
+impl<K, V, S> HashMap<K, V, S> {
     pub fn len(&self) -> usize {
-        self.item_count
+        self.table.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.item_count == 0
+        self.table.len() == 0
     }
-}
 
-impl<K, V, S> HashMap<K, V, S> 
-where
-    K: Hash + Eq,
-    S: BuildHasher
-{
-    pub fn iter<'a>(&'a self) -> Iter<'a, K, V, S> {
-        Iter::new(self)
+    pub fn capacity(&self) -> usize {
+        self.table.capacity()
+    }
+
+    pub fn max_load_factor(&self) -> f32 {
+        self.table.max_load_factor()
+    }
+
+    pub fn clear(&mut self) {
+        self.table.clear();
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::new(&self.table)
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
     }
 
-    pub fn keys<'a>(&'a self) -> Keys<'a, K, V, S> {
-        Keys::new(self)
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
     }
 
-    pub fn values<'a>(&'a self) -> Values<'a, K, V, S> {
-        Values::new(self)
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut::new(&mut self.table)
+    }
+
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut { inner: self.iter_mut() }
+    }
+
+    pub fn into_keys(self) -> IntoKeys<K, V> {
+        IntoKeys { inner: self.into_iter() }
+    }
+
+    pub fn into_values(self) -> IntoValues<K, V> {
+        IntoValues { inner: self.into_iter() }
+    }
+
+    /// Remove every entry, yielding them as an iterator.
+    ///
+    /// Entries that are not consumed are dropped along with the iterator.
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        let inner = self.table.iter();
+        Drain { table: &mut self.table, inner }
+    }
+
+    /// Keep only the entries for which `f` returns true.
+    pub fn retain<F>(&mut self, mut f: F) 
+    where
+        F: FnMut(&K, &mut V) -> bool
+    {
+        for index in self.table.iter() {
+            // SAFETY: removing the slot being visited does not disturb the iteration
+            unsafe {
+                let (k, v) = self.table.get_mut(index);
+                if !f(k, v) {
+                    drop(self.table.remove(index));
+                }
+            }
+        }
+    }
+
+    /// Lazily remove and yield the entries for which `pred` returns true.
+    ///
+    /// Entries that are not visited because the iterator is dropped early are retained.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, K, V, F> 
+    where
+        F: FnMut(&K, &mut V) -> bool
+    {
+        let inner = self.table.iter();
+        ExtractIf { table: &mut self.table, inner, pred }
     }
 }
 
-pub struct Iter<'a, K, V, S> {
-    map: &'a HashMap<K, V, S>,
-    bucket: usize,
-    at: usize
+pub struct Iter<'a, K, V> {
+    table: &'a Table<(K, V)>,
+    inner: TableIter
 }
 
-pub struct IterMut<'a, K, V, S> {
-    map: &'a mut HashMap<K, V, S>,
-    bucket: usize,
-    at: usize
+pub struct IterMut<'a, K, V> {
+    table: &'a mut Table<(K, V)>,
+    inner: TableIter
 }
 
-pub struct Keys<'a, K, V, S> {
-    map: &'a HashMap<K, V, S>,
-    bucket: usize,
-    at: usize
+pub struct IntoIter<K, V> {
+    table: Table<(K, V)>,
+    inner: TableIter
 }
 
-pub struct Values<'a, K, V, S> {
-    map: &'a HashMap<K, V, S>,
-    bucket: usize,
-    at: usize
+pub struct Keys<'a, K, V> {
+    inner: Iter<'a, K, V>
 }
 
-impl<'a, K, V, S> Iter<'a, K, V, S> {
-    pub(crate) fn new(map: &'a HashMap<K, V, S>) -> Self {
-        Self {
-            map,
-            bucket: 0,
-            at: 0
-        }
-    }
+pub struct Values<'a, K, V> {
+    inner: Iter<'a, K, V>
+}
+
+pub struct ValuesMut<'a, K, V> {
+    inner: IterMut<'a, K, V>
+}
+
+pub struct IntoKeys<K, V> {
+    inner: IntoIter<K, V>
+}
+
+pub struct IntoValues<K, V> {
+    inner: IntoIter<K, V>
+}
+
+pub struct Drain<'a, K, V> {
+    table: &'a mut Table<(K, V)>,
+    inner: TableIter
+}
+
+pub struct ExtractIf<'a, K, V, F> {
+    table: &'a mut Table<(K, V)>,
+    inner: TableIter,
+    pred: F
 }
 
-impl<'a, K, V, S> Keys<'a, K, V, S> {
-    pub(crate) fn new(map: &'a HashMap<K, V, S>) -> Self {
+impl<'a, K, V> Iter<'a, K, V> {
+    pub(crate) fn new(table: &'a Table<(K, V)>) -> Self {
         Self {
-            map,
-            bucket: 0,
-            at: 0
+            table,
+            inner: table.iter()
         }
     }
 }
 
-impl<'a, K, V, S> Values<'a, K, V, S> {
-    pub(crate) fn new(map: &'a HashMap<K, V, S>) -> Self {
+impl<'a, K, V> IterMut<'a, K, V> {
+    pub(crate) fn new(table: &'a mut Table<(K, V)>) -> Self {
+        let inner = table.iter();
         Self {
-            map,
-            bucket: 0,
-            at: 0
+            table,
+            inner
         }
     }
 }
 
-impl<'a, K, V, S> Iterator for Iter<'a, K, V, S> {
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
     type Item = (&'a K, &'a V);
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            match self.map.buckets.get(self.bucket) {
-                Some(bucket) => {
-                    match bucket.get(self.at) {
-                        Some(&(ref k, ref v)) => {
-                            self.at += 1;
-                            break Some((k, v));
-                        },
-                        None => {
-                            self.bucket += 1;
-                            self.at = 0;
-                            continue;
-                        },
-                    }
-                },
-                None => break None,
-            }
-        }
+        let index = self.inner.next()?;
+        // SAFETY: the table is borrowed for 'a, and index refers to a FULL slot
+        let (k, v) = unsafe { self.table.get(index) };
+        Some((k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
     }
 } 
 
-impl<'a, K, V, S> Iterator for Keys<'a, K, V, S> {
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
     type Item = &'a K;
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            match self.map.buckets.get(self.bucket) {
-                Some(bucket) => {
-                    match bucket.get(self.at) {
-                        Some(&(ref k, _)) => {
-                            self.at += 1;
-                            break Some(k);
-                        },
-                        None => {
-                            self.bucket += 1;
-                            self.at = 0;
-                            continue;
-                        },
-                    }
-                },
-                None => break None,
-            }
-        }
+        self.inner.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
     }
 }
 
-impl<'a, K, V, S> Iterator for Values<'a, K, V, S> {
+impl<'a, K, V> Iterator for Values<'a, K, V> {
     type Item = &'a V;
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            match self.map.buckets.get(self.bucket) {
-                Some(bucket) => {
-                    match bucket.get(self.at) {
-                        Some(&(_, ref v)) => {
-                            self.at += 1;
-                            break Some(v);
-                        },
-                        None => {
-                            self.bucket += 1;
-                            self.at = 0;
-                            continue;
-                        },
-                    }
-                },
-                None => break None,
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.inner.next()?;
+        // SAFETY: the table is mutably borrowed for 'a, and each FULL slot is
+        // yielded at most once, so the returned references never alias
+        let (k, v) = unsafe { &mut *self.table.bucket(index) };
+        Some((&*k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.inner.next()?;
+        // SAFETY: index refers to a FULL slot, and removing it does not disturb the
+        // iteration; whatever remains is dropped along with the table
+        Some(unsafe { self.table.remove(index) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> Iterator for IntoKeys<K, V> {
+    type Item = K;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> Iterator for IntoValues<K, V> {
+    type Item = V;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> Iterator for Drain<'a, K, V> {
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.inner.next()?;
+        // SAFETY: as for IntoIter; the table stays consistent even if the Drain is leaked
+        Some(unsafe { self.table.remove(index) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> Drop for Drain<'a, K, V> {
+    fn drop(&mut self) {
+        self.table.clear();
+    }
+}
+
+impl<'a, K, V, F> Iterator for ExtractIf<'a, K, V, F> 
+where
+    F: FnMut(&K, &mut V) -> bool
+{
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        for index in &mut self.inner {
+            // SAFETY: index refers to a FULL slot, and removing it does not disturb the iteration
+            unsafe {
+                let (k, v) = self.table.get_mut(index);
+                if (self.pred)(k, v) {
+                    return Some(self.table.remove(index));
+                }
             }
         }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
     }
 }
 
 impl<'a, K, V, S> IntoIterator for &'a HashMap<K, V, S> {
     type Item = (&'a K, &'a V);
-    type IntoIter = Iter<'a, K, V, S>;
+    type IntoIter = Iter<'a, K, V>;
 
     fn into_iter(self) -> Self::IntoIter {
-        Iter::new(self)
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut HashMap<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<K, V, S> IntoIterator for HashMap<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let inner = self.table.iter();
+        IntoIter { table: self.table, inner }
+    }
+}
+
+impl<K, V, S> Default for HashMap<K, V, S> 
+where
+    S: BuildHasher + Default
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> Clone for HashMap<K, V, S> 
+where
+    K: Clone,
+    V: Clone,
+    S: Clone
+{
+    fn clone(&self) -> Self {
+        Self {
+            table: self.table.clone(),
+            hash_builder: self.hash_builder.clone(),
+            probe_guard: self.probe_guard.clone()
+        }
+    }
+}
+
+impl<K, V, S> fmt::Debug for HashMap<K, V, S> 
+where
+    K: fmt::Debug,
+    V: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, S> PartialEq for HashMap<K, V, S> 
+where
+    K: Hash + Eq,
+    V: PartialEq,
+    S: BuildHasher
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<K, V, S> Eq for HashMap<K, V, S> 
+where
+    K: Hash + Eq,
+    V: Eq,
+    S: BuildHasher
+{
+}
+
+impl<K, Q, V, S> Index<&Q> for HashMap<K, V, S> 
+where
+    K: Hash + Eq + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    S: BuildHasher
+{
+    type Output = V;
+
+    /// Panics if the key is not present.
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("key not found in HashMap")
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for HashMap<K, V, S> 
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::with_hasher(S::default());
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V, const N: usize> From<[(K, V); N]> for HashMap<K, V> 
+where
+    K: Hash + Eq
+{
+    fn from(entries: [(K, V); N]) -> Self {
+        IntoIterator::into_iter(entries).collect()
+    }
+}
+
+impl<K, V, S> Extend<(K, V)> for HashMap<K, V, S> 
+where
+    K: Hash + Eq,
+    S: BuildHasher
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        // Duplicate keys don't need room, so when adding to a non-empty map
+        // only reserve for half of the incoming entries, as std does.
+        let iter = iter.into_iter();
+        let reserve = if self.is_empty() {
+            iter.size_hint().0
+        } else {
+            iter.size_hint().0.div_ceil(2)
+        };
+        self.reserve(reserve);
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<'a, K, V, S> Extend<(&'a K, &'a V)> for HashMap<K, V, S> 
+where
+    K: Hash + Eq + Copy,
+    V: Copy,
+    S: BuildHasher
+{
+    fn extend<I: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(&k, &v)| (k, v)));
     }
 }
 
@@ -420,7 +856,7 @@ mod tests {
         map.insert(1, 1);
         map.insert(2, 2);
 
-        let keys: Vec<i32> = map.keys().map(|&k| k.clone()).collect();
+        let keys: Vec<i32> = map.keys().copied().collect();
 
         assert_eq!(keys.len(), 3);
         assert!(keys.contains(&0));
@@ -435,7 +871,7 @@ mod tests {
         map.insert(1, 1);
         map.insert(2, 2);
 
-        let values: Vec<i32> = map.values().map(|&k| k.clone()).collect();
+        let values: Vec<i32> = map.values().copied().collect();
 
         assert_eq!(values.len(), 3);
         assert!(values.contains(&0));
@@ -455,4 +891,587 @@ mod tests {
         assert_eq!(*map.entry(0).or_insert(1337), expected);
         assert_eq!(map.len(), 1);
     }
+
+    #[test]
+    fn insert_replaces() {
+        let mut map : HashMap<i32, i32> = HashMap::new();
+        assert_eq!(map.insert(0, 0), None);
+        assert_eq!(map.insert(0, 1), Some(0));
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get(&0), Some(&1));
+    }
+
+    #[test]
+    fn growth() {
+        let mut map : HashMap<i32, i32> = HashMap::new();
+        for i in 0..10_000 {
+            map.insert(i, i * 2);
+        }
+
+        assert_eq!(map.len(), 10_000);
+        assert!(map.capacity() >= 10_000);
+        for i in 0..10_000 {
+            assert_eq!(map.get(&i), Some(&(i * 2)));
+        }
+        assert_eq!(map.get(&10_000), None);
+        assert_eq!(map.iter().count(), 10_000);
+    }
+
+    #[test]
+    fn remove() {
+        let mut map : HashMap<i32, i32> = HashMap::new();
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+
+        for i in (0..1000).step_by(2) {
+            assert_eq!(map.remove(&i), Some(i));
+            assert_eq!(map.remove(&i), None);
+        }
+
+        assert_eq!(map.len(), 500);
+        for i in 0..1000 {
+            assert_eq!(map.contains_key(&i), i % 2 == 1);
+        }
+
+        // reinserting fills the tombstones left behind
+        for i in (0..1000).step_by(2) {
+            map.insert(i, i);
+        }
+        assert_eq!(map.len(), 1000);
+    }
+
+    #[test]
+    fn borrowed_lookup() {
+        let mut map : HashMap<String, i32> = HashMap::new();
+        map.insert("foo".to_string(), 1);
+
+        assert_eq!(map.get("foo"), Some(&1));
+        assert_eq!(map.remove("foo"), Some(1));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn drops_values() {
+        use std::rc::Rc;
+
+        let value = Rc::new(());
+        let mut map : HashMap<i32, Rc<()>> = HashMap::new();
+        for i in 0..100 {
+            map.insert(i, Rc::clone(&value));
+        }
+        map.remove(&0);
+        assert_eq!(Rc::strong_count(&value), 100);
+
+        map.clear();
+        assert_eq!(Rc::strong_count(&value), 1);
+        assert_eq!(map.len(), 0);
+
+        map.insert(0, Rc::clone(&value));
+        drop(map);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn zero_sized_values() {
+        let mut map : HashMap<(), ()> = HashMap::new();
+        assert_eq!(map.insert((), ()), None);
+        assert_eq!(map.insert((), ()), Some(()));
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.remove(&()), Some(()));
+    }
+
+    #[test]
+    fn iter_mut() {
+        let mut map : HashMap<i32, i32> = HashMap::new();
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+
+        for (&k, v) in &mut map {
+            *v += k;
+        }
+        for v in map.values_mut() {
+            *v += 1;
+        }
+
+        for i in 0..100 {
+            assert_eq!(map.get(&i), Some(&(2 * i + 1)));
+        }
+    }
+
+    #[test]
+    fn into_iter() {
+        let mut map : HashMap<i32, String> = HashMap::new();
+        for i in 0..100 {
+            map.insert(i, i.to_string());
+        }
+
+        let mut entries: Vec<(i32, String)> = map.into_iter().collect();
+        entries.sort();
+        assert_eq!(entries.len(), 100);
+        for (i, (k, v)) in entries.into_iter().enumerate() {
+            assert_eq!(k, i as i32);
+            assert_eq!(v, i.to_string());
+        }
+    }
+
+    #[test]
+    fn into_keys_and_values() {
+        let mut map : HashMap<i32, i32> = HashMap::new();
+        for i in 0..10 {
+            map.insert(i, i * 10);
+        }
+
+        let mut keys: Vec<i32> = map.into_keys().collect();
+        keys.sort_unstable();
+        assert_eq!(keys, (0..10).collect::<Vec<_>>());
+
+        let mut map : HashMap<i32, i32> = HashMap::new();
+        for i in 0..10 {
+            map.insert(i, i * 10);
+        }
+
+        let mut values: Vec<i32> = map.into_values().collect();
+        values.sort_unstable();
+        assert_eq!(values, (0..10).map(|i| i * 10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn partial_into_iter_drops_rest() {
+        use std::rc::Rc;
+
+        let value = Rc::new(());
+        let mut map : HashMap<i32, Rc<()>> = HashMap::new();
+        for i in 0..10 {
+            map.insert(i, Rc::clone(&value));
+        }
+
+        let mut iter = map.into_iter();
+        let taken: Vec<_> = iter.by_ref().take(3).collect();
+        assert_eq!(iter.size_hint(), (7, Some(7)));
+        drop(iter);
+        assert_eq!(Rc::strong_count(&value), 4);
+        drop(taken);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn drain() {
+        let mut map : HashMap<i32, i32> = HashMap::new();
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+
+        let mut drained: Vec<i32> = map.drain().map(|(k, _)| k).collect();
+        drained.sort_unstable();
+        assert_eq!(drained, (0..100).collect::<Vec<_>>());
+        assert!(map.is_empty());
+
+        // a partially consumed drain still empties the map
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+        assert_eq!(map.drain().take(10).count(), 10);
+        assert!(map.is_empty());
+        assert_eq!(map.iter().count(), 0);
+
+        map.insert(1, 1);
+        assert_eq!(map.get(&1), Some(&1));
+    }
+
+    #[test]
+    fn leaked_drain() {
+        let mut map : HashMap<i32, String> = HashMap::new();
+        for i in 0..100 {
+            map.insert(i, i.to_string());
+        }
+
+        let mut drain = map.drain();
+        assert!(drain.next().is_some());
+        std::mem::forget(drain);
+
+        // whatever was not yet yielded is still in the map
+        assert_eq!(map.len(), 99);
+        assert_eq!(map.iter().count(), 99);
+    }
+
+    #[test]
+    fn retain() {
+        let mut map : HashMap<i32, i32> = HashMap::new();
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+
+        map.retain(|&k, v| {
+            *v *= 2;
+            k % 3 == 0
+        });
+
+        assert_eq!(map.len(), 334);
+        assert_eq!(map.iter().count(), 334);
+        for i in 0..1000 {
+            if i % 3 == 0 {
+                assert_eq!(map.get(&i), Some(&(2 * i)));
+            } else {
+                assert!(!map.contains_key(&i));
+            }
+        }
+
+        map.retain(|_, _| false);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn retain_then_insert() {
+        let mut map : HashMap<i32, i32> = HashMap::new();
+        for round in 0..10 {
+            for i in 0..100 {
+                map.insert(round * 100 + i, i);
+            }
+            map.retain(|_, &mut v| v % 10 == 0);
+        }
+
+        assert_eq!(map.len(), 100);
+        assert!(map.values().all(|&v| v % 10 == 0));
+    }
+
+    #[test]
+    fn extract_if() {
+        let mut map : HashMap<i32, i32> = HashMap::new();
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+
+        let mut evens: Vec<i32> = map.extract_if(|k, _| k % 2 == 0).map(|(k, _)| k).collect();
+        evens.sort_unstable();
+        assert_eq!(evens, (0..100).step_by(2).collect::<Vec<_>>());
+        assert_eq!(map.len(), 50);
+
+        // stopping early retains the unvisited entries
+        assert_eq!(map.extract_if(|_, _| true).take(5).count(), 5);
+        assert_eq!(map.len(), 45);
+        assert_eq!(map.iter().count(), 45);
+    }
+
+    #[test]
+    fn entry_counting() {
+        let mut counts : HashMap<char, usize> = HashMap::new();
+        for c in "abracadabra".chars() {
+            counts.entry(c).and_modify(|n| *n += 1).or_insert(1);
+        }
+
+        assert_eq!(counts.len(), 5);
+        assert_eq!(counts.get(&'a'), Some(&5));
+        assert_eq!(counts.get(&'b'), Some(&2));
+        assert_eq!(counts.get(&'r'), Some(&2));
+        assert_eq!(counts.get(&'c'), Some(&1));
+        assert_eq!(counts.get(&'d'), Some(&1));
+    }
+
+    #[test]
+    fn entry_grouping() {
+        let mut groups : HashMap<usize, Vec<&str>> = HashMap::new();
+        for word in ["a", "bb", "cc", "d", "eee"] {
+            groups.entry(word.len()).or_default().push(word);
+        }
+
+        assert_eq!(groups.get(&1), Some(&vec!["a", "d"]));
+        assert_eq!(groups.get(&2), Some(&vec!["bb", "cc"]));
+        assert_eq!(groups.get(&3), Some(&vec!["eee"]));
+    }
+
+    #[test]
+    fn entry_or_insert_with_key() {
+        let mut map : HashMap<i32, String> = HashMap::new();
+        assert_eq!(map.entry(7).or_insert_with_key(|k| k.to_string()), "7");
+        assert_eq!(map.entry(7).or_insert_with_key(|_| unreachable!()), "7");
+        assert_eq!(map.entry(8).key(), &8);
+    }
+
+    #[test]
+    fn occupied_entry() {
+        let mut map : HashMap<&str, i32> = HashMap::new();
+        map.insert("foo", 1);
+
+        match map.entry("foo") {
+            Entry::Occupied(mut e) => {
+                assert_eq!(e.key(), &"foo");
+                assert_eq!(e.get(), &1);
+                *e.get_mut() += 1;
+                assert_eq!(e.insert(10), 2);
+                assert_eq!(e.get(), &10);
+            },
+            Entry::Vacant(_) => unreachable!(),
+        }
+        assert_eq!(map.get("foo"), Some(&10));
+
+        match map.entry("foo") {
+            Entry::Occupied(e) => assert_eq!(e.remove_entry(), ("foo", 10)),
+            Entry::Vacant(_) => unreachable!(),
+        }
+        assert!(map.is_empty());
+        assert!(!map.contains_key("foo"));
+    }
+
+    #[test]
+    fn vacant_entry() {
+        let mut map : HashMap<String, i32> = HashMap::new();
+
+        match map.entry("foo".to_string()) {
+            Entry::Vacant(e) => {
+                assert_eq!(e.key(), "foo");
+                let mut e = e.insert_entry(1);
+                assert_eq!(e.insert(2), 1);
+            },
+            Entry::Occupied(_) => unreachable!(),
+        }
+        assert_eq!(map.get("foo"), Some(&2));
+
+        match map.entry("bar".to_string()) {
+            Entry::Vacant(e) => assert_eq!(e.into_key(), "bar"),
+            Entry::Occupied(_) => unreachable!(),
+        }
+        assert_eq!(map.len(), 1);
+
+        let e = map.entry("foo".to_string()).insert_entry(3);
+        assert_eq!(e.remove(), 3);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn with_capacity() {
+        let map : HashMap<i32, i32> = HashMap::with_capacity(0);
+        assert_eq!(map.capacity(), 0);
+
+        let mut map : HashMap<i32, i32> = HashMap::with_capacity(100);
+        let capacity = map.capacity();
+        assert!(capacity >= 100);
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+        assert_eq!(map.capacity(), capacity);
+    }
+
+    #[test]
+    fn reserve() {
+        let mut map : HashMap<i32, i32> = HashMap::new();
+        map.insert(0, 0);
+        map.reserve(1000);
+        let capacity = map.capacity();
+        assert!(capacity >= 1001);
+
+        for i in 0..1001 {
+            map.insert(i, i);
+        }
+        assert_eq!(map.capacity(), capacity);
+        assert_eq!(map.len(), 1001);
+    }
+
+    #[test]
+    fn try_reserve() {
+        let mut map : HashMap<i32, i32> = HashMap::new();
+        assert_eq!(map.try_reserve(10), Ok(()));
+        assert!(map.capacity() >= 10);
+
+        map.insert(0, 0);
+        assert_eq!(map.try_reserve(usize::MAX), Err(TryReserveError::CapacityOverflow));
+        assert_eq!(map.try_reserve(usize::MAX / 4), Err(TryReserveError::CapacityOverflow));
+
+        // a failed reservation leaves the map untouched
+        assert_eq!(map.get(&0), Some(&0));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn shrink() {
+        let mut map : HashMap<i32, i32> = HashMap::new();
+        for i in 0..10_000 {
+            map.insert(i, i);
+        }
+        let full_capacity = map.capacity();
+
+        map.retain(|&k, _| k < 10);
+        assert!(map.capacity() <= full_capacity);
+
+        map.shrink_to(100);
+        assert!(map.capacity() >= 100 && map.capacity() < full_capacity);
+
+        map.shrink_to_fit();
+        assert!(map.capacity() >= 10 && map.capacity() < 100);
+        for i in 0..10 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+
+        map.clear();
+        map.shrink_to_fit();
+        assert_eq!(map.capacity(), 0);
+        map.insert(1, 1);
+        assert_eq!(map.get(&1), Some(&1));
+    }
+
+    // counts every hash computed, as a proxy for the work done by each operation
+    #[derive(Clone, Default)]
+    struct CountingState {
+        hashes: std::rc::Rc<std::cell::Cell<usize>>
+    }
+
+    impl BuildHasher for CountingState {
+        type Hasher = std::collections::hash_map::DefaultHasher;
+        fn build_hasher(&self) -> Self::Hasher {
+            self.hashes.set(self.hashes.get() + 1);
+            Default::default()
+        }
+    }
+
+    // the most hashes computed by any single insert while filling a map
+    fn max_hashes_per_insert(mut map: HashMap<u32, u32, CountingState>) -> usize {
+        let hashes = map.hasher().hashes.clone();
+        let mut worst = 0;
+        for i in 0..100_000 {
+            let before = hashes.get();
+            map.insert(i, i);
+            worst = worst.max(hashes.get() - before);
+        }
+
+        for i in 0..100_000 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+        assert_eq!(map.len(), 100_000);
+        worst
+    }
+
+    #[test]
+    fn incremental_resize_bounds_insert_work() {
+        let map = Builder::new().hasher(CountingState::default()).build();
+        // a stop-the-world resize rehashes every entry
+        assert!(max_hashes_per_insert(map) > 50_000);
+
+        let map = Builder::new().hasher(CountingState::default()).incremental_resize(true).build();
+        // the key itself, plus at most one hash per migrated slot
+        assert!(max_hashes_per_insert(map) <= 1 + table::MIGRATE_STEP);
+    }
+
+    #[test]
+    fn incremental_resize_operations() {
+        let mut map : HashMap<u32, u32> = Builder::new().incremental_resize(true).build();
+        let mut expected = std::collections::HashMap::new();
+        for i in 0..10_000 {
+            map.insert(i, i);
+            expected.insert(i, i);
+            if i % 3 == 0 {
+                assert_eq!(map.remove(&(i / 2)), expected.remove(&(i / 2)));
+            }
+            // every remaining entry is reachable, whichever table holds it
+            if i % 97 == 0 {
+                assert_eq!(map.iter().count(), map.len());
+            }
+        }
+
+        assert_eq!(map.len(), expected.len());
+        for i in 0..10_000 {
+            assert_eq!(map.get(&i), expected.get(&i));
+        }
+
+        *map.entry(1).or_insert(0) += 1;
+        map.retain(|&k, _| k % 2 == 1);
+        assert!(map.keys().all(|k| k % 2 == 1));
+
+        let len = map.len();
+        assert_eq!(map.drain().count(), len);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn collect_and_extend() {
+        let mut map : HashMap<i32, i32> = (0..100).map(|i| (i, i * i)).collect();
+        assert_eq!(map.len(), 100);
+        assert_eq!(map[&9], 81);
+
+        map.extend((50..150).map(|i| (i, -i)));
+        assert_eq!(map.len(), 150);
+        assert_eq!(map[&49], 49 * 49);
+        assert_eq!(map[&50], -50);
+
+        let other : HashMap<i32, i32> = HashMap::from([(1000, 1), (1001, 2)]);
+        map.extend(&other);
+        assert_eq!(map.len(), 152);
+        assert_eq!(map[&1001], 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn index_missing_key() {
+        let map : HashMap<&str, i32> = HashMap::from([("a", 1)]);
+        let _ = map["b"];
+    }
+
+    #[test]
+    fn clone_and_eq() {
+        let mut map : HashMap<String, Vec<i32>> = HashMap::new();
+        for i in 0..100 {
+            map.insert(i.to_string(), vec![i]);
+        }
+        for i in 0..50 {
+            map.remove(&i.to_string());
+        }
+
+        let mut copy = map.clone();
+        assert_eq!(copy, map);
+        assert_eq!(copy.len(), 50);
+
+        // the clone is independent of the original, tombstones and all
+        copy.entry("99".to_string()).or_default().push(100);
+        assert_ne!(copy, map);
+        for i in 0..200 {
+            copy.insert(i.to_string(), vec![]);
+        }
+        assert_eq!(map.len(), 50);
+        assert_eq!(map["99"], vec![99]);
+
+        // equality ignores order and hasher state
+        let a : HashMap<i32, i32> = (0..10).map(|i| (i, i)).collect();
+        let b : HashMap<i32, i32> = (0..10).rev().map(|i| (i, i)).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, HashMap::new());
+    }
+
+    #[test]
+    fn clone_panic_safety() {
+        use std::rc::Rc;
+        use std::panic::{self, AssertUnwindSafe};
+
+        struct Bomb(Rc<()>, bool);
+        impl Clone for Bomb {
+            fn clone(&self) -> Self {
+                assert!(!self.1, "boom");
+                Bomb(Rc::clone(&self.0), self.1)
+            }
+        }
+
+        let counter = Rc::new(());
+        let mut map : HashMap<i32, Bomb> = HashMap::new();
+        for i in 0..100 {
+            map.insert(i, Bomb(Rc::clone(&counter), i == 57));
+        }
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| map.clone()));
+        assert!(result.is_err());
+        // whatever was cloned before the panic has been dropped again
+        assert_eq!(Rc::strong_count(&counter), 101);
+    }
+
+    #[test]
+    fn debug() {
+        let map : HashMap<&str, i32> = HashMap::from([("a", 1)]);
+        assert_eq!(format!("{:?}", map), "{\"a\": 1}");
+        assert_eq!(format!("{:?}", HashMap::<i32, i32>::new()), "{}");
+    }
+
+    #[test]
+    fn default_with_custom_hasher() {
+        let mut map : hash::FxHashMap<i32, i32> = Default::default();
+        map.insert(1, 1);
+        assert_eq!(map[&1], 1);
+    }
 }
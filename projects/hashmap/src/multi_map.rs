@@ -0,0 +1,475 @@
+// multi_map.rs
+// Map from each key to any number of values, built on HashMap.
+//
+// Most keys in a multimap hold only a value or two, so each key's values
+// live in an InlineVec that only allocates once it outgrows INLINE.
+// Values are kept in insertion order per key, and a key disappears with
+// its last value.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::mem;
+use std::slice;
+
+use crate::inline_vec::InlineVec;
+use crate::{HashMap, RawEntryMut};
+
+const INLINE: usize = 2;
+
+type Values<V> = InlineVec<V, INLINE>;
+
+pub struct MultiMap<K, V, S = RandomState> {
+    map: HashMap<K, Values<V>, S>,
+    len_values: usize
+}
+
+impl<K, V> MultiMap<K, V> {
+    pub fn new() -> Self {
+        Self { map: HashMap::new(), len_values: 0 }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { map: HashMap::with_capacity(capacity), len_values: 0 }
+    }
+}
+
+impl<K, V, S> MultiMap<K, V, S>
+where
+    S: BuildHasher
+{
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self { map: HashMap::with_hasher(hash_builder), len_values: 0 }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self { map: HashMap::with_capacity_and_hasher(capacity, hash_builder), len_values: 0 }
+    }
+}
+
+impl<K, V, S> MultiMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default
+{
+    /// Build a map grouping each value under the key `key` computes for it,
+    /// keeping the values of each group in iteration order.
+    pub fn group_by<I, F>(iter: I, mut key: F) -> Self
+    where
+        I: IntoIterator<Item = V>,
+        F: FnMut(&V) -> K
+    {
+        let mut map = Self::with_hasher(S::default());
+        for value in iter {
+            map.insert(key(&value), value);
+        }
+        map
+    }
+}
+
+impl<K, V, S> MultiMap<K, V, S> {
+    /// The number of distinct keys.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// The number of values over all keys.
+    pub fn len_values(&self) -> usize {
+        self.len_values
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.len_values = 0;
+    }
+
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    /// Iterate over each key with all of its values.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { inner: self.map.iter() }
+    }
+
+    /// Iterate over every key-value pair, a key appearing once per value.
+    pub fn iter_flat(&self) -> IterFlat<'_, K, V> {
+        IterFlat { inner: self.map.iter(), current: None }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.map.keys()
+    }
+}
+
+impl<K, V, S> MultiMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher
+{
+    /// Add `value` to those under `key`, after any already there.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.map.entry(key).or_insert_with(InlineVec::new).push(value);
+        self.len_values += 1;
+    }
+
+    /// The values under `key`, in insertion order; empty if there are none.
+    pub fn get_all<Q>(&self, key: &Q) -> &[V]
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized
+    {
+        self.map.get(key).map_or(&[], Values::as_slice)
+    }
+
+    pub fn get_all_mut<Q>(&mut self, key: &Q) -> &mut [V]
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized
+    {
+        match self.map.raw_entry_mut().from_key(key) {
+            RawEntryMut::Occupied(e) => e.into_mut().as_mut_slice(),
+            RawEntryMut::Vacant(_) => &mut []
+        }
+    }
+
+    /// The first value inserted under `key`.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized
+    {
+        self.get_all(key).first()
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Remove the first value under `key` equal to `value`, removing the key
+    /// too if that was its last value.
+    pub fn remove_one<Q>(&mut self, key: &Q, value: &V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: PartialEq
+    {
+        match self.map.raw_entry_mut().from_key(key) {
+            RawEntryMut::Occupied(mut e) => {
+                let index = e.get().as_slice().iter().position(|v| v == value)?;
+                let removed = e.get_mut().remove(index);
+                if e.get().is_empty() {
+                    e.remove();
+                }
+                self.len_values -= 1;
+                Some(removed)
+            },
+            RawEntryMut::Vacant(_) => None
+        }
+    }
+
+    /// Remove `key` and return all of its values, in insertion order.
+    pub fn remove_all<Q>(&mut self, key: &Q) -> Vec<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized
+    {
+        match self.map.remove(key) {
+            Some(values) => {
+                self.len_values -= values.len();
+                values.into_vec()
+            },
+            None => Vec::new()
+        }
+    }
+
+    /// Keep only the pairs for which `f` returns true, removing keys left
+    /// with no values.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &V) -> bool
+    {
+        // Restores the invariants if `f` panics partway through: some keys
+        // may have been emptied, and the count of values has to be retaken.
+        struct Recount<'a, K, V, S>(&'a mut MultiMap<K, V, S>);
+
+        impl<'a, K, V, S> Drop for Recount<'a, K, V, S> {
+            fn drop(&mut self) {
+                let multi = &mut *self.0;
+                multi.map.retain(|_, values| !values.is_empty());
+                multi.len_values = multi.map.values().map(|values| values.len()).sum();
+            }
+        }
+
+        let guard = Recount(self);
+        let mut kept = 0;
+        guard.0.map.retain(|key, values| {
+            values.retain(|value| f(key, value));
+            kept += values.len();
+            !values.is_empty()
+        });
+        guard.0.len_values = kept;
+        // every key was visited, so the invariants already hold
+        mem::forget(guard);
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    inner: crate::Iter<'a, K, Values<V>>
+}
+
+pub struct IterFlat<'a, K, V> {
+    inner: crate::Iter<'a, K, Values<V>>,
+    current: Option<(&'a K, slice::Iter<'a, V>)>
+}
+
+pub struct IntoIter<K, V> {
+    inner: crate::IntoIter<K, Values<V>>
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a [V]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, values)| (key, values.as_slice()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> Iterator for IterFlat<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, values)) = &mut self.current {
+                if let Some(value) = values.next() {
+                    return Some((*key, value));
+                }
+            }
+            let (key, values) = self.inner.next()?;
+            self.current = Some((key, values.as_slice().iter()));
+        }
+    }
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, Vec<V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, values)| (key, values.into_vec()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a MultiMap<K, V, S> {
+    type Item = (&'a K, &'a [V]);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K, V, S> IntoIterator for MultiMap<K, V, S> {
+    type Item = (K, Vec<V>);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { inner: self.map.into_iter() }
+    }
+}
+
+impl<K, V, S> Default for MultiMap<K, V, S>
+where
+    S: BuildHasher + Default
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> Clone for MultiMap<K, V, S>
+where
+    K: Clone,
+    V: Clone,
+    S: Clone
+{
+    fn clone(&self) -> Self {
+        Self { map: self.map.clone(), len_values: self.len_values }
+    }
+}
+
+impl<K, V, S> fmt::Debug for MultiMap<K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+// Values are compared in order, so maps built from the same pairs in a
+// different order may differ.
+impl<K, V, S> PartialEq for MultiMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: PartialEq,
+    S: BuildHasher
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self.len_values == other.len_values
+            && self.iter().all(|(key, values)| other.get_all(key) == values)
+    }
+}
+
+impl<K, V, S> Eq for MultiMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Eq,
+    S: BuildHasher
+{
+}
+
+impl<K, V, S> FromIterator<(K, V)> for MultiMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V, S> Extend<(K, V)> for MultiMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_get() {
+        let mut map = MultiMap::new();
+        map.insert("a", 1);
+        map.insert("b", 2);
+        map.insert("a", 3);
+        map.insert("a", 4);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.len_values(), 4);
+        assert_eq!(map.get_all("a"), [1, 3, 4]);
+        assert_eq!(map.get_all("b"), [2]);
+        assert_eq!(map.get_all("c"), [] as [i32; 0]);
+        assert_eq!(map.get("a"), Some(&1));
+
+        map.get_all_mut("a")[0] = 10;
+        assert_eq!(map.get_all("a"), [10, 3, 4]);
+    }
+
+    #[test]
+    fn remove() {
+        let mut map: MultiMap<u32, String> = (0..20).map(|i| (i % 3, i.to_string())).collect();
+        assert_eq!(map.len_values(), 20);
+
+        assert_eq!(map.remove_one(&0, &"3".to_string()), Some("3".to_string()));
+        assert_eq!(map.remove_one(&0, &"4".to_string()), None);
+        assert_eq!(map.get_all(&0), ["0", "6", "9", "12", "15", "18"]);
+        assert_eq!(map.len_values(), 19);
+
+        assert_eq!(map.remove_all(&1), ["1", "4", "7", "10", "13", "16", "19"]);
+        assert!(map.remove_all(&1).is_empty());
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.len_values(), 12);
+
+        // removing a key's last value removes the key
+        let mut map = MultiMap::new();
+        map.insert('x', 1);
+        assert_eq!(map.remove_one(&'x', &1), Some(1));
+        assert!(!map.contains_key(&'x'));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn flat_iteration() {
+        let map: MultiMap<u8, u32> = (0..100).map(|i| ((i % 7) as u8, i)).collect();
+        let mut pairs: Vec<_> = map.iter_flat().map(|(&k, &v)| (k, v)).collect();
+        pairs.sort_unstable_by_key(|&(_, v)| v);
+        assert_eq!(pairs, (0..100).map(|i| ((i % 7) as u8, i)).collect::<Vec<_>>());
+        assert_eq!(map.iter().map(|(_, values)| values.len()).sum::<usize>(), map.len_values());
+
+        let mut owned: Vec<_> = map.into_iter().collect();
+        owned.sort();
+        assert_eq!(owned[3], (3, (3..100).step_by(7).collect()));
+    }
+
+    #[test]
+    fn group_by() {
+        let words = ["apple", "avocado", "banana", "blueberry", "cherry", "apricot"];
+        let map: MultiMap<char, &str> = MultiMap::group_by(words.iter().copied(), |w| w.chars().next().unwrap());
+        assert_eq!(map.get_all(&'a'), ["apple", "avocado", "apricot"]);
+        assert_eq!(map.get_all(&'b'), ["banana", "blueberry"]);
+        assert_eq!(map.get_all(&'c'), ["cherry"]);
+        assert_eq!(map.len_values(), words.len());
+
+        let mut map = map;
+        map.retain(|_, w| w.len() > 5);
+        assert_eq!(map.get_all(&'a'), ["avocado", "apricot"]);
+        assert_eq!(map.get_all(&'c'), ["cherry"]);
+        assert_eq!(map.len_values(), 5);
+        assert_eq!(map, map.clone());
+    }
+
+    #[test]
+    fn retain_panics() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        // keys emptied before the predicate panics are removed, and the
+        // values left are counted
+        let mut map: MultiMap<u32, u32> = (0..60).map(|i| (i % 6, i)).collect();
+        let mut calls = 0;
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            map.retain(|_, _| {
+                calls += 1;
+                assert!(calls < 35, "predicate panicked");
+                false
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.len_values(), 26);
+        assert!(map.iter().all(|(_, values)| !values.is_empty()));
+        assert_eq!(map.iter().map(|(_, values)| values.len()).sum::<usize>(), map.len_values());
+        for (&k, values) in map.iter() {
+            assert!(map.contains_key(&k));
+            assert_eq!(values.last(), Some(&(k + 54)));
+        }
+    }
+}
@@ -0,0 +1,754 @@
+// persistent.rs
+// Immutable hash map whose versions share structure, built as a hash array
+// mapped trie (Bagwell's HAMT).
+//
+// Each level of the trie consumes BITS bits of a key's hash. A branch keeps a
+// bitmap of the slots in use and a dense vector of just those children, so a
+// sparse branch stays small. A child is either a single entry, a deeper
+// branch, or a bucket of entries whose hashes collide completely.
+//
+// Every node sits behind an Arc. Cloning a map copies one pointer. An update
+// copies only the branches on the path to the changed entry; the copies
+// point at the same children as the originals (Arc::make_mut does the copy
+// only when a node is actually shared).
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::slice;
+use std::sync::Arc;
+
+use crate::make_hash;
+
+// hash bits consumed per level
+const BITS: u32 = 5;
+const MASK: u64 = (1 << BITS) - 1;
+
+struct Leaf<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+struct Branch<K, V> {
+    bitmap: u32,
+    children: Vec<Child<K, V>>,
+}
+
+// Entries whose full 64-bit hashes are equal.
+struct Collision<K, V> {
+    hash: u64,
+    leaves: Vec<Arc<Leaf<K, V>>>,
+}
+
+enum Child<K, V> {
+    Leaf(Arc<Leaf<K, V>>),
+    Branch(Arc<Branch<K, V>>),
+    Collision(Arc<Collision<K, V>>),
+}
+
+// Nodes only hold Arcs, so copying one never needs to clone a key or value.
+impl<K, V> Clone for Child<K, V> {
+    fn clone(&self) -> Self {
+        match self {
+            Child::Leaf(leaf) => Child::Leaf(leaf.clone()),
+            Child::Branch(branch) => Child::Branch(branch.clone()),
+            Child::Collision(collision) => Child::Collision(collision.clone()),
+        }
+    }
+}
+
+impl<K, V> Clone for Branch<K, V> {
+    fn clone(&self) -> Self {
+        Self { bitmap: self.bitmap, children: self.children.clone() }
+    }
+}
+
+impl<K, V> Clone for Collision<K, V> {
+    fn clone(&self) -> Self {
+        Self { hash: self.hash, leaves: self.leaves.clone() }
+    }
+}
+
+impl<K, V> Child<K, V> {
+    fn ptr_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Child::Leaf(a), Child::Leaf(b)) => Arc::ptr_eq(a, b),
+            (Child::Branch(a), Child::Branch(b)) => Arc::ptr_eq(a, b),
+            (Child::Collision(a), Child::Collision(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    // every entry at or below this child
+    fn leaves<'a>(&'a self, out: &mut Vec<&'a Leaf<K, V>>) {
+        match self {
+            Child::Leaf(leaf) => out.push(leaf),
+            Child::Branch(branch) => {
+                for child in &branch.children {
+                    child.leaves(out);
+                }
+            },
+            Child::Collision(collision) => out.extend(collision.leaves.iter().map(|leaf| &**leaf)),
+        }
+    }
+}
+
+impl<K, V> Branch<K, V> {
+    const EMPTY: Branch<K, V> = Branch { bitmap: 0, children: Vec::new() };
+
+    // the bitmap bit for a hash at this level, and the index of its child
+    fn locate(&self, hash: u64, shift: u32) -> (u32, usize) {
+        let bit = 1 << ((hash >> shift) & MASK);
+        (bit, (self.bitmap & (bit - 1)).count_ones() as usize)
+    }
+
+    fn child(&self, bit: u32, index: usize) -> Option<&Child<K, V>> {
+        if self.bitmap & bit == 0 {
+            None
+        } else {
+            Some(&self.children[index])
+        }
+    }
+
+    // A branch holding two children with distinct hashes, splitting as deep
+    // as their hashes agree. Distinct hashes always differ by the last level.
+    fn pair(a: Child<K, V>, a_hash: u64, b: Child<K, V>, b_hash: u64, shift: u32) -> Self {
+        let a_slot = (a_hash >> shift) & MASK;
+        let b_slot = (b_hash >> shift) & MASK;
+        if a_slot == b_slot {
+            let inner = Self::pair(a, a_hash, b, b_hash, shift + BITS);
+            return Branch { bitmap: 1 << a_slot, children: vec![Child::Branch(Arc::new(inner))] };
+        }
+
+        let children = if a_slot < b_slot { vec![a, b] } else { vec![b, a] };
+        Branch { bitmap: (1 << a_slot) | (1 << b_slot), children }
+    }
+}
+
+impl<K: Eq, V> Branch<K, V> {
+    // Insert an entry below this branch, copying any shared nodes on the way
+    // down. Returns whether the key is new.
+    fn insert(&mut self, leaf: Leaf<K, V>, shift: u32) -> bool {
+        let hash = leaf.hash;
+        let (bit, index) = self.locate(hash, shift);
+        if self.bitmap & bit == 0 {
+            self.bitmap |= bit;
+            self.children.insert(index, Child::Leaf(Arc::new(leaf)));
+            return true;
+        }
+
+        let child = &mut self.children[index];
+        let replacement = match child {
+            Child::Branch(branch) => return Arc::make_mut(branch).insert(leaf, shift + BITS),
+            Child::Leaf(old) if old.hash == hash && old.key == leaf.key => {
+                *child = Child::Leaf(Arc::new(leaf));
+                return false;
+            },
+            Child::Leaf(old) if old.hash == hash => {
+                Child::Collision(Arc::new(Collision { hash, leaves: vec![old.clone(), Arc::new(leaf)] }))
+            },
+            Child::Collision(collision) if collision.hash == hash => {
+                let collision = Arc::make_mut(collision);
+                return match collision.leaves.iter().position(|old| old.key == leaf.key) {
+                    Some(i) => {
+                        collision.leaves[i] = Arc::new(leaf);
+                        false
+                    },
+                    None => {
+                        collision.leaves.push(Arc::new(leaf));
+                        true
+                    },
+                };
+            },
+            Child::Leaf(old) => {
+                let old_hash = old.hash;
+                let branch = Branch::pair(child.clone(), old_hash, Child::Leaf(Arc::new(leaf)), hash, shift + BITS);
+                Child::Branch(Arc::new(branch))
+            },
+            Child::Collision(collision) => {
+                let old_hash = collision.hash;
+                let branch = Branch::pair(child.clone(), old_hash, Child::Leaf(Arc::new(leaf)), hash, shift + BITS);
+                Child::Branch(Arc::new(branch))
+            },
+        };
+        *child = replacement;
+        true
+    }
+
+    // Remove a key known to be present below this branch, copying any shared
+    // nodes on the way down. A branch left holding a single entry or
+    // collision bucket is replaced by that child, keeping the trie shallow.
+    fn remove<Q>(&mut self, hash: u64, key: &Q, shift: u32)
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let (bit, index) = self.locate(hash, shift);
+        assert!(self.bitmap & bit != 0, "removed key is present");
+
+        let child = &mut self.children[index];
+        match child {
+            Child::Leaf(_) => {
+                self.bitmap &= !bit;
+                self.children.remove(index);
+            },
+            Child::Branch(branch) => {
+                let branch = Arc::make_mut(branch);
+                branch.remove(hash, key, shift + BITS);
+                if branch.children.len() == 1 && !matches!(branch.children[0], Child::Branch(_)) {
+                    *child = branch.children.pop().expect("checked length");
+                }
+            },
+            Child::Collision(collision) => {
+                let collision = Arc::make_mut(collision);
+                let i = collision
+                    .leaves
+                    .iter()
+                    .position(|leaf| leaf.key.borrow() == key)
+                    .expect("removed key is present");
+                collision.leaves.swap_remove(i);
+                if collision.leaves.len() == 1 {
+                    *child = Child::Leaf(collision.leaves.pop().expect("checked length"));
+                }
+            },
+        }
+    }
+}
+
+pub struct PersistentHashMap<K, V, S = RandomState> {
+    root: Arc<Branch<K, V>>,
+    len: usize,
+    hash_builder: S
+}
+
+impl<K, V> PersistentHashMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K, V, S> PersistentHashMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            root: Arc::new(Branch::EMPTY),
+            len: 0,
+            hash_builder
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Whether two maps are the same version, or one is an unmodified clone
+    /// of the other. Cheap, but maps built separately are never ptr_eq even
+    /// when they hold the same entries.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.root, &other.root)
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            stack: vec![self.root.children.iter()],
+            collision: [].iter(),
+            remaining: self.len,
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// The changes that turn `self` into `other`.
+    ///
+    /// Subtrees the two versions share are skipped without being visited,
+    /// so diffing a version against one derived from it by a few updates
+    /// is cheap however large the maps are. Both maps must use the same
+    /// hasher, as versions derived from a common ancestor do.
+    pub fn diff<'a>(&'a self, other: &'a Self) -> Diff<'a, K, V> {
+        let mut diff = Diff { pending: Vec::new(), ready: Vec::new() };
+        if !Arc::ptr_eq(&self.root, &other.root) {
+            diff.push_branches(&self.root, &other.root);
+        }
+        diff
+    }
+}
+
+impl<K, V, S> PersistentHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher
+{
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = make_hash(&self.hash_builder, key);
+        let mut branch = &*self.root;
+        let mut shift = 0;
+        loop {
+            let (bit, index) = branch.locate(hash, shift);
+            let leaf = match branch.child(bit, index)? {
+                Child::Branch(next) => {
+                    branch = next;
+                    shift += BITS;
+                    continue;
+                },
+                Child::Leaf(leaf) => leaf,
+                Child::Collision(collision) => collision.leaves.iter().find(|leaf| leaf.key.borrow() == key)?,
+            };
+            return if leaf.hash == hash && leaf.key.borrow() == key {
+                Some((&leaf.key, &leaf.value))
+            } else {
+                None
+            };
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_key_value(key).is_some()
+    }
+}
+
+impl<K, V, S> PersistentHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone
+{
+    /// A new version of the map with the key set to the value.
+    pub fn insert(&self, key: K, value: V) -> Self {
+        let mut map = self.clone();
+        map.insert_in_place(key, value);
+        map
+    }
+
+    // Update this version directly; nodes shared with other versions are
+    // copied before they are changed.
+    fn insert_in_place(&mut self, key: K, value: V) {
+        let hash = make_hash(&self.hash_builder, &key);
+        if Arc::make_mut(&mut self.root).insert(Leaf { hash, key, value }, 0) {
+            self.len += 1;
+        }
+    }
+
+    /// A new version of the map without the key.
+    ///
+    /// Removing a missing key returns a clone sharing the whole map.
+    pub fn remove<Q>(&self, key: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut map = self.clone();
+        // only start copying nodes once the key is known to be present
+        if self.contains_key(key) {
+            let hash = make_hash(&self.hash_builder, key);
+            Arc::make_mut(&mut map.root).remove(hash, key, 0);
+            map.len -= 1;
+        }
+        map
+    }
+}
+
+impl<K, V, S> Clone for PersistentHashMap<K, V, S>
+where
+    S: Clone
+{
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            len: self.len,
+            hash_builder: self.hash_builder.clone()
+        }
+    }
+}
+
+impl<K, V, S> Default for PersistentHashMap<K, V, S>
+where
+    S: Default
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> fmt::Debug for PersistentHashMap<K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, S> PartialEq for PersistentHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: PartialEq,
+    S: BuildHasher
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other)
+            || (self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v)))
+    }
+}
+
+impl<K, V, S> Eq for PersistentHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Eq,
+    S: BuildHasher
+{
+}
+
+impl<K, V, S> FromIterator<(K, V)> for PersistentHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone + Default
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V, S> Extend<(K, V)> for PersistentHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.insert_in_place(k, v);
+        }
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a PersistentHashMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    // the children still to visit in each branch on the path from the root
+    stack: Vec<slice::Iter<'a, Child<K, V>>>,
+    collision: slice::Iter<'a, Arc<Leaf<K, V>>>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(leaf) = self.collision.next() {
+                self.remaining -= 1;
+                return Some((&leaf.key, &leaf.value));
+            }
+            match self.stack.last_mut()?.next() {
+                None => {
+                    self.stack.pop();
+                },
+                Some(Child::Leaf(leaf)) => {
+                    self.remaining -= 1;
+                    return Some((&leaf.key, &leaf.value));
+                },
+                Some(Child::Branch(branch)) => self.stack.push(branch.children.iter()),
+                Some(Child::Collision(collision)) => self.collision = collision.leaves.iter(),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+/// One difference between two versions of a map.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DiffItem<'a, K, V> {
+    /// The key is only in the second map.
+    Added(&'a K, &'a V),
+    /// The key is only in the first map.
+    Removed(&'a K, &'a V),
+    /// The key is in both maps, with different values.
+    Changed { key: &'a K, old: &'a V, new: &'a V },
+}
+
+// corresponding children of two versions, either of which may be missing
+type ChildPair<'a, K, V> = (Option<&'a Child<K, V>>, Option<&'a Child<K, V>>);
+
+/// Iterator over the differences between two maps, in no particular order.
+pub struct Diff<'a, K, V> {
+    // pairs of children still to compare
+    pending: Vec<ChildPair<'a, K, V>>,
+    ready: Vec<DiffItem<'a, K, V>>,
+}
+
+impl<'a, K, V> Diff<'a, K, V> {
+    fn push_branches(&mut self, old: &'a Branch<K, V>, new: &'a Branch<K, V>) {
+        let mut bitmap = old.bitmap | new.bitmap;
+        while bitmap != 0 {
+            let bit = 1 << bitmap.trailing_zeros();
+            bitmap &= !bit;
+            let index = |branch: &'a Branch<K, V>| {
+                let index = (branch.bitmap & (bit - 1)).count_ones() as usize;
+                branch.child(bit, index)
+            };
+            self.pending.push((index(old), index(new)));
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Diff<'a, K, V>
+where
+    K: Eq,
+    V: PartialEq
+{
+    type Item = DiffItem<'a, K, V>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.ready.pop() {
+                return Some(item);
+            }
+
+            let (old, new) = self.pending.pop()?;
+            if let (Some(old), Some(new)) = (old, new) {
+                if old.ptr_eq(new) {
+                    continue;
+                }
+                if let (Child::Branch(old), Child::Branch(new)) = (old, new) {
+                    self.push_branches(old, new);
+                    continue;
+                }
+            }
+
+            // at least one side is an entry or collision bucket, so the
+            // subtrees are small enough to compare entry by entry
+            let mut old_leaves = Vec::new();
+            let mut new_leaves = Vec::new();
+            old.into_iter().for_each(|child| child.leaves(&mut old_leaves));
+            new.into_iter().for_each(|child| child.leaves(&mut new_leaves));
+
+            for old in &old_leaves {
+                match new_leaves.iter().find(|new| new.key == old.key) {
+                    Some(new) if std::ptr::eq(*old, *new) || old.value == new.value => {},
+                    Some(new) => self.ready.push(DiffItem::Changed { key: &new.key, old: &old.value, new: &new.value }),
+                    None => self.ready.push(DiffItem::Removed(&old.key, &old.value)),
+                }
+            }
+            for new in &new_leaves {
+                if !old_leaves.iter().any(|old| old.key == new.key) {
+                    self.ready.push(DiffItem::Added(&new.key, &new.value));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::{BuildHasherDefault, Hasher};
+
+    // hashes every key to the same few values, forcing deep paths and collisions
+    #[derive(Default)]
+    struct Clashing(u64);
+
+    impl Hasher for Clashing {
+        fn finish(&self) -> u64 {
+            // differ only in the top bits, or not at all
+            (self.0 % 3) << 61
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for &b in bytes {
+                self.0 = self.0.wrapping_mul(31).wrapping_add(b as u64);
+            }
+        }
+    }
+
+    type ClashingMap<K, V> = PersistentHashMap<K, V, BuildHasherDefault<Clashing>>;
+
+    fn sorted<'a, K: Ord, V>(items: impl Iterator<Item = DiffItem<'a, K, V>>) -> Vec<DiffItem<'a, K, V>> {
+        let mut items: Vec<_> = items.collect();
+        items.sort_by(|a, b| {
+            let key = |item: &DiffItem<'a, K, V>| match *item {
+                DiffItem::Added(k, _) | DiffItem::Removed(k, _) => k,
+                DiffItem::Changed { key, .. } => key,
+            };
+            key(a).cmp(key(b))
+        });
+        items
+    }
+
+    #[test]
+    fn versions_are_independent() {
+        let empty = PersistentHashMap::new();
+        let one = empty.insert("a", 1);
+        let two = one.insert("b", 2);
+        let changed = two.insert("a", 10);
+        let removed = changed.remove("b");
+
+        assert!(empty.is_empty());
+        assert_eq!(one.get("a"), Some(&1));
+        assert_eq!(one.get("b"), None);
+        assert_eq!(two.len(), 2);
+        assert_eq!(two.get("a"), Some(&1));
+        assert_eq!(changed.get("a"), Some(&10));
+        assert_eq!(changed.len(), 2);
+        assert_eq!(removed.len(), 1);
+        assert!(!removed.contains_key("b"));
+        assert!(two.contains_key("b"));
+
+        // removing a missing key shares everything
+        assert!(removed.remove("zzz").ptr_eq(&removed));
+    }
+
+    #[test]
+    fn matches_hashmap() {
+        let mut map = PersistentHashMap::new();
+        let mut oracle = std::collections::HashMap::new();
+        let mut versions = Vec::new();
+        let mut x = 1u64;
+        for i in 0..20_000 {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            let key = x % 5_000;
+            if x.is_multiple_of(4) {
+                map = map.remove(&key);
+                oracle.remove(&key);
+            } else {
+                map = map.insert(key, i);
+                oracle.insert(key, i);
+            }
+            if i % 2_000 == 0 {
+                versions.push((map.clone(), oracle.clone()));
+            }
+        }
+
+        // earlier versions were not disturbed by later updates
+        versions.push((map, oracle));
+        for (map, oracle) in &versions {
+            assert_eq!(map.len(), oracle.len());
+            assert_eq!(map.iter().count(), oracle.len());
+            for (k, v) in oracle {
+                assert_eq!(map.get(k), Some(v));
+            }
+        }
+    }
+
+    #[test]
+    fn collisions() {
+        let mut map = ClashingMap::default();
+        for i in 0..100 {
+            map = map.insert(i, i * 2);
+        }
+        assert_eq!(map.len(), 100);
+        for i in 0..100 {
+            assert_eq!(map.get(&i), Some(&(i * 2)));
+        }
+        assert_eq!(map.iter().count(), 100);
+
+        let mut smaller = map.clone();
+        for i in (0..100).step_by(2) {
+            smaller = smaller.remove(&i);
+        }
+        assert_eq!(smaller.len(), 50);
+        assert!(smaller.iter().all(|(k, _)| k % 2 == 1));
+        assert_eq!(map.len(), 100);
+
+        for i in (1..100).step_by(2) {
+            smaller = smaller.remove(&i);
+        }
+        assert!(smaller.is_empty());
+        assert_eq!(smaller.iter().count(), 0);
+    }
+
+    #[test]
+    fn structural_sharing() {
+        let base: PersistentHashMap<u32, u32> = (0..10_000).map(|i| (i, i)).collect();
+        let next = base.insert(5, 50);
+
+        // only the path to the changed entry was copied
+        let shared = base
+            .root
+            .children
+            .iter()
+            .zip(&next.root.children)
+            .filter(|(a, b)| a.ptr_eq(b))
+            .count();
+        assert_eq!(shared, base.root.children.len() - 1);
+        assert!(base.clone().ptr_eq(&base));
+    }
+
+    #[test]
+    fn diff() {
+        let base: PersistentHashMap<u32, u32> = (0..1000).map(|i| (i, i)).collect();
+        assert_eq!(base.diff(&base.clone()).count(), 0);
+
+        let next = base.insert(5, 50).insert(2000, 1).remove(&7).insert(9, 9);
+        assert_eq!(sorted(base.diff(&next)), vec![
+            DiffItem::Changed { key: &5, old: &5, new: &50 },
+            DiffItem::Removed(&7, &7),
+            DiffItem::Added(&2000, &1),
+        ]);
+        assert_eq!(sorted(next.diff(&base)), vec![
+            DiffItem::Changed { key: &5, old: &50, new: &5 },
+            DiffItem::Added(&7, &7),
+            DiffItem::Removed(&2000, &1),
+        ]);
+
+        // unrelated maps, where nothing is shared
+        let empty = PersistentHashMap::with_hasher(base.hasher().clone());
+        assert_eq!(base.diff(&empty).count(), 1000);
+        assert!(empty.diff(&base).all(|item| matches!(item, DiffItem::Added(..))));
+    }
+
+    #[test]
+    fn diff_collisions() {
+        let base: ClashingMap<u32, u32> = (0..50).map(|i| (i, i)).collect();
+        let next = base.insert(3, 30).remove(&4).insert(60, 60);
+        assert_eq!(sorted(base.diff(&next)), vec![
+            DiffItem::Changed { key: &3, old: &3, new: &30 },
+            DiffItem::Removed(&4, &4),
+            DiffItem::Added(&60, &60),
+        ]);
+    }
+}
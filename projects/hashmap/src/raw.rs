@@ -0,0 +1,814 @@
+// raw.rs
+// Open-addressing table storage with SwissTable-style control bytes.
+//
+// The table is a single allocation holding `buckets` slots for values followed
+// by `buckets + GROUP_WIDTH` control bytes. Each control byte describes the slot
+// at the same index: EMPTY, DELETED (a tombstone), or FULL, in which case the
+// low 7 bits hold the top 7 bits of the value's hash (h2). Lookups scan a group
+// of GROUP_WIDTH control bytes at once using portable u64 bit tricks, and only
+// compare keys for slots whose h2 matches.
+//
+// The first GROUP_WIDTH control bytes are mirrored after the end of the array
+// so a group can be loaded at any slot index without wrapping.
+
+use std::alloc::{self, Layout};
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr::{self, NonNull};
+
+// control byte for a slot that has never held a value
+const EMPTY: u8 = 0b1111_1111;
+// control byte for a slot whose value has been removed
+const DELETED: u8 = 0b1000_0000;
+
+// number of control bytes probed at a time
+pub(crate) const GROUP_WIDTH: usize = mem::size_of::<u64>();
+
+const LO_BITS: u64 = 0x0101_0101_0101_0101;
+const HI_BITS: u64 = 0x8080_8080_8080_8080;
+
+fn is_full(ctrl: u8) -> bool {
+    ctrl & 0x80 == 0
+}
+
+// the probe start position
+fn h1(hash: u64) -> usize {
+    hash as usize
+}
+
+// the 7 bits of hash stored in the control byte
+fn h2(hash: u64) -> u8 {
+    (hash >> (64 - 7)) as u8
+}
+
+/// A set of matching bytes within a group, one high bit per matching byte.
+#[derive(Clone, Copy)]
+pub(crate) struct BitMask(u64);
+
+impl BitMask {
+    fn any_bit_set(self) -> bool {
+        self.0 != 0
+    }
+
+    fn lowest_set_bit(self) -> Option<usize> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(self.trailing_zeros())
+        }
+    }
+
+    fn remove_lowest_bit(self) -> Self {
+        BitMask(self.0 & self.0.wrapping_sub(1))
+    }
+
+    // number of unmatched bytes at the start of the group
+    fn trailing_zeros(self) -> usize {
+        self.0.trailing_zeros() as usize / 8
+    }
+
+    // number of unmatched bytes at the end of the group
+    fn leading_zeros(self) -> usize {
+        self.0.leading_zeros() as usize / 8
+    }
+}
+
+impl Iterator for BitMask {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
+        let bit = self.lowest_set_bit()?;
+        *self = self.remove_lowest_bit();
+        Some(bit)
+    }
+}
+
+/// GROUP_WIDTH control bytes loaded as a single little-endian word.
+#[derive(Clone, Copy)]
+struct Group(u64);
+
+impl Group {
+    // SAFETY: ptr must be valid for reads of GROUP_WIDTH bytes
+    unsafe fn load(ptr: *const u8) -> Self {
+        Group(u64::from_le(ptr::read_unaligned(ptr as *const u64)))
+    }
+
+    // Bytes equal to `byte`. May report false positives for bytes adjacent to
+    // a true match, but only ever on FULL bytes, so callers must still compare
+    // keys (which they do anyway).
+    fn match_byte(self, byte: u8) -> BitMask {
+        let cmp = self.0 ^ (LO_BITS * byte as u64);
+        BitMask(cmp.wrapping_sub(LO_BITS) & !cmp & HI_BITS)
+    }
+
+    // EMPTY is the only control byte with both of its top two bits set
+    fn match_empty(self) -> BitMask {
+        BitMask(self.0 & (self.0 << 1) & HI_BITS)
+    }
+
+    fn match_empty_or_deleted(self) -> BitMask {
+        BitMask(self.0 & HI_BITS)
+    }
+
+    fn match_full(self) -> BitMask {
+        BitMask(!self.0 & HI_BITS)
+    }
+}
+
+// Triangular probing over groups; with a power-of-two number of buckets this
+// visits every group exactly once before repeating.
+struct ProbeSeq {
+    pos: usize,
+    stride: usize,
+}
+
+impl ProbeSeq {
+    fn move_next(&mut self, bucket_mask: usize) {
+        self.stride += GROUP_WIDTH;
+        self.pos += self.stride;
+        self.pos &= bucket_mask;
+    }
+}
+
+// the fraction of slots that may be filled before the table grows
+pub(crate) const DEFAULT_MAX_LOAD_FACTOR: f32 = 0.875;
+
+// Number of items a table with `buckets` slots may hold before it must grow.
+// At least one slot is always left EMPTY so that probing terminates.
+fn bucket_count_to_capacity(buckets: usize, max_load: f32) -> usize {
+    let capacity = (buckets as f64 * max_load as f64) as usize;
+    capacity.min(buckets.saturating_sub(1))
+}
+
+// Number of buckets required to hold `capacity` items, or None on overflow.
+fn capacity_to_bucket_count(capacity: usize, max_load: f32) -> Option<usize> {
+    let adjusted = (capacity as f64 / max_load as f64).ceil();
+    if adjusted >= (usize::MAX / 2) as f64 {
+        return None;
+    }
+
+    let buckets = (adjusted as usize).max(GROUP_WIDTH).checked_next_power_of_two()?;
+    // guard against the float round trip losing a slot
+    if bucket_count_to_capacity(buckets, max_load) < capacity {
+        buckets.checked_mul(2)
+    } else {
+        Some(buckets)
+    }
+}
+
+/// The error returned when reserving capacity fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TryReserveError {
+    /// The requested capacity exceeds what the table can address.
+    CapacityOverflow,
+    /// The allocator could not provide the memory.
+    AllocError { layout: Layout },
+}
+
+impl fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryReserveError::CapacityOverflow => write!(f, "capacity overflow"),
+            TryReserveError::AllocError { layout } => {
+                write!(f, "failed to allocate {} bytes", layout.size())
+            }
+        }
+    }
+}
+
+impl Error for TryReserveError {}
+
+// Whether a failed allocation is reported to the caller or is fatal.
+#[derive(Clone, Copy)]
+enum Fallibility {
+    Fallible,
+    Infallible,
+}
+
+impl Fallibility {
+    fn capacity_overflow(self) -> TryReserveError {
+        match self {
+            Fallibility::Fallible => TryReserveError::CapacityOverflow,
+            Fallibility::Infallible => panic!("capacity overflow"),
+        }
+    }
+
+    fn alloc_err(self, layout: Layout) -> TryReserveError {
+        match self {
+            Fallibility::Fallible => TryReserveError::AllocError { layout },
+            Fallibility::Infallible => alloc::handle_alloc_error(layout),
+        }
+    }
+}
+
+pub(crate) struct RawTable<T> {
+    // start of the control bytes, within the same allocation as `data`
+    ctrl: NonNull<u8>,
+    // start of the allocation; slot i lives at data + i
+    data: NonNull<T>,
+    // zero (unallocated) or a power of two no smaller than GROUP_WIDTH
+    buckets: usize,
+    items: usize,
+    // number of EMPTY slots that may still be filled before growing
+    growth_left: usize,
+    max_load: f32,
+    marker: PhantomData<T>,
+}
+
+// SAFETY: RawTable owns its values just like a Vec<T> would
+unsafe impl<T: Send> Send for RawTable<T> {}
+unsafe impl<T: Sync> Sync for RawTable<T> {}
+
+impl<T> RawTable<T> {
+    pub(crate) const fn new() -> Self {
+        Self::with_max_load_factor(DEFAULT_MAX_LOAD_FACTOR)
+    }
+
+    /// An unallocated table that grows once `max_load` of its slots are used.
+    pub(crate) const fn with_max_load_factor(max_load: f32) -> Self {
+        Self {
+            ctrl: NonNull::dangling(),
+            data: NonNull::dangling(),
+            buckets: 0,
+            items: 0,
+            growth_left: 0,
+            max_load,
+            marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn with_capacity(capacity: usize, max_load: f32) -> Self {
+        let mut table = Self::with_max_load_factor(max_load);
+        if capacity != 0 {
+            let buckets = capacity_to_bucket_count(capacity, max_load).expect("capacity overflow");
+            table = unwrap_infallible(Self::allocate(buckets, max_load, Fallibility::Infallible));
+        }
+        table
+    }
+
+    pub(crate) fn max_load_factor(&self) -> f32 {
+        self.max_load
+    }
+
+    // layout of the allocation for `buckets` slots, and the offset of the control bytes
+    fn layout(buckets: usize) -> Option<(Layout, usize)> {
+        let data = Layout::array::<T>(buckets).ok()?;
+        let ctrl = Layout::array::<u8>(buckets + GROUP_WIDTH).ok()?;
+        let (layout, ctrl_offset) = data.extend(ctrl).ok()?;
+        Some((layout, ctrl_offset))
+    }
+
+    fn allocate(buckets: usize, max_load: f32, fallibility: Fallibility) -> Result<Self, TryReserveError> {
+        debug_assert!(buckets.is_power_of_two() && buckets >= GROUP_WIDTH);
+
+        let (layout, ctrl_offset) = match Self::layout(buckets) {
+            Some(layout) => layout,
+            None => return Err(fallibility.capacity_overflow()),
+        };
+
+        // SAFETY: the layout always includes the control bytes, so is never zero-sized
+        let ptr = unsafe { alloc::alloc(layout) };
+        let ptr = match NonNull::new(ptr) {
+            Some(ptr) => ptr,
+            None => return Err(fallibility.alloc_err(layout)),
+        };
+
+        // SAFETY: ctrl_offset + buckets + GROUP_WIDTH bytes lie within the allocation
+        let ctrl = unsafe {
+            let ctrl = ptr.as_ptr().add(ctrl_offset);
+            ptr::write_bytes(ctrl, EMPTY, buckets + GROUP_WIDTH);
+            NonNull::new_unchecked(ctrl)
+        };
+
+        Ok(Self {
+            ctrl,
+            data: ptr.cast(),
+            buckets,
+            items: 0,
+            growth_left: bucket_count_to_capacity(buckets, max_load),
+            max_load,
+            marker: PhantomData,
+        })
+    }
+
+    // Release the allocation without dropping any values.
+    fn free_buckets(&mut self) {
+        if self.buckets == 0 {
+            return;
+        }
+        let (layout, _) = Self::layout(self.buckets).expect("layout was valid at allocation");
+        // SAFETY: data is the start of an allocation made with this layout
+        unsafe { alloc::dealloc(self.data.as_ptr() as *mut u8, layout) };
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.items
+    }
+
+    pub(crate) fn buckets(&self) -> usize {
+        self.buckets
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.items + self.growth_left
+    }
+
+    pub(crate) fn growth_left(&self) -> usize {
+        self.growth_left
+    }
+
+    pub(crate) fn is_full(&self, index: usize) -> bool {
+        assert!(index < self.buckets);
+        // SAFETY: index < buckets, so the table is allocated
+        is_full(unsafe { *self.ctrl(index) })
+    }
+
+    fn bucket_mask(&self) -> usize {
+        self.buckets.wrapping_sub(1)
+    }
+
+    fn probe_seq(&self, hash: u64) -> ProbeSeq {
+        ProbeSeq {
+            pos: h1(hash) & self.bucket_mask(),
+            stride: 0,
+        }
+    }
+
+    // SAFETY: the table must be allocated and index < buckets + GROUP_WIDTH
+    unsafe fn ctrl(&self, index: usize) -> *mut u8 {
+        self.ctrl.as_ptr().add(index)
+    }
+
+    // Set a control byte, along with its mirror if it is one of the first GROUP_WIDTH.
+    //
+    // SAFETY: the table must be allocated and index < buckets
+    unsafe fn set_ctrl(&mut self, index: usize, ctrl: u8) {
+        let mirror = (index.wrapping_sub(GROUP_WIDTH) & self.bucket_mask()) + GROUP_WIDTH;
+        *self.ctrl(index) = ctrl;
+        *self.ctrl(mirror) = ctrl;
+    }
+
+    // SAFETY: index < buckets
+    pub(crate) unsafe fn bucket(&self, index: usize) -> *mut T {
+        debug_assert!(index < self.buckets);
+        self.data.as_ptr().add(index)
+    }
+
+    // SAFETY: index must refer to a FULL slot
+    pub(crate) unsafe fn get(&self, index: usize) -> &T {
+        debug_assert!(is_full(*self.ctrl(index)));
+        &*self.bucket(index)
+    }
+
+    // SAFETY: index must refer to a FULL slot
+    pub(crate) unsafe fn get_mut(&mut self, index: usize) -> &mut T {
+        debug_assert!(is_full(*self.ctrl(index)));
+        &mut *self.bucket(index)
+    }
+
+    /// Find the slot holding a value with the given hash for which `eq` holds.
+    pub(crate) fn find(&self, hash: u64, mut eq: impl FnMut(&T) -> bool) -> Option<usize> {
+        if self.buckets == 0 {
+            return None;
+        }
+
+        let h2 = h2(hash);
+        let mut probe = self.probe_seq(hash);
+        loop {
+            // SAFETY: pos < buckets, and the control bytes extend GROUP_WIDTH past buckets
+            let group = unsafe { Group::load(self.ctrl(probe.pos)) };
+            for bit in group.match_byte(h2) {
+                let index = (probe.pos + bit) & self.bucket_mask();
+                // SAFETY: match_byte only reports FULL slots
+                if eq(unsafe { self.get(index) }) {
+                    return Some(index);
+                }
+            }
+            // an EMPTY slot means the value would have been placed here had it existed
+            if group.match_empty().any_bit_set() {
+                return None;
+            }
+            probe.move_next(self.bucket_mask());
+        }
+    }
+
+    // Find the first EMPTY or DELETED slot along the probe sequence for `hash`.
+    //
+    // SAFETY: the table must be allocated
+    unsafe fn find_insert_slot(&self, hash: u64) -> usize {
+        let mut probe = self.probe_seq(hash);
+        loop {
+            let group = Group::load(self.ctrl(probe.pos));
+            if let Some(bit) = group.match_empty_or_deleted().lowest_set_bit() {
+                return (probe.pos + bit) & self.bucket_mask();
+            }
+            probe.move_next(self.bucket_mask());
+        }
+    }
+
+    /// Insert a value, growing the table if required, and return its slot.
+    ///
+    /// The caller must have checked that no equal value is already present.
+    pub(crate) fn insert(&mut self, hash: u64, value: T, hasher: impl Fn(&T) -> u64) -> usize {
+        if self.buckets == 0 {
+            self.reserve(1, &hasher);
+        }
+
+        // SAFETY: the table is allocated
+        unsafe {
+            let mut index = self.find_insert_slot(hash);
+            // reusing a tombstone does not consume any growth
+            if self.growth_left == 0 && *self.ctrl(index) == EMPTY {
+                self.reserve(1, &hasher);
+                index = self.find_insert_slot(hash);
+            }
+            self.write_slot(index, hash, value);
+            index
+        }
+    }
+
+    /// Insert a value without growing; the caller must have reserved room.
+    pub(crate) fn insert_no_grow(&mut self, hash: u64, value: T) -> usize {
+        assert!(self.growth_left > 0, "insert_no_grow without reserved capacity");
+        // SAFETY: growth_left > 0 implies the table is allocated
+        unsafe {
+            let index = self.find_insert_slot(hash);
+            self.write_slot(index, hash, value);
+            index
+        }
+    }
+
+    // SAFETY: index must be an EMPTY or DELETED slot, and if EMPTY, growth_left > 0
+    unsafe fn write_slot(&mut self, index: usize, hash: u64, value: T) {
+        if *self.ctrl(index) == EMPTY {
+            self.growth_left -= 1;
+        }
+        self.set_ctrl(index, h2(hash));
+        self.bucket(index).write(value);
+        self.items += 1;
+    }
+
+    /// Remove and return the value in a FULL slot.
+    ///
+    /// # Safety
+    /// index must refer to a FULL slot
+    pub(crate) unsafe fn remove(&mut self, index: usize) -> T {
+        self.erase_ctrl(index);
+        self.bucket(index).read()
+    }
+
+    // Mark a FULL slot as free. If some probe window of GROUP_WIDTH bytes could
+    // have seen this slot without also seeing an EMPTY one, lookups may have
+    // probed past it, so it must become a tombstone rather than EMPTY.
+    unsafe fn erase_ctrl(&mut self, index: usize) {
+        debug_assert!(is_full(*self.ctrl(index)));
+
+        let index_before = index.wrapping_sub(GROUP_WIDTH) & self.bucket_mask();
+        let empty_before = Group::load(self.ctrl(index_before)).match_empty();
+        let empty_after = Group::load(self.ctrl(index)).match_empty();
+
+        let ctrl = if empty_before.leading_zeros() + empty_after.trailing_zeros() >= GROUP_WIDTH {
+            DELETED
+        } else {
+            self.growth_left += 1;
+            EMPTY
+        };
+        self.set_ctrl(index, ctrl);
+        self.items -= 1;
+    }
+
+    /// Ensure room for `additional` more values without further growth.
+    pub(crate) fn reserve(&mut self, additional: usize, hasher: impl Fn(&T) -> u64) {
+        if additional > self.growth_left {
+            unwrap_infallible(self.reserve_rehash(additional, hasher, Fallibility::Infallible));
+        }
+    }
+
+    /// As reserve(), but report failure rather than panicking or aborting.
+    pub(crate) fn try_reserve(
+        &mut self,
+        additional: usize,
+        hasher: impl Fn(&T) -> u64,
+    ) -> Result<(), TryReserveError> {
+        if additional > self.growth_left {
+            self.reserve_rehash(additional, hasher, Fallibility::Fallible)
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn reserve_rehash(
+        &mut self,
+        additional: usize,
+        hasher: impl Fn(&T) -> u64,
+        fallibility: Fallibility,
+    ) -> Result<(), TryReserveError> {
+        let new_items = match self.items.checked_add(additional) {
+            Some(new_items) => new_items,
+            None => return Err(fallibility.capacity_overflow()),
+        };
+        let capacity = self.grown_capacity(new_items);
+        self.resize(capacity, hasher, fallibility)
+    }
+
+    // The capacity to rebuild the table with once it must hold `new_items`.
+    fn grown_capacity(&self, new_items: usize) -> usize {
+        let full_capacity = bucket_count_to_capacity(self.buckets, self.max_load);
+
+        // if the table is mostly tombstones, rebuilding at the same size is enough
+        if new_items <= full_capacity / 2 {
+            full_capacity
+        } else {
+            new_items.max(full_capacity + 1)
+        }
+    }
+
+    /// The bucket count and allocation size the table will grow to once its
+    /// remaining growth is used up, assuming nothing is removed meanwhile.
+    pub(crate) fn next_growth(&self) -> Option<(usize, usize)> {
+        let new_items = self.items.checked_add(self.growth_left)?.checked_add(1)?;
+        let buckets = capacity_to_bucket_count(self.grown_capacity(new_items), self.max_load)?;
+        let (layout, _) = Self::layout(buckets)?;
+        Some((buckets, layout.size()))
+    }
+
+    /// The number of DELETED slots.
+    pub(crate) fn tombstones(&self) -> usize {
+        bucket_count_to_capacity(self.buckets, self.max_load) - self.items - self.growth_left
+    }
+
+    /// The number of groups probed before reaching the one holding a slot,
+    /// for a value with the given hash; 0 if it sits in its home group.
+    pub(crate) fn probe_length(&self, hash: u64, index: usize) -> usize {
+        assert!(index < self.buckets);
+        let mut probe = self.probe_seq(hash);
+        let mut length = 0;
+        // the probe sequence visits every group, so this terminates
+        while index.wrapping_sub(probe.pos) & self.bucket_mask() >= GROUP_WIDTH {
+            probe.move_next(self.bucket_mask());
+            length += 1;
+        }
+        length
+    }
+
+    /// The probe length of the slot a new value with the given hash would take.
+    pub(crate) fn insert_probe_length(&self, hash: u64) -> usize {
+        if self.buckets == 0 {
+            return 0;
+        }
+        // SAFETY: the table is allocated
+        let index = unsafe { self.find_insert_slot(hash) };
+        self.probe_length(hash, index)
+    }
+
+    /// Shrink the allocation as far as possible while holding at least
+    /// `min_capacity` items.
+    pub(crate) fn shrink_to(&mut self, min_capacity: usize, hasher: impl Fn(&T) -> u64) {
+        let capacity = self.items.max(min_capacity);
+        if capacity == 0 {
+            // nothing to move; just release the allocation
+            *self = Self::with_max_load_factor(self.max_load);
+            return;
+        }
+
+        let buckets = capacity_to_bucket_count(capacity, self.max_load);
+        if buckets.is_some_and(|buckets| buckets < self.buckets) {
+            unwrap_infallible(self.resize(capacity, hasher, Fallibility::Infallible));
+        }
+    }
+
+    // Move every value into a new allocation sized for `capacity` items.
+    fn resize(
+        &mut self,
+        capacity: usize,
+        hasher: impl Fn(&T) -> u64,
+        fallibility: Fallibility,
+    ) -> Result<(), TryReserveError> {
+        debug_assert!(self.items <= capacity);
+
+        let buckets = match capacity_to_bucket_count(capacity, self.max_load) {
+            Some(buckets) => buckets,
+            None => return Err(fallibility.capacity_overflow()),
+        };
+        let new_table = Self::allocate(buckets, self.max_load, fallibility)?;
+
+        // If the hasher panics part way, the new table holds only bitwise
+        // copies of values still owned by self, so it must free its memory
+        // without dropping them.
+        struct Guard<T>(mem::ManuallyDrop<RawTable<T>>);
+        impl<T> Drop for Guard<T> {
+            fn drop(&mut self) {
+                self.0.free_buckets();
+            }
+        }
+        let mut guard = Guard(mem::ManuallyDrop::new(new_table));
+
+        for index in self.iter() {
+            // SAFETY: index comes from iterating FULL slots of self, and the new
+            // table was sized to hold every item
+            unsafe {
+                let item = self.bucket(index);
+                let hash = hasher(&*item);
+                let new_index = guard.0.find_insert_slot(hash);
+                guard.0.set_ctrl(new_index, h2(hash));
+                ptr::copy_nonoverlapping(item, guard.0.bucket(new_index), 1);
+            }
+        }
+        guard.0.growth_left -= self.items;
+        guard.0.items = self.items;
+
+        // the values now live in the new table; the guard releases the old memory
+        mem::swap(self, &mut *guard.0);
+        Ok(())
+    }
+
+    /// Drop every value, keeping the allocation.
+    pub(crate) fn clear(&mut self) {
+        self.drop_elements();
+        self.clear_no_drop();
+    }
+
+    // Mark every slot EMPTY without dropping the values in them.
+    fn clear_no_drop(&mut self) {
+        if self.buckets != 0 {
+            // SAFETY: the control bytes span buckets + GROUP_WIDTH bytes
+            unsafe { ptr::write_bytes(self.ctrl.as_ptr(), EMPTY, self.buckets + GROUP_WIDTH) };
+        }
+        self.items = 0;
+        self.growth_left = bucket_count_to_capacity(self.buckets, self.max_load);
+    }
+
+    fn drop_elements(&mut self) {
+        if mem::needs_drop::<T>() {
+            for index in self.iter() {
+                // SAFETY: index comes from iterating FULL slots
+                unsafe { self.bucket(index).drop_in_place() };
+            }
+        }
+    }
+
+    /// Iterate the indices of all FULL slots.
+    ///
+    /// The iterator reads the control bytes through a raw pointer, so the
+    /// caller must keep the table alive and unchanged while using it.
+    pub(crate) fn iter(&self) -> RawIter {
+        RawIter {
+            ctrl: self.ctrl.as_ptr(),
+            current: BitMask(0),
+            group: 0,
+            next_group: 0,
+            buckets: self.buckets,
+            items: self.items,
+        }
+    }
+}
+
+impl<T: Clone> Clone for RawTable<T> {
+    fn clone(&self) -> Self {
+        if self.buckets == 0 {
+            return Self::with_max_load_factor(self.max_load);
+        }
+
+        // Clone each value into the same slot of an identically sized table.
+        // Control bytes are set as each clone lands, so if a clone panics the
+        // partial table drops exactly the values cloned so far.
+        let mut new = unwrap_infallible(Self::allocate(self.buckets, self.max_load, Fallibility::Infallible));
+        for index in self.iter() {
+            // SAFETY: index is a FULL slot of self, and both tables have the same number of buckets
+            unsafe {
+                new.bucket(index).write(self.get(index).clone());
+                new.set_ctrl(index, *self.ctrl(index));
+            }
+            new.items += 1;
+        }
+
+        // copy the tombstones across too, so growth_left carries over as is
+        // SAFETY: both tables have buckets + GROUP_WIDTH control bytes
+        unsafe {
+            ptr::copy_nonoverlapping(self.ctrl.as_ptr(), new.ctrl.as_ptr(), self.buckets + GROUP_WIDTH);
+        }
+        new.growth_left = self.growth_left;
+        new
+    }
+}
+
+// Infallible allocation never returns an error; it panics or aborts instead.
+fn unwrap_infallible<T>(result: Result<T, TryReserveError>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(_) => unreachable!("infallible allocation returned an error"),
+    }
+}
+
+impl<T> Drop for RawTable<T> {
+    fn drop(&mut self) {
+        self.drop_elements();
+        self.free_buckets();
+    }
+}
+
+/// Iterator over the indices of FULL slots in a RawTable.
+pub(crate) struct RawIter {
+    ctrl: *const u8,
+    current: BitMask,
+    group: usize,
+    next_group: usize,
+    buckets: usize,
+    items: usize,
+}
+
+impl Iterator for RawIter {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
+        if self.items == 0 {
+            return None;
+        }
+        loop {
+            if let Some(bit) = self.current.lowest_set_bit() {
+                self.current = self.current.remove_lowest_bit();
+                self.items -= 1;
+                return Some(self.group + bit);
+            }
+
+            debug_assert!(self.next_group < self.buckets);
+            // SAFETY: items > 0 so the table is allocated, and groups start at
+            // multiples of GROUP_WIDTH below buckets
+            let group = unsafe { Group::load(self.ctrl.add(self.next_group)) };
+            self.current = group.match_full();
+            self.group = self.next_group;
+            self.next_group += GROUP_WIDTH;
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.items, Some(self.items))
+    }
+}
+
+impl ExactSizeIterator for RawIter {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_matching() {
+        let bytes = [EMPTY, 0x12, DELETED, 0x13, 0x12, EMPTY, 0x00, 0x7f];
+        let group = unsafe { Group::load(bytes.as_ptr()) };
+
+        assert_eq!(group.match_empty().collect::<Vec<_>>(), vec![0, 5]);
+        assert_eq!(group.match_empty_or_deleted().collect::<Vec<_>>(), vec![0, 2, 5]);
+        assert_eq!(group.match_full().collect::<Vec<_>>(), vec![1, 3, 4, 6, 7]);
+
+        // false positives are permitted, but every true match must be reported
+        let matches: Vec<_> = group.match_byte(0x12).collect();
+        assert!(matches.contains(&1) && matches.contains(&4));
+        assert!(matches.iter().all(|&i| is_full(bytes[i])));
+    }
+
+    #[test]
+    fn capacity_rounding() {
+        let max_load = DEFAULT_MAX_LOAD_FACTOR;
+        assert_eq!(capacity_to_bucket_count(1, max_load), Some(8));
+        assert_eq!(capacity_to_bucket_count(7, max_load), Some(8));
+        assert_eq!(capacity_to_bucket_count(8, max_load), Some(16));
+        assert_eq!(capacity_to_bucket_count(usize::MAX, max_load), None);
+        assert_eq!(bucket_count_to_capacity(8, max_load), 7);
+        assert_eq!(bucket_count_to_capacity(0, max_load), 0);
+
+        // a slot is always left free, however high the load factor
+        assert_eq!(bucket_count_to_capacity(8, 0.999), 7);
+        assert_eq!(capacity_to_bucket_count(8, 0.5), Some(16));
+        assert_eq!(capacity_to_bucket_count(9, 0.5), Some(32));
+        for capacity in 1..1000 {
+            for &max_load in &[0.1, 0.5, 0.75, 0.875, 0.99] {
+                let buckets = capacity_to_bucket_count(capacity, max_load).unwrap();
+                assert!(bucket_count_to_capacity(buckets, max_load) >= capacity);
+            }
+        }
+    }
+
+    #[test]
+    fn insert_remove_churn() {
+        let hasher = |&x: &u64| x.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let mut table = RawTable::new();
+        for i in 0..1000u64 {
+            table.insert(hasher(&i), i, hasher);
+        }
+
+        // churn through many removals and insertions, leaving tombstones behind
+        for i in 0..1000u64 {
+            let index = table.find(hasher(&i), |&x| x == i).unwrap();
+            assert_eq!(unsafe { table.remove(index) }, i);
+            table.insert(hasher(&(i + 1000)), i + 1000, hasher);
+        }
+
+        assert_eq!(table.len(), 1000);
+        for i in 1000..2000u64 {
+            assert!(table.find(hasher(&i), |&x| x == i).is_some());
+        }
+    }
+}
@@ -0,0 +1,348 @@
+// raw_entry.rs
+// Lookup and insertion by precomputed hash.
+//
+// The raw entry API lets callers hash a key once and reuse that hash for many
+// probes, and match entries with an arbitrary closure rather than through
+// Borrow, so keys can be found without first building an owned K.
+
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+
+use crate::table::Table;
+use crate::{make_hash, make_hasher, HashMap};
+
+pub struct RawEntryBuilder<'a, K, V, S> {
+    map: &'a HashMap<K, V, S>
+}
+
+pub struct RawEntryBuilderMut<'a, K, V, S> {
+    map: &'a mut HashMap<K, V, S>
+}
+
+pub enum RawEntryMut<'a, K, V, S> {
+    Occupied(RawOccupiedEntryMut<'a, K, V>),
+    Vacant(RawVacantEntryMut<'a, K, V, S>)
+}
+
+pub struct RawOccupiedEntryMut<'a, K, V> {
+    table: &'a mut Table<(K, V)>,
+    index: usize
+}
+
+pub struct RawVacantEntryMut<'a, K, V, S> {
+    map: &'a mut HashMap<K, V, S>
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Immutable lookups by precomputed hash or custom equality.
+    pub fn raw_entry(&self) -> RawEntryBuilder<'_, K, V, S> {
+        RawEntryBuilder { map: self }
+    }
+
+    /// Entry-style access by precomputed hash or custom equality.
+    pub fn raw_entry_mut(&mut self) -> RawEntryBuilderMut<'_, K, V, S> {
+        RawEntryBuilderMut { map: self }
+    }
+}
+
+impl<'a, K, V, S> RawEntryBuilder<'a, K, V, S> 
+where
+    S: BuildHasher
+{
+    pub fn from_key<Q>(self, key: &Q) -> Option<(&'a K, &'a V)> 
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = make_hash(&self.map.hash_builder, key);
+        self.from_key_hashed_nocheck(hash, key)
+    }
+
+    /// Look up `key` using a hash the caller has already computed with this map's hasher.
+    pub fn from_key_hashed_nocheck<Q>(self, hash: u64, key: &Q) -> Option<(&'a K, &'a V)> 
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.from_hash(hash, |k| k.borrow() == key)
+    }
+
+    /// Look up the entry with the given hash for which `is_match` returns true.
+    pub fn from_hash<F>(self, hash: u64, mut is_match: F) -> Option<(&'a K, &'a V)> 
+    where
+        F: FnMut(&K) -> bool
+    {
+        let table = &self.map.table;
+        let index = table.find(hash, |(k, _)| is_match(k))?;
+        // SAFETY: index was just returned by find
+        let (k, v) = unsafe { table.get(index) };
+        Some((k, v))
+    }
+}
+
+impl<'a, K, V, S> RawEntryBuilderMut<'a, K, V, S> 
+where
+    S: BuildHasher
+{
+    pub fn from_key<Q>(self, key: &Q) -> RawEntryMut<'a, K, V, S> 
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = make_hash(&self.map.hash_builder, key);
+        self.from_key_hashed_nocheck(hash, key)
+    }
+
+    /// Find `key` using a hash the caller has already computed with this map's hasher.
+    pub fn from_key_hashed_nocheck<Q>(self, hash: u64, key: &Q) -> RawEntryMut<'a, K, V, S> 
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.from_hash(hash, |k| k.borrow() == key)
+    }
+
+    /// Find the entry with the given hash for which `is_match` returns true.
+    pub fn from_hash<F>(self, hash: u64, mut is_match: F) -> RawEntryMut<'a, K, V, S> 
+    where
+        F: FnMut(&K) -> bool
+    {
+        let map = self.map;
+        match map.table.find(hash, |(k, _)| is_match(k)) {
+            Some(index) => RawEntryMut::Occupied(RawOccupiedEntryMut {
+                table: &mut map.table,
+                index
+            }),
+            None => RawEntryMut::Vacant(RawVacantEntryMut { map }),
+        }
+    }
+}
+
+impl<'a, K, V, S> RawEntryMut<'a, K, V, S> {
+    pub fn or_insert(self, default_key: K, default_value: V) -> (&'a mut K, &'a mut V) 
+    where
+        K: Hash,
+        S: BuildHasher
+    {
+        match self {
+            RawEntryMut::Occupied(e) => e.into_key_value(),
+            RawEntryMut::Vacant(e) => e.insert(default_key, default_value),
+        }
+    }
+
+    pub fn or_insert_with<F>(self, maker: F) -> (&'a mut K, &'a mut V) 
+    where
+        F: FnOnce() -> (K, V),
+        K: Hash,
+        S: BuildHasher
+    {
+        match self {
+            RawEntryMut::Occupied(e) => e.into_key_value(),
+            RawEntryMut::Vacant(e) => {
+                let (k, v) = maker();
+                e.insert(k, v)
+            },
+        }
+    }
+
+    /// Modify the key and value in place if the entry is occupied.
+    pub fn and_modify<F>(self, f: F) -> Self 
+    where
+        F: FnOnce(&mut K, &mut V)
+    {
+        match self {
+            RawEntryMut::Occupied(mut e) => {
+                let (k, v) = e.get_key_value_mut();
+                f(k, v);
+                RawEntryMut::Occupied(e)
+            },
+            RawEntryMut::Vacant(e) => RawEntryMut::Vacant(e),
+        }
+    }
+}
+
+impl<'a, K, V> RawOccupiedEntryMut<'a, K, V> {
+    fn pair(&self) -> &(K, V) {
+        // SAFETY: the entry was found in the table and the table has not been modified since
+        unsafe { self.table.get(self.index) }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.pair().0
+    }
+
+    /// Mutable access to the key; the caller must not change its hash or equality.
+    pub fn key_mut(&mut self) -> &mut K {
+        self.get_key_value_mut().0
+    }
+
+    pub fn into_key(self) -> &'a mut K {
+        self.into_key_value().0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.pair().1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        self.get_key_value_mut().1
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        self.into_key_value().1
+    }
+
+    pub fn get_key_value(&self) -> (&K, &V) {
+        let (k, v) = self.pair();
+        (k, v)
+    }
+
+    pub fn get_key_value_mut(&mut self) -> (&mut K, &mut V) {
+        // SAFETY: as for pair()
+        let (k, v) = unsafe { self.table.get_mut(self.index) };
+        (k, v)
+    }
+
+    pub fn into_key_value(self) -> (&'a mut K, &'a mut V) {
+        // SAFETY: as for pair(), and the table stays borrowed for 'a
+        let (k, v) = unsafe { self.table.get_mut(self.index) };
+        (k, v)
+    }
+
+    /// Replace the value, returning the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    /// Replace the key, returning the old one; the new key must be equal to it.
+    pub fn insert_key(&mut self, key: K) -> K {
+        std::mem::replace(self.key_mut(), key)
+    }
+
+    pub fn remove_entry(self) -> (K, V) {
+        // SAFETY: as for pair(); the entry is consumed so the slot is never read again
+        unsafe { self.table.remove(self.index) }
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+}
+
+impl<'a, K, V, S> RawVacantEntryMut<'a, K, V, S> {
+    pub fn insert(self, key: K, value: V) -> (&'a mut K, &'a mut V) 
+    where
+        K: Hash,
+        S: BuildHasher
+    {
+        let hash = make_hash(&self.map.hash_builder, &key);
+        self.insert_hashed_nocheck(hash, key, value)
+    }
+
+    /// Insert using a hash the caller has already computed with this map's hasher.
+    ///
+    /// The hash must match the one the map would compute for `key`, or later
+    /// lookups through the regular API will not find the entry.
+    pub fn insert_hashed_nocheck(self, hash: u64, key: K, value: V) -> (&'a mut K, &'a mut V) 
+    where
+        K: Hash,
+        S: BuildHasher
+    {
+        let map = self.map;
+        // as for HashMap::entry, make room before checking the probe length
+        // so that the insert lands where the guard measured
+        map.table.reserve(1, make_hasher(&map.hash_builder));
+        let hash = map.guard_vacant(hash, &key);
+        let index = map.table.insert(hash, (key, value), make_hasher(&map.hash_builder));
+        // SAFETY: index refers to the slot just filled
+        let (k, v) = unsafe { map.table.get_mut(index) };
+        (k, v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precomputed_hash() {
+        let mut map : HashMap<String, usize> = HashMap::new();
+        let words = ["foo", "bar", "baz", "foo", "bar", "foo"];
+
+        for word in words.iter() {
+            // hash once, then use the hash both to probe and to insert
+            let hash = map.hasher().hash_one(word);
+            let (_, count) = map
+                .raw_entry_mut()
+                .from_key_hashed_nocheck(hash, *word)
+                .or_insert_with(|| (word.to_string(), 0));
+            *count += 1;
+        }
+
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get("foo"), Some(&3));
+        assert_eq!(map.get("bar"), Some(&2));
+        assert_eq!(map.get("baz"), Some(&1));
+    }
+
+    #[test]
+    fn custom_equality() {
+        let mut map : HashMap<String, i32> = HashMap::new();
+        map.insert("Hello".to_string(), 1);
+
+        // match on a borrowed key with a closure instead of an owned String
+        let hash = map.hasher().hash_one("Hello");
+        let found = map.raw_entry().from_hash(hash, |k| k == "Hello");
+        assert_eq!(found, Some((&"Hello".to_string(), &1)));
+        assert_eq!(map.raw_entry().from_hash(hash, |k| k == "World"), None);
+        assert_eq!(map.raw_entry().from_key("Hello").map(|(_, v)| *v), Some(1));
+    }
+
+    #[test]
+    fn interning() {
+        let mut interned : HashMap<String, usize> = HashMap::new();
+        let mut intern = |s: &str| -> usize {
+            let hash = interned.hasher().hash_one(s);
+            let next = interned.len();
+            match interned.raw_entry_mut().from_key_hashed_nocheck(hash, s) {
+                RawEntryMut::Occupied(e) => *e.get(),
+                RawEntryMut::Vacant(e) => *e.insert_hashed_nocheck(hash, s.to_string(), next).1,
+            }
+        };
+
+        assert_eq!(intern("a"), 0);
+        assert_eq!(intern("b"), 1);
+        assert_eq!(intern("a"), 0);
+        for i in 0..100 {
+            intern(&i.to_string());
+        }
+        assert_eq!(intern("b"), 1);
+        assert_eq!(interned.len(), 102);
+        assert_eq!(interned.get("99"), Some(&101));
+    }
+
+    #[test]
+    fn occupied_raw_entry() {
+        let mut map : HashMap<i32, i32> = HashMap::new();
+        map.insert(1, 10);
+
+        match map.raw_entry_mut().from_key(&1) {
+            RawEntryMut::Occupied(mut e) => {
+                assert_eq!(e.get_key_value(), (&1, &10));
+                assert_eq!(e.insert(20), 10);
+                assert_eq!(e.insert_key(1), 1);
+                assert_eq!(e.remove_entry(), (1, 20));
+            },
+            RawEntryMut::Vacant(_) => unreachable!(),
+        }
+        assert!(map.is_empty());
+
+        map.raw_entry_mut().from_key(&2).and_modify(|_, _| unreachable!()).or_insert(2, 2);
+        map.raw_entry_mut().from_key(&2).and_modify(|_, v| *v += 1).or_insert(2, 0);
+        assert_eq!(map.get(&2), Some(&3));
+    }
+}
@@ -0,0 +1,552 @@
+// set.rs
+// Hash set built on HashMap, with the usual set algebra.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::iter::{Chain, FromIterator};
+use std::ops::{BitAnd, BitOr, BitXor, Sub};
+
+use crate::{HashMap, RawEntryMut};
+
+pub struct HashSet<T, S = RandomState> {
+    map: HashMap<T, (), S>
+}
+
+impl<T> HashSet<T> {
+    pub fn new() -> Self {
+        Self { map: HashMap::new() }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { map: HashMap::with_capacity(capacity) }
+    }
+}
+
+impl<T, S> HashSet<T, S>
+where
+    S: BuildHasher
+{
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self { map: HashMap::with_hasher(hash_builder) }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self { map: HashMap::with_capacity_and_hasher(capacity, hash_builder) }
+    }
+}
+
+impl<T, S> HashSet<T, S> {
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { inner: self.map.keys() }
+    }
+
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain { inner: self.map.drain() }
+    }
+
+    /// Keep only the values for which `f` returns true.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool
+    {
+        self.map.retain(|k, _| f(k));
+    }
+}
+
+impl<T, S> HashSet<T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher
+{
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit();
+    }
+
+    /// Add a value, returning false if an equal value was already present.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.map.insert(value, ()).is_none()
+    }
+
+    /// Add a value, replacing and returning any equal value already present.
+    pub fn replace(&mut self, value: T) -> Option<T> {
+        match self.map.raw_entry_mut().from_key(&value) {
+            RawEntryMut::Occupied(mut e) => Some(e.insert_key(value)),
+            RawEntryMut::Vacant(e) => {
+                e.insert(value, ());
+                None
+            },
+        }
+    }
+
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains_key(value)
+    }
+
+    /// The stored value equal to the given one, if any.
+    pub fn get<Q>(&self, value: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.raw_entry().from_key(value).map(|(k, _)| k)
+    }
+
+    /// Remove a value, returning whether it was present.
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.remove(value).is_some()
+    }
+
+    /// Remove and return the stored value equal to the given one, if any.
+    pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.map.raw_entry_mut().from_key(value) {
+            RawEntryMut::Occupied(e) => Some(e.remove_entry().0),
+            RawEntryMut::Vacant(_) => None,
+        }
+    }
+
+    /// Values in either set.
+    pub fn union<'a>(&'a self, other: &'a HashSet<T, S>) -> Union<'a, T, S> {
+        // walk the larger set in full and filter the smaller one against it
+        let (larger, smaller) = if self.len() >= other.len() { (self, other) } else { (other, self) };
+        Union { inner: larger.iter().chain(smaller.difference(larger)) }
+    }
+
+    /// Values in both sets.
+    pub fn intersection<'a>(&'a self, other: &'a HashSet<T, S>) -> Intersection<'a, T, S> {
+        // probe the larger set once per value of the smaller one
+        let (larger, smaller) = if self.len() >= other.len() { (self, other) } else { (other, self) };
+        Intersection { iter: smaller.iter(), other: larger }
+    }
+
+    /// Values in self but not in other.
+    pub fn difference<'a>(&'a self, other: &'a HashSet<T, S>) -> Difference<'a, T, S> {
+        Difference { iter: self.iter(), other }
+    }
+
+    /// Values in exactly one of the sets.
+    pub fn symmetric_difference<'a>(&'a self, other: &'a HashSet<T, S>) -> SymmetricDifference<'a, T, S> {
+        SymmetricDifference { inner: self.difference(other).chain(other.difference(self)) }
+    }
+
+    pub fn is_disjoint(&self, other: &HashSet<T, S>) -> bool {
+        self.intersection(other).next().is_none()
+    }
+
+    pub fn is_subset(&self, other: &HashSet<T, S>) -> bool {
+        self.len() <= other.len() && self.iter().all(|v| other.contains(v))
+    }
+
+    pub fn is_superset(&self, other: &HashSet<T, S>) -> bool {
+        other.is_subset(self)
+    }
+}
+
+pub struct Iter<'a, T> {
+    inner: crate::Keys<'a, T, ()>
+}
+
+pub struct IntoIter<T> {
+    inner: crate::IntoKeys<T, ()>
+}
+
+pub struct Drain<'a, T> {
+    inner: crate::Drain<'a, T, ()>
+}
+
+pub struct Union<'a, T, S> {
+    inner: Chain<Iter<'a, T>, Difference<'a, T, S>>
+}
+
+pub struct Intersection<'a, T, S> {
+    iter: Iter<'a, T>,
+    other: &'a HashSet<T, S>
+}
+
+pub struct Difference<'a, T, S> {
+    iter: Iter<'a, T>,
+    other: &'a HashSet<T, S>
+}
+
+pub struct SymmetricDifference<'a, T, S> {
+    inner: Chain<Difference<'a, T, S>, Difference<'a, T, S>>
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, T> Iterator for Drain<'a, T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, T, S> Iterator for Union<'a, T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher
+{
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl<'a, T, S> Iterator for Intersection<'a, T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher
+{
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        let other = self.other;
+        self.iter.find(|v| other.contains(*v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+impl<'a, T, S> Iterator for Difference<'a, T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher
+{
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        let other = self.other;
+        self.iter.find(|v| !other.contains(*v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+impl<'a, T, S> Iterator for SymmetricDifference<'a, T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher
+{
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl<'a, T, S> IntoIterator for &'a HashSet<T, S> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, S> IntoIterator for HashSet<T, S> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { inner: self.map.into_keys() }
+    }
+}
+
+impl<T, S> Default for HashSet<T, S>
+where
+    S: BuildHasher + Default
+{
+    fn default() -> Self {
+        Self { map: HashMap::default() }
+    }
+}
+
+impl<T, S> Clone for HashSet<T, S>
+where
+    T: Clone,
+    S: Clone
+{
+    fn clone(&self) -> Self {
+        Self { map: self.map.clone() }
+    }
+}
+
+impl<T, S> fmt::Debug for HashSet<T, S>
+where
+    T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T, S> PartialEq for HashSet<T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.is_subset(other)
+    }
+}
+
+impl<T, S> Eq for HashSet<T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher
+{
+}
+
+impl<T, S> FromIterator<T> for HashSet<T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher + Default
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self { map: iter.into_iter().map(|v| (v, ())).collect() }
+    }
+}
+
+impl<T, const N: usize> From<[T; N]> for HashSet<T>
+where
+    T: Hash + Eq
+{
+    fn from(values: [T; N]) -> Self {
+        IntoIterator::into_iter(values).collect()
+    }
+}
+
+impl<T, S> Extend<T> for HashSet<T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.map.extend(iter.into_iter().map(|v| (v, ())));
+    }
+}
+
+impl<'a, T, S> Extend<&'a T> for HashSet<T, S>
+where
+    T: Hash + Eq + Copy,
+    S: BuildHasher
+{
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
+// The operators build a new set using a fresh instance of the hasher type.
+macro_rules! set_operator {
+    ($trait:ident, $method:ident, $algebra:ident) => {
+        impl<T, S> $trait<&HashSet<T, S>> for &HashSet<T, S>
+        where
+            T: Hash + Eq + Clone,
+            S: BuildHasher + Default
+        {
+            type Output = HashSet<T, S>;
+
+            fn $method(self, rhs: &HashSet<T, S>) -> HashSet<T, S> {
+                self.$algebra(rhs).cloned().collect()
+            }
+        }
+    };
+}
+
+set_operator!(BitOr, bitor, union);
+set_operator!(BitAnd, bitand, intersection);
+set_operator!(Sub, sub, difference);
+set_operator!(BitXor, bitxor, symmetric_difference);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(values: &[i32]) -> HashSet<i32> {
+        values.iter().copied().collect()
+    }
+
+    fn sorted<'a>(iter: impl Iterator<Item = &'a i32>) -> Vec<i32> {
+        let mut values: Vec<i32> = iter.copied().collect();
+        values.sort_unstable();
+        values
+    }
+
+    #[test]
+    fn insert_contains_remove() {
+        let mut s = HashSet::new();
+        assert!(s.insert(1));
+        assert!(!s.insert(1));
+        assert!(s.contains(&1));
+        assert!(!s.contains(&2));
+        assert_eq!(s.len(), 1);
+
+        assert!(s.remove(&1));
+        assert!(!s.remove(&1));
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn get_take_replace() {
+        // values that are equal by key but distinguishable
+        #[derive(Debug)]
+        struct Item(i32, &'static str);
+        impl PartialEq for Item {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+        impl Eq for Item {}
+        impl Hash for Item {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                self.0.hash(state);
+            }
+        }
+
+        let mut s = HashSet::new();
+        assert!(s.replace(Item(1, "first")).is_none());
+        assert_eq!(s.get(&Item(1, "probe")).unwrap().1, "first");
+
+        let old = s.replace(Item(1, "second")).unwrap();
+        assert_eq!(old.1, "first");
+        assert_eq!(s.get(&Item(1, "probe")).unwrap().1, "second");
+        assert_eq!(s.len(), 1);
+
+        assert_eq!(s.take(&Item(1, "probe")).unwrap().1, "second");
+        assert!(s.take(&Item(1, "probe")).is_none());
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn algebra() {
+        let a = set(&[1, 2, 3, 4]);
+        let b = set(&[3, 4, 5]);
+
+        assert_eq!(sorted(a.union(&b)), vec![1, 2, 3, 4, 5]);
+        assert_eq!(sorted(a.intersection(&b)), vec![3, 4]);
+        assert_eq!(sorted(a.difference(&b)), vec![1, 2]);
+        assert_eq!(sorted(b.difference(&a)), vec![5]);
+        assert_eq!(sorted(a.symmetric_difference(&b)), vec![1, 2, 5]);
+
+        // the argument order doesn't change the result
+        assert_eq!(sorted(b.union(&a)), vec![1, 2, 3, 4, 5]);
+        assert_eq!(sorted(b.intersection(&a)), vec![3, 4]);
+    }
+
+    #[test]
+    fn operators() {
+        let a = set(&[1, 2, 3, 4]);
+        let b = set(&[3, 4, 5]);
+
+        assert_eq!(&a | &b, set(&[1, 2, 3, 4, 5]));
+        assert_eq!(&a & &b, set(&[3, 4]));
+        assert_eq!(&a - &b, set(&[1, 2]));
+        assert_eq!(&a ^ &b, set(&[1, 2, 5]));
+    }
+
+    #[test]
+    fn relations() {
+        let a = set(&[1, 2, 3]);
+        let b = set(&[1, 2]);
+        let c = set(&[4]);
+        let empty = set(&[]);
+
+        assert!(b.is_subset(&a));
+        assert!(!a.is_subset(&b));
+        assert!(a.is_superset(&b));
+        assert!(empty.is_subset(&a));
+        assert!(a.is_subset(&a));
+
+        assert!(a.is_disjoint(&c));
+        assert!(!a.is_disjoint(&b));
+        assert!(empty.is_disjoint(&empty));
+    }
+
+    #[test]
+    fn lazy_algebra() {
+        let a: HashSet<i32> = (0..10_000).collect();
+        let b: HashSet<i32> = (5_000..15_000).collect();
+
+        // nothing is materialized up front, so taking a few is cheap
+        assert_eq!(a.intersection(&b).take(3).count(), 3);
+        assert_eq!(a.union(&b).count(), 15_000);
+        assert_eq!(a.symmetric_difference(&b).count(), 10_000);
+    }
+
+    #[test]
+    fn traits() {
+        let mut s = HashSet::from(["a", "b"]);
+        s.extend(["c", "a"].iter());
+        assert_eq!(s.len(), 3);
+        assert_eq!(s.clone(), s);
+        assert_eq!(format!("{:?}", HashSet::from([1])), "{1}");
+
+        s.retain(|v| *v != "b");
+        let mut values: Vec<_> = s.drain().collect();
+        values.sort_unstable();
+        assert_eq!(values, vec!["a", "c"]);
+        assert!(s.is_empty());
+    }
+}
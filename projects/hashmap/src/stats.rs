@@ -0,0 +1,384 @@
+// stats.rs
+// Diagnostics for how well a map's hasher spreads its keys, and a guard
+// against hashers that pile them up.
+//
+// With open addressing, a weak hasher doesn't grow a chain off one bucket;
+// it sends many keys to the same home group. Each key after the first
+// few must then probe further along the sequence to find a free slot. The
+// probe length of an entry is the number of groups probed before reaching
+// the one that holds it. Under a good hasher it is almost always 0 or 1,
+// whatever the size of the map.
+//
+// MapStats::is_pathological reports an entry probed past PROBE_LIMIT. A map
+// can also opt in to checking every new entry in debug builds: past the
+// limit it either panics or, if its hasher can be re-seeded, re-seeds it and
+// rehashes every entry. By default nothing is checked.
+
+use std::hash::{BuildHasher, Hash};
+use std::collections::hash_map::RandomState;
+use std::mem;
+
+use crate::hash::RandomSipState;
+use crate::table::Table;
+use crate::{make_hash, HashMap};
+
+/// Probe lengths beyond this many groups are considered pathological.
+pub const PROBE_LIMIT: usize = 32;
+
+/// A snapshot of how a map's entries are laid out, from HashMap::stats.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapStats {
+    pub len: usize,
+    pub buckets: usize,
+    /// Entries per bucket; 0 for a map that hasn't allocated.
+    pub load_factor: f64,
+    /// Slots left DELETED by removals, which lengthen probes until the
+    /// table is next rebuilt.
+    pub tombstones: usize,
+    /// `probe_histogram[n]` counts the entries n groups past their home group.
+    pub probe_histogram: Vec<usize>,
+    pub max_probe_length: usize,
+    pub mean_probe_length: f64,
+    /// Whether an incremental resize is in progress.
+    pub migrating: bool,
+    pub next_resize: ResizeCost,
+}
+
+impl MapStats {
+    /// Whether any entry is further from home than a reasonable hasher
+    /// would ever put it.
+    pub fn is_pathological(&self) -> bool {
+        self.max_probe_length > PROBE_LIMIT
+    }
+}
+
+/// The estimated cost of the map's next growth.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResizeCost {
+    /// New entries the map can take before it grows.
+    pub inserts_until_resize: usize,
+    /// Entries that will be rehashed into the new table, all at once or,
+    /// with incremental resizing, spread over later operations.
+    pub entries_moved: usize,
+    /// None if the table can't grow any further.
+    pub new_buckets: Option<usize>,
+    pub bytes_allocated: Option<usize>,
+}
+
+/// Hasher builders that can switch to fresh random keys, allowing a map to
+/// recover from keys that collide under its current ones.
+pub trait Reseed: BuildHasher {
+    fn reseed(&mut self);
+}
+
+impl Reseed for RandomState {
+    fn reseed(&mut self) {
+        *self = RandomState::new();
+    }
+}
+
+impl Reseed for RandomSipState {
+    fn reseed(&mut self) {
+        *self = RandomSipState::new();
+    }
+}
+
+/// What a map does, in debug builds, when a new entry lands more than
+/// PROBE_LIMIT groups from home.
+pub(crate) enum ProbeGuard<S> {
+    Off,
+    Panic,
+    Reseed(fn(&mut S)),
+}
+
+impl<S> ProbeGuard<S> {
+    pub(crate) fn reseeding() -> Self
+    where
+        S: Reseed
+    {
+        ProbeGuard::Reseed(S::reseed)
+    }
+
+    fn is_off(&self) -> bool {
+        matches!(self, ProbeGuard::Off)
+    }
+}
+
+impl<S> Clone for ProbeGuard<S> {
+    fn clone(&self) -> Self {
+        match self {
+            ProbeGuard::Off => ProbeGuard::Off,
+            ProbeGuard::Panic => ProbeGuard::Panic,
+            ProbeGuard::Reseed(reseed) => ProbeGuard::Reseed(*reseed),
+        }
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash,
+    S: BuildHasher
+{
+    /// Measure how the map's entries are spread over its table.
+    ///
+    /// This hashes every key, so it takes time proportional to the size
+    /// of the map.
+    pub fn stats(&self) -> MapStats {
+        let mut probe_histogram = Vec::new();
+        let mut total = 0;
+        for index in self.table.iter() {
+            // SAFETY: index comes from iterating FULL slots
+            let (key, _) = unsafe { self.table.get(index) };
+            let length = self.table.probe_length(make_hash(&self.hash_builder, key), index);
+            if length >= probe_histogram.len() {
+                probe_histogram.resize(length + 1, 0);
+            }
+            probe_histogram[length] += 1;
+            total += length;
+        }
+
+        let len = self.len();
+        let buckets = self.table.buckets();
+        let inserts_until_resize = self.table.growth_left();
+        let next_growth = self.table.next_growth();
+        MapStats {
+            len,
+            buckets,
+            load_factor: if buckets == 0 { 0.0 } else { len as f64 / buckets as f64 },
+            tombstones: self.table.tombstones(),
+            max_probe_length: probe_histogram.len().saturating_sub(1),
+            mean_probe_length: if len == 0 { 0.0 } else { total as f64 / len as f64 },
+            probe_histogram,
+            migrating: self.table.is_migrating(),
+            next_resize: ResizeCost {
+                inserts_until_resize,
+                entries_moved: len + inserts_until_resize,
+                new_buckets: next_growth.map(|(buckets, _)| buckets),
+                bytes_allocated: next_growth.map(|(_, bytes)| bytes),
+            },
+        }
+    }
+
+    // Enforce the probe guard on a new entry just inserted at `index`.
+    pub(crate) fn guard_inserted(&mut self, hash: u64, index: usize) {
+        if cfg!(debug_assertions) && !self.probe_guard.is_off() {
+            let length = self.table.probe_length(hash, index);
+            self.enforce_probe_limit(length);
+        }
+    }
+
+    // Enforce the probe guard on a new entry about to be inserted, returning
+    // the hash to insert it with; re-seeding changes every key's hash.
+    pub(crate) fn guard_vacant(&mut self, hash: u64, key: &K) -> u64 {
+        if cfg!(debug_assertions) && !self.probe_guard.is_off() {
+            let length = self.table.insert_probe_length(hash);
+            if self.enforce_probe_limit(length) {
+                return make_hash(&self.hash_builder, key);
+            }
+        }
+        hash
+    }
+
+    // Returns whether the map was re-seeded.
+    fn enforce_probe_limit(&mut self, length: usize) -> bool {
+        if length <= PROBE_LIMIT {
+            return false;
+        }
+        match self.probe_guard {
+            ProbeGuard::Off => false,
+            ProbeGuard::Panic => panic!(
+                "an insert probed {} groups past its home group (limit {}); \
+                 the map's BuildHasher is distributing its keys poorly",
+                length,
+                PROBE_LIMIT
+            ),
+            ProbeGuard::Reseed(reseed) => {
+                reseed(&mut self.hash_builder);
+                self.rehash();
+                true
+            },
+        }
+    }
+
+    // Move every entry into a new table of the same capacity under the
+    // current hasher. A panicking hasher loses the entries not yet moved.
+    fn rehash(&mut self) {
+        let mut table = Table::with_capacity(self.capacity(), self.table.max_load_factor());
+        table.set_incremental(self.table.is_incremental());
+        let mut old = mem::replace(&mut self.table, table);
+        for index in old.iter() {
+            // SAFETY: index comes from iterating FULL slots, and removing
+            // while iterating is allowed
+            let entry = unsafe { old.remove(index) };
+            let hash = make_hash(&self.hash_builder, &entry.0);
+            self.table.insert_no_grow(hash, entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{FxBuildHasher, IdentityBuildHasher};
+    use crate::Builder;
+    use std::hash::{BuildHasherDefault, Hasher};
+
+    // sends every key to the same home group
+    #[derive(Default)]
+    struct Constant;
+
+    impl Hasher for Constant {
+        fn finish(&self) -> u64 {
+            0
+        }
+
+        fn write(&mut self, _: &[u8]) {}
+    }
+
+    type ConstantState = BuildHasherDefault<Constant>;
+
+    // as weak as Constant until re-seeded
+    #[derive(Default)]
+    struct Weak {
+        seeded: bool,
+    }
+
+    impl BuildHasher for Weak {
+        type Hasher = Box<dyn Hasher>;
+        fn build_hasher(&self) -> Box<dyn Hasher> {
+            if self.seeded {
+                Box::new(std::collections::hash_map::DefaultHasher::new())
+            } else {
+                Box::new(Constant)
+            }
+        }
+    }
+
+    impl Reseed for Weak {
+        fn reseed(&mut self) {
+            self.seeded = true;
+        }
+    }
+
+    #[test]
+    fn good_hasher() {
+        let map: HashMap<u64, u64> = (0..100_000).map(|i| (i, i)).collect();
+        let stats = map.stats();
+        assert_eq!(stats.len, 100_000);
+        assert_eq!(stats.buckets, map.table.buckets());
+        assert!(stats.load_factor > 0.3 && stats.load_factor <= 0.875);
+        assert_eq!(stats.probe_histogram.iter().sum::<usize>(), 100_000);
+        assert!(stats.mean_probe_length < 1.0);
+        assert!(!stats.is_pathological());
+        assert_eq!(stats.tombstones, 0);
+    }
+
+    #[test]
+    fn weak_hasher() {
+        let mut map = Builder::new().hasher(ConstantState::default()).check_probe_lengths(false).build();
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+        let stats = map.stats();
+        assert!(stats.is_pathological());
+        assert!(stats.max_probe_length >= 1000 / 8 - 1);
+        assert_eq!(map.get(&999), Some(&999));
+    }
+
+    #[test]
+    fn resize_cost() {
+        let mut map: HashMap<u32, u32, FxBuildHasher> = HashMap::default();
+        assert_eq!(map.stats().load_factor, 0.0);
+        assert_eq!(map.stats().next_resize.new_buckets, Some(8));
+
+        map.insert(0, 0);
+        let before = map.stats().next_resize;
+        for i in 1..=before.inserts_until_resize as u32 {
+            map.insert(i, i);
+        }
+        assert_eq!(map.stats().next_resize.inserts_until_resize, 0);
+        assert_eq!(map.stats().buckets, 8);
+
+        // the prediction made before filling the table holds
+        map.insert(1000, 0);
+        assert_eq!(Some(map.stats().buckets), before.new_buckets);
+        assert_eq!(map.len(), before.entries_moved + 1);
+        assert!(before.bytes_allocated.unwrap() >= before.new_buckets.unwrap() * (8 + 1));
+
+        map.remove(&1000);
+        map.remove(&0);
+        assert!(map.stats().tombstones <= 2);
+    }
+
+    #[test]
+    fn identity_hasher_is_fine_for_sequential_keys() {
+        let map: HashMap<u64, (), IdentityBuildHasher> = (0..10_000).map(|i| (i, ())).collect();
+        assert_eq!(map.stats().max_probe_length, 0);
+    }
+
+    #[test]
+    fn guard_off_by_default() {
+        // the identity hasher sends keys differing only in their high bits
+        // to the same group; the map still works, and stats say why it's slow
+        let mut map: HashMap<u64, u64, IdentityBuildHasher> = HashMap::default();
+        for i in 0..2000 {
+            map.insert(i << 32, i);
+        }
+        assert!(map.stats().is_pathological());
+        assert_eq!(map.get(&(1999 << 32)), Some(&1999));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "distributing its keys poorly")]
+    fn guard_panics() {
+        let mut map = Builder::new().hasher(ConstantState::default()).check_probe_lengths(true).build();
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "distributing its keys poorly")]
+    fn guard_panics_on_raw_entry() {
+        let mut map = Builder::new().hasher(ConstantState::default()).check_probe_lengths(true).build();
+        for i in 0..1000 {
+            map.raw_entry_mut().from_key(&i).or_insert(i, i);
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn guard_reseeds() {
+        let mut map = Builder::new().hasher(Weak::default()).reseed_on_long_probes().build();
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+        assert!(map.hasher().seeded);
+        assert!(!map.stats().is_pathological());
+
+        // entries placed before and after the re-seed are all reachable
+        let mut map = Builder::new().hasher(Weak::default()).reseed_on_long_probes().build();
+        for i in 0..1000 {
+            *map.entry(i).or_insert(0) += i;
+        }
+        for i in 0..1000 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+        assert!(map.hasher().seeded);
+
+        // likewise through the raw entry API, whose returned references
+        // point at the entry wherever the rehash put it
+        let mut map = Builder::new().hasher(Weak::default()).reseed_on_long_probes().build();
+        for i in 0..1000 {
+            let (k, v) = map.raw_entry_mut().from_key(&i).or_insert(i, 0);
+            assert_eq!(*k, i);
+            *v += i;
+        }
+        for i in 0..1000 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+        assert!(map.hasher().seeded);
+    }
+}
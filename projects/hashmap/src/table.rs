@@ -0,0 +1,384 @@
+// table.rs
+// The map's storage: a RawTable, plus the table being migrated away from
+// while an incremental resize is in progress.
+//
+// By default a table that fills up is rehashed into a larger one in a single
+// step, so one insert can take time proportional to the size of the map. In
+// incremental mode the full table is instead kept aside as `old` and a fresh,
+// larger table takes new inserts. Every subsequent insert or remove moves the
+// next MIGRATE_STEP slots of `old` across, and lookups and iteration consult
+// both tables until `old` is empty (much like Redis's dict).
+//
+// Slot indices handed out by a Table address both tables at once: indices
+// below `current.buckets()` refer to `current`, and the rest refer to `old`.
+
+use std::mem;
+
+use crate::raw::{RawIter, RawTable, TryReserveError};
+
+// slots of the old table migrated per operation while resizing incrementally
+pub(crate) const MIGRATE_STEP: usize = 16;
+
+pub(crate) struct Table<T> {
+    current: RawTable<T>,
+    // the table being migrated from; unallocated when no migration is in progress
+    old: RawTable<T>,
+    // slots of `old` before this index have all been migrated
+    migrate_pos: usize,
+    incremental: bool,
+}
+
+impl<T> Table<T> {
+    pub(crate) fn new() -> Self {
+        Self::from_raw(RawTable::new())
+    }
+
+    pub(crate) fn with_capacity(capacity: usize, max_load: f32) -> Self {
+        Self::from_raw(RawTable::with_capacity(capacity, max_load))
+    }
+
+    fn from_raw(current: RawTable<T>) -> Self {
+        Self {
+            old: RawTable::with_max_load_factor(current.max_load_factor()),
+            current,
+            migrate_pos: 0,
+            incremental: false,
+        }
+    }
+
+    pub(crate) fn set_incremental(&mut self, incremental: bool) {
+        self.incremental = incremental;
+    }
+
+    pub(crate) fn is_incremental(&self) -> bool {
+        self.incremental
+    }
+
+    pub(crate) fn is_migrating(&self) -> bool {
+        self.old.buckets() != 0
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.current.len() + self.old.len()
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        // items still in the old table already have room reserved in the current one
+        self.current.capacity()
+    }
+
+    pub(crate) fn max_load_factor(&self) -> f32 {
+        self.current.max_load_factor()
+    }
+
+    pub(crate) fn buckets(&self) -> usize {
+        self.current.buckets()
+    }
+
+    pub(crate) fn growth_left(&self) -> usize {
+        self.current.growth_left()
+    }
+
+    pub(crate) fn tombstones(&self) -> usize {
+        self.current.tombstones() + self.old.tombstones()
+    }
+
+    /// See RawTable::next_growth. While migrating, the current table is the
+    /// one that will grow next.
+    pub(crate) fn next_growth(&self) -> Option<(usize, usize)> {
+        self.current.next_growth()
+    }
+
+    pub(crate) fn probe_length(&self, hash: u64, index: usize) -> usize {
+        let (table, index) = self.split(index);
+        table.probe_length(hash, index)
+    }
+
+    pub(crate) fn insert_probe_length(&self, hash: u64) -> usize {
+        self.current.insert_probe_length(hash)
+    }
+
+    // which raw table a slot index refers to, and the index within it
+    fn split(&self, index: usize) -> (&RawTable<T>, usize) {
+        match index.checked_sub(self.current.buckets()) {
+            Some(index) => (&self.old, index),
+            None => (&self.current, index),
+        }
+    }
+
+    fn split_mut(&mut self, index: usize) -> (&mut RawTable<T>, usize) {
+        match index.checked_sub(self.current.buckets()) {
+            Some(index) => (&mut self.old, index),
+            None => (&mut self.current, index),
+        }
+    }
+
+    pub(crate) fn find(&self, hash: u64, mut eq: impl FnMut(&T) -> bool) -> Option<usize> {
+        if let Some(index) = self.current.find(hash, &mut eq) {
+            return Some(index);
+        }
+        let index = self.old.find(hash, eq)?;
+        Some(self.current.buckets() + index)
+    }
+
+    // SAFETY: index < buckets of the table it refers to
+    pub(crate) unsafe fn bucket(&self, index: usize) -> *mut T {
+        let (table, index) = self.split(index);
+        table.bucket(index)
+    }
+
+    // SAFETY: index must refer to a FULL slot
+    pub(crate) unsafe fn get(&self, index: usize) -> &T {
+        let (table, index) = self.split(index);
+        table.get(index)
+    }
+
+    // SAFETY: index must refer to a FULL slot
+    pub(crate) unsafe fn get_mut(&mut self, index: usize) -> &mut T {
+        let (table, index) = self.split_mut(index);
+        table.get_mut(index)
+    }
+
+    /// Remove and return the value in a FULL slot.
+    ///
+    /// This never migrates, so it is safe to call while iterating.
+    ///
+    /// # Safety
+    /// index must refer to a FULL slot
+    pub(crate) unsafe fn remove(&mut self, index: usize) -> T {
+        let (table, index) = self.split_mut(index);
+        table.remove(index)
+    }
+
+    /// Insert a value, growing the table if required, and return its slot.
+    ///
+    /// The caller must have checked that no equal value is already present.
+    pub(crate) fn insert(&mut self, hash: u64, value: T, hasher: impl Fn(&T) -> u64) -> usize {
+        if !self.incremental && !self.is_migrating() {
+            return self.current.insert(hash, value, hasher);
+        }
+        self.reserve(1, hasher);
+        self.current.insert_no_grow(hash, value)
+    }
+
+    /// Insert a value without growing; the caller must have reserved room.
+    pub(crate) fn insert_no_grow(&mut self, hash: u64, value: T) -> usize {
+        self.current.insert_no_grow(hash, value)
+    }
+
+    /// Ensure room for `additional` more values without further growth.
+    ///
+    /// In incremental mode this also advances any migration in progress, and
+    /// room for a single value is made by starting a migration rather than
+    /// by rehashing everything at once.
+    pub(crate) fn reserve(&mut self, additional: usize, hasher: impl Fn(&T) -> u64) {
+        if self.is_migrating() {
+            self.migrate_step(&hasher);
+            if additional <= self.current.growth_left() {
+                return;
+            }
+            self.finish_migration(&hasher);
+        }
+
+        if additional <= self.current.growth_left() {
+            return;
+        }
+        if self.incremental && additional == 1 && self.current.len() != 0 {
+            self.start_migration(&hasher);
+        } else {
+            self.current.reserve(additional, hasher);
+        }
+    }
+
+    pub(crate) fn try_reserve(
+        &mut self,
+        additional: usize,
+        hasher: impl Fn(&T) -> u64,
+    ) -> Result<(), TryReserveError> {
+        self.finish_migration(&hasher);
+        self.current.try_reserve(additional, hasher)
+    }
+
+    pub(crate) fn shrink_to(&mut self, min_capacity: usize, hasher: impl Fn(&T) -> u64) {
+        self.finish_migration(&hasher);
+        self.current.shrink_to(min_capacity, hasher);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.current.clear();
+        self.old = RawTable::with_max_load_factor(self.max_load_factor());
+        self.migrate_pos = 0;
+    }
+
+    // Set the full current table aside and allocate one large enough to take
+    // every value from it, plus any inserts made before the migration completes.
+    fn start_migration(&mut self, hasher: &impl Fn(&T) -> u64) {
+        debug_assert!(!self.is_migrating());
+
+        let items = self.current.len();
+        let steps = self.current.buckets().div_ceil(MIGRATE_STEP);
+        let capacity = (items * 2).max(items + steps + 1);
+
+        let new = RawTable::with_capacity(capacity, self.max_load_factor());
+        self.old = mem::replace(&mut self.current, new);
+        self.migrate_pos = 0;
+        self.migrate_step(hasher);
+    }
+
+    /// Move the next MIGRATE_STEP slots of the old table into the current one.
+    pub(crate) fn migrate_step(&mut self, hasher: &impl Fn(&T) -> u64) {
+        if !self.is_migrating() {
+            return;
+        }
+
+        let end = (self.migrate_pos + MIGRATE_STEP).min(self.old.buckets());
+        for index in self.migrate_pos..end {
+            if self.old.is_full(index) {
+                // SAFETY: the slot is FULL; hashing before removing means a
+                // panicking hasher leaves the value where it was
+                unsafe {
+                    let hash = hasher(self.old.get(index));
+                    let value = self.old.remove(index);
+                    self.current.insert_no_grow(hash, value);
+                }
+            }
+        }
+        self.migrate_pos = end;
+
+        if self.old.len() == 0 {
+            // nothing left behind the cursor or ahead of it; release the old table
+            self.old = RawTable::with_max_load_factor(self.max_load_factor());
+            self.migrate_pos = 0;
+        }
+    }
+
+    fn finish_migration(&mut self, hasher: &impl Fn(&T) -> u64) {
+        while self.is_migrating() {
+            self.migrate_step(hasher);
+        }
+    }
+
+    /// Iterate the slot indices of every value in both tables.
+    ///
+    /// As with RawTable::iter, the table must outlive the iterator; values
+    /// may be removed while iterating, but not inserted.
+    pub(crate) fn iter(&self) -> TableIter {
+        TableIter {
+            current: self.current.iter(),
+            old: self.old.iter(),
+            offset: self.current.buckets(),
+        }
+    }
+}
+
+impl<T: Clone> Clone for Table<T> {
+    fn clone(&self) -> Self {
+        Self {
+            current: self.current.clone(),
+            old: self.old.clone(),
+            migrate_pos: self.migrate_pos,
+            incremental: self.incremental,
+        }
+    }
+}
+
+/// Iterator over the slot indices of a Table.
+pub(crate) struct TableIter {
+    current: RawIter,
+    old: RawIter,
+    offset: usize,
+}
+
+impl Iterator for TableIter {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
+        match self.current.next() {
+            Some(index) => Some(index),
+            None => self.old.next().map(|index| self.offset + index),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.current.len() + self.old.len();
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for TableIter {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hasher(x: &u64) -> u64 {
+        x.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+    }
+
+    fn incremental() -> Table<u64> {
+        let mut table = Table::new();
+        table.set_incremental(true);
+        table
+    }
+
+    fn insert(table: &mut Table<u64>, x: u64) {
+        if table.find(hasher(&x), |&y| y == x).is_none() {
+            table.insert(hasher(&x), x, hasher);
+        }
+    }
+
+    #[test]
+    fn lookups_span_both_tables() {
+        let mut table = incremental();
+        let mut migrated = false;
+        for i in 0..10_000 {
+            insert(&mut table, i);
+            migrated |= table.is_migrating();
+
+            // everything inserted so far is visible, whichever table it is in
+            if table.is_migrating() && i % 7 == 0 {
+                for j in (0..=i).step_by(97) {
+                    assert!(table.find(hasher(&j), |&y| y == j).is_some());
+                }
+                assert_eq!(table.iter().count(), i as usize + 1);
+            }
+        }
+        assert!(migrated);
+        assert_eq!(table.len(), 10_000);
+    }
+
+    #[test]
+    fn removal_during_migration() {
+        let mut table = incremental();
+        let mut i = 0;
+        while !table.is_migrating() {
+            insert(&mut table, i);
+            i += 1;
+        }
+
+        // remove everything, from whichever table holds it
+        for j in 0..i {
+            let index = table.find(hasher(&j), |&y| y == j).unwrap();
+            assert_eq!(unsafe { table.remove(index) }, j);
+        }
+        assert_eq!(table.len(), 0);
+        assert_eq!(table.iter().count(), 0);
+
+        insert(&mut table, 0);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn finishes_before_reserving() {
+        let mut table = incremental();
+        let mut i = 0;
+        while !table.is_migrating() {
+            insert(&mut table, i);
+            i += 1;
+        }
+
+        table.try_reserve(100, hasher).unwrap();
+        assert!(!table.is_migrating());
+        assert_eq!(table.len(), i as usize);
+        assert!(table.capacity() >= table.len() + 100);
+    }
+}
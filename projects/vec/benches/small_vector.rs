@@ -0,0 +1,64 @@
+// small_vector.rs
+// Compare building, reading and draining many small vectors with Vector
+// and with SmallVector, for sizes around the inline capacity.
+//
+// Run with `cargo bench --bench small_vector`; timings are printed as nanoseconds per vector.
+
+extern crate vec;
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use vec::{SmallVector, Vector};
+
+const ROUNDS: usize = 100_000;
+const INLINE: usize = 8;
+
+fn report(kind: &str, size: usize, start: Instant) {
+    let nanos = start.elapsed().as_nanos() as f64 / ROUNDS as f64;
+    println!("{:<14} {:>3} items {:>8.1} ns/vec", kind, size, nanos);
+}
+
+fn bench_vector(size: usize) {
+    let start = Instant::now();
+    for round in 0..ROUNDS {
+        let mut vec = Vector::new();
+        for i in 0..size {
+            vec.push(black_box(round + i));
+        }
+        black_box(vec.iter().sum::<usize>());
+        vec.drain(..size / 2).for_each(|x| {
+            black_box(x);
+        });
+        while let Some(x) = vec.pop() {
+            black_box(x);
+        }
+    }
+    report("Vector", size, start);
+}
+
+fn bench_small_vector(size: usize) {
+    let start = Instant::now();
+    for round in 0..ROUNDS {
+        let mut vec = SmallVector::<_, INLINE>::new();
+        for i in 0..size {
+            vec.push(black_box(round + i));
+        }
+        black_box(vec.iter().sum::<usize>());
+        vec.drain(..size / 2).for_each(|x| {
+            black_box(x);
+        });
+        while let Some(x) = vec.pop() {
+            black_box(x);
+        }
+    }
+    report("SmallVector<8>", size, start);
+}
+
+fn main() {
+    for &size in &[1, 4, 8, 9, 16, 64] {
+        bench_vector(size);
+        bench_small_vector(size);
+        println!();
+    }
+}
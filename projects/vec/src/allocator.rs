@@ -0,0 +1,346 @@
+// allocator.rs
+// The allocators a Vector can take its buffer from.
+//
+// std's Allocator trait is unstable, so this is a small stand-in for it.
+// Vectors use Global, the global allocator, unless given another one.
+
+use std::alloc::{self, Layout};
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+use std::ptr::{self, NonNull};
+
+/// An allocator ran out of memory, or couldn't satisfy a layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocError;
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("memory allocation failed")
+    }
+}
+
+impl Error for AllocError {}
+
+/// A source of memory blocks.
+///
+/// # Safety
+/// A block returned by allocate or reallocate must be valid for reads and
+/// writes of its layout's size, be aligned to its layout's alignment, and
+/// stay that way until it is passed to deallocate or reallocate, even if
+/// the allocator is moved.
+pub unsafe trait Allocator {
+    /// Allocate a block for `layout`, which has a nonzero size.
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError>;
+
+    /// Free a block.
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated by this allocator with `layout`.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+
+    /// Move a block to one fitting `new_layout`, keeping its contents up to
+    /// the smaller of the two sizes. On failure the old block is untouched.
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated by this allocator with `old_layout`,
+    /// and `new_layout` must have the same alignment and a nonzero size.
+    unsafe fn reallocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        let new_ptr = self.allocate(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.as_ptr(), old_layout.size().min(new_layout.size()));
+        self.deallocate(ptr, old_layout);
+        Ok(new_ptr)
+    }
+}
+
+// Lets many vectors share one allocator, e.g. Vector::new_in(&bump).
+unsafe impl<A: Allocator + ?Sized> Allocator for &A {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        (**self).allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        (**self).deallocate(ptr, layout)
+    }
+
+    unsafe fn reallocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        (**self).reallocate(ptr, old_layout, new_layout)
+    }
+}
+
+/// The global allocator, through std::alloc.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Global;
+
+unsafe impl Allocator for Global {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        // SAFETY: the layout has a nonzero size
+        NonNull::new(unsafe { alloc::alloc(layout) }).ok_or(AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        alloc::dealloc(ptr.as_ptr(), layout)
+    }
+
+    unsafe fn reallocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        NonNull::new(alloc::realloc(ptr.as_ptr(), old_layout, new_layout.size())).ok_or(AllocError)
+    }
+}
+
+/// A bump allocator handing out pieces of one fixed-size block.
+///
+/// Allocating advances an offset into the block, and freeing does nothing
+/// unless the freed piece is the most recent one, so memory is reclaimed
+/// all at once when the Bump is reset or dropped. The most recent piece
+/// can also grow in place, which suits a single growing vector well.
+/// Allocations fail once the block is used up.
+pub struct Bump {
+    start: NonNull<u8>,
+    capacity: usize,
+    // the end of the used part of the block
+    offset: Cell<usize>,
+    // the start of the most recent allocation
+    last: Cell<usize>,
+}
+
+impl Bump {
+    // alignment of the block itself, enough for any common type
+    const BLOCK_ALIGN: usize = 16;
+
+    /// Create a Bump owning a block of `capacity` bytes.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let start = if capacity == 0 {
+            NonNull::dangling()
+        } else {
+            let layout = Self::block_layout(capacity);
+            // SAFETY: the layout has a nonzero size
+            let ptr = unsafe { alloc::alloc(layout) };
+            NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout))
+        };
+        Self {
+            start,
+            capacity,
+            offset: Cell::new(0),
+            last: Cell::new(0),
+        }
+    }
+
+    /// The size of the block, in bytes.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of bytes handed out, including alignment padding.
+    pub fn used(&self) -> usize {
+        self.offset.get()
+    }
+
+    /// Make the whole block available again. Taking `&mut self` ensures no
+    /// vector still borrows the Bump.
+    pub fn reset(&mut self) {
+        self.offset.set(0);
+        self.last.set(0);
+    }
+
+    fn block_layout(capacity: usize) -> Layout {
+        Layout::from_size_align(capacity, Self::BLOCK_ALIGN).expect("Bump capacity overflow")
+    }
+
+    // The offset of `ptr` into the block.
+    fn offset_of(&self, ptr: NonNull<u8>) -> usize {
+        ptr.as_ptr() as usize - self.start.as_ptr() as usize
+    }
+}
+
+unsafe impl Allocator for Bump {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        let base = self.start.as_ptr() as usize;
+        let aligned = (base + self.offset.get())
+            .checked_add(layout.align() - 1)
+            .ok_or(AllocError)?
+            & !(layout.align() - 1);
+        let begin = aligned - base;
+        let end = begin.checked_add(layout.size()).ok_or(AllocError)?;
+        if end > self.capacity {
+            return Err(AllocError);
+        }
+
+        self.last.set(begin);
+        self.offset.set(end);
+        // SAFETY: begin < end <= capacity, so the pointer is within the block
+        Ok(unsafe { NonNull::new_unchecked(self.start.as_ptr().add(begin)) })
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
+        // only the most recent allocation can be given back
+        if self.offset_of(ptr) == self.last.get() {
+            self.offset.set(self.last.get());
+        }
+    }
+
+    unsafe fn reallocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        let begin = self.offset_of(ptr);
+        if begin == self.last.get() {
+            // the most recent allocation resizes in place, if it fits
+            let end = begin.checked_add(new_layout.size()).ok_or(AllocError)?;
+            if end > self.capacity {
+                return Err(AllocError);
+            }
+            self.offset.set(end);
+            return Ok(ptr);
+        }
+
+        let new_ptr = self.allocate(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.as_ptr(), old_layout.size().min(new_layout.size()));
+        Ok(new_ptr)
+    }
+}
+
+impl Drop for Bump {
+    fn drop(&mut self) {
+        if self.capacity != 0 {
+            // SAFETY: start was allocated with the block layout
+            unsafe { alloc::dealloc(self.start.as_ptr(), Self::block_layout(self.capacity)) };
+        }
+    }
+}
+
+impl fmt::Debug for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bump")
+            .field("capacity", &self.capacity)
+            .field("used", &self.used())
+            .finish()
+    }
+}
+
+/// An allocator that counts what passes through it to another, for
+/// checking that vectors free everything they allocate.
+#[derive(Debug, Default)]
+pub struct Counting<A = Global> {
+    inner: A,
+    allocations: Cell<usize>,
+    deallocations: Cell<usize>,
+    bytes_in_use: Cell<usize>,
+}
+
+impl<A> Counting<A> {
+    pub fn new(inner: A) -> Self {
+        Self {
+            inner,
+            allocations: Cell::new(0),
+            deallocations: Cell::new(0),
+            bytes_in_use: Cell::new(0),
+        }
+    }
+
+    /// The number of blocks allocated, not counting reallocations.
+    pub fn allocations(&self) -> usize {
+        self.allocations.get()
+    }
+
+    pub fn deallocations(&self) -> usize {
+        self.deallocations.get()
+    }
+
+    /// The number of blocks allocated but not yet freed.
+    pub fn live(&self) -> usize {
+        self.allocations.get() - self.deallocations.get()
+    }
+
+    pub fn bytes_in_use(&self) -> usize {
+        self.bytes_in_use.get()
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for Counting<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        let ptr = self.inner.allocate(layout)?;
+        self.allocations.set(self.allocations.get() + 1);
+        self.bytes_in_use.set(self.bytes_in_use.get() + layout.size());
+        Ok(ptr)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.inner.deallocate(ptr, layout);
+        self.deallocations.set(self.deallocations.get() + 1);
+        self.bytes_in_use.set(self.bytes_in_use.get() - layout.size());
+    }
+
+    unsafe fn reallocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        let new_ptr = self.inner.reallocate(ptr, old_layout, new_layout)?;
+        self.bytes_in_use.set(self.bytes_in_use.get() - old_layout.size() + new_layout.size());
+        Ok(new_ptr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bump1() {
+        let bump = Bump::with_capacity(64);
+        let a = bump.allocate(Layout::new::<u8>()).unwrap();
+        let b = bump.allocate(Layout::new::<u64>()).unwrap();
+        assert_eq!(b.as_ptr() as usize % 8, 0);
+        assert_eq!(bump.used(), 16);
+
+        // only the most recent allocation is given back, or grows in place
+        unsafe {
+            let b = bump.reallocate(b, Layout::new::<u64>(), Layout::new::<[u64; 4]>()).unwrap();
+            assert_eq!(bump.used(), 40);
+            bump.deallocate(a, Layout::new::<u8>());
+            assert_eq!(bump.used(), 40);
+            bump.deallocate(b, Layout::new::<[u64; 4]>());
+            assert_eq!(bump.used(), 8);
+        }
+
+        assert!(bump.allocate(Layout::new::<[u8; 64]>()).is_err());
+        assert!(bump.allocate(Layout::new::<[u8; 56]>()).is_ok());
+    }
+
+    #[test]
+    fn bump2() {
+        let mut bump = Bump::with_capacity(32);
+        let a = bump.allocate(Layout::new::<[u8; 8]>()).unwrap();
+        let _b = bump.allocate(Layout::new::<[u8; 8]>()).unwrap();
+
+        // a can't grow in place, so it moves, keeping its contents
+        unsafe {
+            a.as_ptr().write(42);
+            let a = bump.reallocate(a, Layout::new::<[u8; 8]>(), Layout::new::<[u8; 16]>()).unwrap();
+            assert_eq!(a.as_ptr().read(), 42);
+        }
+        assert_eq!(bump.used(), 32);
+
+        bump.reset();
+        assert_eq!(bump.used(), 0);
+        assert!(Bump::with_capacity(0).allocate(Layout::new::<u8>()).is_err());
+    }
+}
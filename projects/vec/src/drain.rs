@@ -0,0 +1,294 @@
+// drain.rs
+// Iterators that move elements out of a Vector while it is borrowed.
+//
+// Both follow std's design. The vector's length is cut down when the
+// iterator is created, and its remaining elements are only moved back into
+// place when the iterator is dropped. So a leaked (mem::forget) iterator
+// leaks elements, but never exposes one that was moved out or dropped.
+
+use std::iter::FusedIterator;
+use std::ops::{Bound, Range, RangeBounds};
+use std::ptr;
+
+use crate::{Allocator, Global, Vector};
+
+impl<T, A: Allocator> Vector<T, A> {
+    /// Remove the elements in `range`, returning them through an iterator.
+    ///
+    /// Elements not consumed by the iterator are dropped with it. Panics if
+    /// the range is decreasing or extends past the end of the vector.
+    pub fn drain<R>(&mut self, range: R) -> Drain<'_, T, A>
+    where
+        R: RangeBounds<usize>,
+    {
+        let len = self.len;
+        let Range { start, end } = slice_range(range, len);
+
+        // elements from start on are now owned by the Drain
+        self.len = start;
+        Drain {
+            idx: start,
+            end,
+            tail_start: end,
+            tail_len: len - end,
+            vec: self,
+        }
+    }
+
+    /// Remove the elements for which `filter` returns true, returning them
+    /// through an iterator. The filter may mutate the elements it keeps.
+    ///
+    /// Elements not yet visited when the iterator is dropped are filtered
+    /// then. If the filter panics, the remaining elements are kept.
+    pub fn drain_filter<F>(&mut self, filter: F) -> DrainFilter<'_, T, F, A>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let old_len = self.len;
+
+        // elements are owned by the DrainFilter until it is dropped
+        self.len = 0;
+        DrainFilter {
+            vec: self,
+            idx: 0,
+            del: 0,
+            old_len,
+            filter,
+            panic_flag: false,
+        }
+    }
+}
+
+/// Resolve any form of range against a slice of length `len`, panicking
+/// if it is out of bounds.
+pub(crate) fn slice_range<R>(range: R, len: usize) -> Range<usize>
+where
+    R: RangeBounds<usize>,
+{
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start
+            .checked_add(1)
+            .unwrap_or_else(|| panic!("attempted to index slice from after maximum usize")),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end
+            .checked_add(1)
+            .unwrap_or_else(|| panic!("attempted to index slice up to maximum usize")),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+
+    if start > end {
+        panic!("slice index starts at {} but ends at {}", start, end);
+    }
+    if end > len {
+        panic!("range end index {} out of range for slice of length {}", end, len);
+    }
+    start..end
+}
+
+pub struct Drain<'a, T, A: Allocator = Global> {
+    vec: &'a mut Vector<T, A>,
+    // the elements idx..end have yet to be yielded
+    idx: usize,
+    end: usize,
+    // the elements after the drained range, moved back on drop
+    tail_start: usize,
+    tail_len: usize,
+}
+
+impl<'a, T, A: Allocator> Drain<'a, T, A> {
+    /// The elements that have yet to be yielded.
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the elements idx..end are initialized and owned by the Drain
+        unsafe { std::slice::from_raw_parts(self.vec.as_ptr().add(self.idx), self.end - self.idx) }
+    }
+
+    /// Fill the gap between the vector's length and the tail from `items`,
+    /// returning whether the gap was filled.
+    ///
+    /// # Safety
+    /// Every drained element must have been yielded, as the gap overwrites them.
+    pub(crate) unsafe fn fill<I: Iterator<Item = T>>(&mut self, items: &mut I) -> bool {
+        while self.vec.len < self.tail_start {
+            match items.next() {
+                Some(item) => {
+                    // the length is bumped per item, so a panicking
+                    // iterator leaves the vector consistent
+                    ptr::write(self.vec.as_mut_ptr().add(self.vec.len), item);
+                    self.vec.len += 1;
+                },
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Move the tail `additional` slots further back, widening the gap.
+    ///
+    /// # Safety
+    /// As for fill.
+    pub(crate) unsafe fn move_tail(&mut self, additional: usize) {
+        self.vec.buf.reserve(self.tail_start + self.tail_len, additional);
+
+        let ptr = self.vec.as_mut_ptr();
+        let new_tail_start = self.tail_start + additional;
+        ptr::copy(ptr.add(self.tail_start), ptr.add(new_tail_start), self.tail_len);
+        self.tail_start = new_tail_start;
+    }
+}
+
+impl<'a, T, A: Allocator> Iterator for Drain<'a, T, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.idx == self.end {
+            return None;
+        }
+        self.idx += 1;
+        // SAFETY: the element at idx had not been yielded, and now won't be again
+        Some(unsafe { ptr::read(self.vec.as_mut_ptr().add(self.idx - 1)) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.idx;
+        (len, Some(len))
+    }
+}
+
+impl<'a, T, A: Allocator> DoubleEndedIterator for Drain<'a, T, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.idx == self.end {
+            return None;
+        }
+        self.end -= 1;
+        // SAFETY: the element at end had not been yielded, and now won't be again
+        Some(unsafe { ptr::read(self.vec.as_mut_ptr().add(self.end)) })
+    }
+}
+
+impl<'a, T, A: Allocator> ExactSizeIterator for Drain<'a, T, A> {}
+
+impl<'a, T, A: Allocator> FusedIterator for Drain<'a, T, A> {}
+
+impl<'a, T, A: Allocator> Drop for Drain<'a, T, A> {
+    fn drop(&mut self) {
+        // Moves the tail back into place even if dropping one of the
+        // remaining elements panics.
+        struct MoveTail<'r, 'a, T, A: Allocator>(&'r mut Drain<'a, T, A>);
+
+        impl<'r, 'a, T, A: Allocator> Drop for MoveTail<'r, 'a, T, A> {
+            fn drop(&mut self) {
+                let drain = &mut *self.0;
+                let start = drain.vec.len;
+                if drain.tail_len > 0 && drain.tail_start != start {
+                    // SAFETY: the tail is initialized, and the space before
+                    // it was vacated by the drained elements
+                    unsafe {
+                        let ptr = drain.vec.as_mut_ptr();
+                        ptr::copy(ptr.add(drain.tail_start), ptr.add(start), drain.tail_len);
+                    }
+                }
+                drain.vec.len = start + drain.tail_len;
+            }
+        }
+
+        let (idx, end) = (self.idx, self.end);
+        self.idx = end;
+        let ptr = self.vec.as_mut_ptr();
+        let _guard = MoveTail(self);
+        // SAFETY: the elements idx..end were never yielded, and are
+        // forgotten by the Drain before being dropped
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(ptr.add(idx), end - idx));
+        }
+    }
+}
+
+pub struct DrainFilter<'a, T, F, A: Allocator = Global>
+where
+    F: FnMut(&mut T) -> bool,
+{
+    vec: &'a mut Vector<T, A>,
+    // the next element to test
+    idx: usize,
+    // the number of elements removed so far; kept elements are shifted
+    // back over the gap they leave
+    del: usize,
+    old_len: usize,
+    filter: F,
+    // set while the filter runs, so a panic in it isn't followed by
+    // calling it again on drop
+    panic_flag: bool,
+}
+
+impl<'a, T, F, A: Allocator> Iterator for DrainFilter<'a, T, F, A>
+where
+    F: FnMut(&mut T) -> bool,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.idx < self.old_len {
+            // SAFETY: idx < old_len, so the element is initialized, and the
+            // del elements before it have been moved out
+            unsafe {
+                let cur = self.vec.as_mut_ptr().add(self.idx);
+                self.panic_flag = true;
+                let drained = (self.filter)(&mut *cur);
+                self.panic_flag = false;
+
+                self.idx += 1;
+                if drained {
+                    self.del += 1;
+                    return Some(ptr::read(cur));
+                } else if self.del > 0 {
+                    ptr::copy_nonoverlapping(cur, cur.sub(self.del), 1);
+                }
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.old_len - self.idx))
+    }
+}
+
+impl<'a, T, F, A: Allocator> Drop for DrainFilter<'a, T, F, A>
+where
+    F: FnMut(&mut T) -> bool,
+{
+    fn drop(&mut self) {
+        // Closes the gap left by removed elements and restores the length,
+        // whether or not draining the rest panics.
+        struct BackshiftOnDrop<'r, 'a, T, F, A: Allocator>(&'r mut DrainFilter<'a, T, F, A>)
+        where
+            F: FnMut(&mut T) -> bool;
+
+        impl<'r, 'a, T, F, A: Allocator> Drop for BackshiftOnDrop<'r, 'a, T, F, A>
+        where
+            F: FnMut(&mut T) -> bool,
+        {
+            fn drop(&mut self) {
+                let drain = &mut *self.0;
+                if drain.idx < drain.old_len && drain.del > 0 {
+                    // SAFETY: the elements idx..old_len are initialized, and
+                    // the del slots before them were vacated
+                    unsafe {
+                        let src = drain.vec.as_mut_ptr().add(drain.idx);
+                        ptr::copy(src, src.sub(drain.del), drain.old_len - drain.idx);
+                    }
+                }
+                drain.vec.len = drain.old_len - drain.del;
+            }
+        }
+
+        let backshift = BackshiftOnDrop(self);
+        if !backshift.0.panic_flag {
+            backshift.0.for_each(drop);
+        }
+    }
+}
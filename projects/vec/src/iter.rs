@@ -0,0 +1,270 @@
+// iter.rs
+// Iterators over a Vector's elements, by reference and by value.
+//
+// Like std's, each walks a pair of pointers towards each other: next reads
+// the element at ptr and advances it, next_back retreats end and reads the
+// element there. For zero-sized types the pointers can't advance, so end is
+// instead a byte count of the elements left, offset from ptr.
+
+use std::iter::{FromIterator, FusedIterator};
+use std::marker::PhantomData;
+use std::mem::{self, ManuallyDrop};
+use std::ptr;
+use std::slice;
+
+use crate::raw_vec::RawVec;
+use crate::{Allocator, Global, Vector};
+
+// Generates the Iterator impls shared by the three iterators, given how to
+// turn a pointer to an unyielded element into an item.
+macro_rules! iterator {
+    ([$($gen:tt)*] $name:ty, $item:ty, |$p:ident| $make:expr) => {
+        impl<$($gen)*> $name {
+            fn len_remaining(&self) -> usize {
+                if mem::size_of::<T>() == 0 {
+                    self.end as usize - self.ptr as usize
+                } else {
+                    // SAFETY: both pointers are into the same buffer, with
+                    // ptr <= end
+                    unsafe { self.end.offset_from(self.ptr) as usize }
+                }
+            }
+        }
+
+        impl<$($gen)*> Iterator for $name {
+            type Item = $item;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                if self.ptr == self.end {
+                    return None;
+                }
+                // SAFETY: ptr != end, so ptr points at an unyielded element,
+                // which is stepped past before being yielded
+                unsafe {
+                    let $p = if mem::size_of::<T>() == 0 {
+                        self.end = (self.end as *const u8).wrapping_sub(1) as _;
+                        self.ptr
+                    } else {
+                        let old = self.ptr;
+                        self.ptr = self.ptr.add(1);
+                        old
+                    };
+                    Some($make)
+                }
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                let len = self.len_remaining();
+                (len, Some(len))
+            }
+
+            fn count(self) -> usize {
+                self.len_remaining()
+            }
+        }
+
+        impl<$($gen)*> DoubleEndedIterator for $name {
+            fn next_back(&mut self) -> Option<Self::Item> {
+                if self.ptr == self.end {
+                    return None;
+                }
+                // SAFETY: as for next, from the other end
+                unsafe {
+                    let $p = if mem::size_of::<T>() == 0 {
+                        self.end = (self.end as *const u8).wrapping_sub(1) as _;
+                        self.ptr
+                    } else {
+                        self.end = self.end.sub(1);
+                        self.end
+                    };
+                    Some($make)
+                }
+            }
+        }
+
+        impl<$($gen)*> ExactSizeIterator for $name {}
+
+        impl<$($gen)*> FusedIterator for $name {}
+    };
+}
+
+// The end pointer for `len` elements from `ptr`.
+fn end_of<T>(ptr: *const T, len: usize) -> *const T {
+    if mem::size_of::<T>() == 0 {
+        (ptr as *const u8).wrapping_add(len) as *const T
+    } else {
+        // SAFETY: the caller's pointer has len elements following it
+        unsafe { ptr.add(len) }
+    }
+}
+
+pub struct Iter<'a, T> {
+    ptr: *const T,
+    end: *const T,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T> Iter<'a, T> {
+    pub(crate) fn new(slice: &'a [T]) -> Self {
+        Self {
+            ptr: slice.as_ptr(),
+            end: end_of(slice.as_ptr(), slice.len()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// The elements that have yet to be yielded.
+    pub fn as_slice(&self) -> &'a [T] {
+        // SAFETY: the iterator borrows the len_remaining elements from ptr
+        unsafe { slice::from_raw_parts(self.ptr, self.len_remaining()) }
+    }
+}
+
+impl<'a, T> Clone for Iter<'a, T> {
+    fn clone(&self) -> Self {
+        Self {
+            ptr: self.ptr,
+            end: self.end,
+            _marker: PhantomData,
+        }
+    }
+}
+
+// SAFETY: an Iter is a shared borrow of a slice
+unsafe impl<'a, T: Sync> Send for Iter<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for Iter<'a, T> {}
+
+iterator!(['a, T] Iter<'a, T>, &'a T, |p| &*p);
+
+pub struct IterMut<'a, T> {
+    ptr: *mut T,
+    end: *mut T,
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> IterMut<'a, T> {
+    pub(crate) fn new(slice: &'a mut [T]) -> Self {
+        let len = slice.len();
+        let ptr = slice.as_mut_ptr();
+        Self {
+            ptr,
+            end: end_of(ptr, len) as *mut T,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The elements that have yet to be yielded.
+    pub fn into_slice(self) -> &'a mut [T] {
+        // SAFETY: the iterator borrows the len_remaining elements from ptr,
+        // and is consumed to hand them out
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len_remaining()) }
+    }
+}
+
+// SAFETY: an IterMut is a unique borrow of a slice
+unsafe impl<'a, T: Send> Send for IterMut<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for IterMut<'a, T> {}
+
+iterator!(['a, T] IterMut<'a, T>, &'a mut T, |p| &mut *p);
+
+/// An iterator moving the elements out of a Vector. The buffer is freed
+/// when it is dropped, along with any elements not yet yielded.
+pub struct IntoIter<T, A: Allocator = Global> {
+    buf: RawVec<T, A>,
+    ptr: *const T,
+    end: *const T,
+}
+
+impl<T, A: Allocator> IntoIter<T, A> {
+    /// The elements that have yet to be yielded.
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the len_remaining elements from ptr are initialized
+        unsafe { slice::from_raw_parts(self.ptr, self.len_remaining()) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: as for as_slice
+        unsafe { slice::from_raw_parts_mut(self.ptr as *mut T, self.len_remaining()) }
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.buf.allocator()
+    }
+}
+
+// SAFETY: an IntoIter owns its buffer and the elements left in it, like
+// the Vector it came from
+unsafe impl<T: Send, A: Allocator + Send> Send for IntoIter<T, A> {}
+unsafe impl<T: Sync, A: Allocator + Sync> Sync for IntoIter<T, A> {}
+
+iterator!([T, A: Allocator] IntoIter<T, A>, T, |p| ptr::read(p));
+
+impl<T, A: Allocator> Drop for IntoIter<T, A> {
+    fn drop(&mut self) {
+        // SAFETY: the remaining elements are initialized and never yielded;
+        // the buffer frees the memory when it is dropped after this, even if
+        // dropping one of them panics
+        unsafe { ptr::drop_in_place(self.as_mut_slice()) }
+    }
+}
+
+impl<T, A: Allocator> IntoIterator for Vector<T, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let vec = ManuallyDrop::new(self);
+        let ptr = vec.as_ptr();
+        IntoIter {
+            // SAFETY: the vector is never dropped, so the buffer and its
+            // elements are moved into the iterator
+            buf: unsafe { ptr::read(&vec.buf) },
+            ptr,
+            end: end_of(ptr, vec.len),
+        }
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a Vector<T, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a mut Vector<T, A> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<T> FromIterator<T> for Vector<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = Vector::new();
+        vec.extend(iter);
+        vec
+    }
+}
+
+impl<T, A: Allocator> Extend<T> for Vector<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        // the lower bound is a safe guess at how much is coming
+        let (lower, _) = iter.size_hint();
+        self.reserve(lower);
+        for item in iter {
+            self.push(item);
+        }
+    }
+}
+
+impl<'a, T: Copy + 'a, A: Allocator> Extend<&'a T> for Vector<T, A> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
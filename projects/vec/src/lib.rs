@@ -1,101 +1,157 @@
 // lib.rs
 // Re-implementation of std::vec::Vec from Ryan Levick's stream.
 
-use std::mem;
+use std::borrow::{Borrow, BorrowMut};
+use std::ops::{Deref, DerefMut, Index, IndexMut};
 use std::ptr;
-use std::ptr::NonNull;
-use std::{alloc, ops::Bound, ops::RangeBounds};
+use std::slice::{self, SliceIndex};
 
-pub struct Vector<T> {
-    ptr: NonNull<T>,
+pub mod allocator;
+pub mod ring_vector;
+pub mod small_vector;
+
+mod drain;
+mod iter;
+mod raw_vec;
+mod splice;
+
+use raw_vec::RawVec;
+
+pub use allocator::{AllocError, Allocator, Bump, Counting, Global};
+pub use drain::{Drain, DrainFilter};
+pub use iter::{IntoIter, Iter, IterMut};
+pub use raw_vec::TryReserveError;
+pub use ring_vector::RingVector;
+pub use small_vector::SmallVector;
+pub use splice::Splice;
+
+pub struct Vector<T, A: Allocator = Global> {
+    buf: RawVec<T, A>,
     len: usize,
-    capacity: usize,
 }
 
 impl<T> Vector<T> {
     pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, TryReserveError> {
+        Self::try_with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T, A: Allocator> Vector<T, A> {
+    /// Create an empty vector that will allocate from `alloc`.
+    pub fn new_in(alloc: A) -> Self {
         Self {
-            ptr: NonNull::dangling(),
+            buf: RawVec::new_in(alloc),
             len: 0,
-            capacity: 0,
         }
     }
 
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        Self {
+            buf: RawVec::with_capacity_in(capacity, alloc),
+            len: 0,
+        }
+    }
+
+    /// Like with_capacity_in, but reporting failure instead of panicking
+    /// or aborting.
+    pub fn try_with_capacity_in(capacity: usize, alloc: A) -> Result<Self, TryReserveError> {
+        Ok(Self {
+            buf: RawVec::try_with_capacity_in(capacity, alloc)?,
+            len: 0,
+        })
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.buf.allocator()
+    }
+
     pub fn len(&self) -> usize {
         self.len
     }
 
+    /// The number of elements the vector can hold without reallocating;
+    /// usize::MAX for zero-sized types, which never allocate.
     pub fn capacity(&self) -> usize {
-        self.capacity
+        self.buf.capacity()
     }
 
     pub fn is_empty(&self) -> bool {
         self.len == 0
     }
 
-    pub fn push(&mut self, value: T) {
-        assert_ne!(mem::size_of::<T>(), 0, "Zero-size types not supported");
+    /// A pointer to the buffer, which is dangling but aligned if nothing
+    /// has been allocated.
+    pub fn as_ptr(&self) -> *const T {
+        self.buf.ptr()
+    }
 
-        if self.capacity == 0 {
-            // vector must be initialized
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.buf.ptr()
+    }
 
-            let layout = alloc::Layout::array::<T>(4).expect("Failed to allocate");
+    /// Make room for at least `additional` more elements, growing the
+    /// buffer geometrically to keep repeated pushes cheap.
+    pub fn reserve(&mut self, additional: usize) {
+        self.buf.reserve(self.len, additional);
+    }
 
-            // SAFETY: the layout is hardcoded to be 4*size_of<T>, and size_of<T> is nonzero
-            let ptr = unsafe { alloc::alloc(layout) } as *mut T;
-            let ptr = NonNull::new(ptr).expect("Failed to allocate");
+    /// Make room for exactly `additional` more elements.
+    pub fn reserve_exact(&mut self, additional: usize) {
+        self.buf.reserve_exact(self.len, additional);
+    }
 
-            // SAFETY: ptr is non-null, and we have allocated sufficient space for 4 Ts;
-            // the memory (uninitialized) at ptr is not read by the call to ptr::write()
-            unsafe {
-                ptr::write(ptr.as_ptr(), value);
-            }
+    /// Like reserve, but reporting failure instead of panicking or aborting.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.buf.try_reserve(self.len, additional)
+    }
 
-            self.ptr = ptr;
-            self.capacity = 4;
-        } else if self.len < self.capacity {
-            // pushed value fits within existing allocation
+    pub fn try_reserve_exact(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.buf.try_reserve_exact(self.len, additional)
+    }
 
-            let offset = self
-                .len
-                .checked_mul(mem::size_of::<T>())
-                .expect("Memory address overflow");
-            assert!(offset < isize::MAX as usize, "Wrapped isize");
+    /// Free any capacity beyond the current length.
+    pub fn shrink_to_fit(&mut self) {
+        self.buf.shrink_to(self.len);
+    }
 
-            // SAFETY: offset cannot overflow usize, ptr is non-null, and writing to offset at self.len is valid
-            unsafe {
-                ptr::write(self.ptr.as_ptr().add(self.len), value);
-            }
-        } else {
-            // internal buffer must reallocate
-            debug_assert_eq!(self.len, self.capacity);
-
-            let new_capacity = self.capacity.checked_mul(2).expect("Wrapped capacity");
-
-            let size = mem::size_of::<T>() * self.capacity;
-            let align = mem::align_of::<T>();
-            size.checked_add(size % align).expect("Failed to allocate");
-            let ptr = unsafe {
-                let layout = alloc::Layout::from_size_align_unchecked(size, align);
-                let new_size = mem::size_of::<T>() * new_capacity;
-                let ptr = alloc::realloc(self.ptr.as_ptr() as *mut u8, layout, new_size) as *mut T;
-                let ptr = NonNull::new(ptr).expect("Failed to allocate");
-
-                ptr::write(ptr.as_ptr().add(self.len), value);
-                ptr
-            };
-            self.ptr = ptr;
-            self.capacity = new_capacity;
+    pub fn push(&mut self, value: T) {
+        if self.len == self.buf.capacity() {
+            self.buf.reserve(self.len, 1);
+        }
+
+        // SAFETY: len < capacity, so the slot at len is allocated and unused
+        unsafe {
+            ptr::write(self.as_mut_ptr().add(self.len), value);
         }
         self.len += 1;
     }
 
+    /// Like push, but reporting failure to grow instead of panicking or
+    /// aborting. On failure the value is handed back along with the error.
+    pub fn try_push(&mut self, value: T) -> Result<(), (T, TryReserveError)> {
+        if self.len == self.buf.capacity() {
+            if let Err(err) = self.buf.try_reserve(self.len, 1) {
+                return Err((value, err));
+            }
+        }
+        self.push(value);
+        Ok(())
+    }
+
     pub fn pop(&mut self) -> Option<T> {
         match self.len {
             0 => None,
             _ => {
                 self.len -= 1;
-                Some(unsafe { ptr::read(self.ptr.as_ptr().add(self.len)) })
+                Some(unsafe { ptr::read(self.as_mut_ptr().add(self.len)) })
             }
         }
     }
@@ -105,11 +161,11 @@ impl<T> Vector<T> {
 
         let ret = unsafe {
             // read the value out
-            let tmp = ptr::read(self.ptr.as_ptr().add(index));
+            let tmp = ptr::read(self.as_mut_ptr().add(index));
             // shift the remaining items down
             ptr::copy(
-                self.ptr.as_ptr().add(index + 1),
-                self.ptr.as_ptr().add(index),
+                self.as_mut_ptr().add(index + 1),
+                self.as_mut_ptr().add(index),
                 self.len - index - 1,
             );
             tmp
@@ -119,142 +175,322 @@ impl<T> Vector<T> {
         ret
     }
 
-    pub fn get(&self, index: usize) -> Option<&T> {
-        if index >= self.len {
-            return None;
+    /// Insert `value` at `index`, shifting the elements after it up.
+    pub fn insert(&mut self, index: usize, value: T) {
+        let len = self.len;
+        assert!(index <= len, "insertion index (is {}) should be <= len (is {})", index, len);
+
+        self.reserve(1);
+        // SAFETY: there is room for len + 1 elements, and the slot at index
+        // is overwritten once the elements from it on are moved up
+        unsafe {
+            let ptr = self.as_mut_ptr().add(index);
+            ptr::copy(ptr, ptr.add(1), len - index);
+            ptr::write(ptr, value);
         }
+        self.len = len + 1;
+    }
 
-        // SAFETY: T at index indicated by index is valid
-        unsafe { self.ptr.as_ptr().add(index).as_ref() }
+    /// Remove the element at `index`, replacing it with the last element.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        let len = self.len;
+        assert!(index < len, "swap_remove index (is {}) should be < len (is {})", index, len);
+
+        // SAFETY: index and len - 1 are in bounds, and the last slot is
+        // vacated by shortening the vector
+        unsafe {
+            let ptr = self.as_mut_ptr();
+            let value = ptr::read(ptr.add(index));
+            ptr::copy(ptr.add(len - 1), ptr.add(index), 1);
+            self.len = len - 1;
+            value
+        }
     }
 
-    pub fn get_mut(&self, index: usize) -> Option<&mut T> {
-        if index >= self.len {
-            return None;
+    /// Drop the elements past the first `len`; a no-op if there are none.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+
+        let remaining = self.len - len;
+        // shorten first, so a panicking destructor can't cause a double drop
+        self.len = len;
+        // SAFETY: the elements past len are initialized and no longer
+        // reachable through the vector
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.as_mut_ptr().add(len), remaining));
         }
-        // SAFETY: T at index indicated by index is valid
-        unsafe { self.ptr.as_ptr().add(index).as_mut() }
     }
 
-    pub fn iter(&self) -> Iter<'_, T> {
-        Iter::new(&self)
+    pub fn clear(&mut self) {
+        self.truncate(0);
     }
 
-    pub fn drain<R>(&mut self, range: R) -> Drain<'_, T>
+    /// Resize to `new_len`, filling new slots with the results of `f`.
+    pub fn resize_with<F>(&mut self, new_len: usize, mut f: F)
     where
-        R: RangeBounds<usize>,
+        F: FnMut() -> T,
     {
-        let begin = match range.start_bound() {
-            Bound::Included(&i) => i,
-            Bound::Unbounded => 0,
-            _ => panic!("Malformed RangeBounds in drain()"),
-        };
-        let end = match range.end_bound() {
-            Bound::Excluded(&i) => i,
-            Bound::Unbounded => self.len,
-            _ => panic!("Malformed RangeBounds in drain()"),
-        };
+        if new_len <= self.len {
+            self.truncate(new_len);
+            return;
+        }
 
-        println!("{} {}", begin, end);
-        println!("{}", end - begin);
-        Drain {
-            idx: begin,
-            cur: 0,
-            len: end - begin,
-            vec: self,
+        self.reserve(new_len - self.len);
+        while self.len < new_len {
+            // pushing within capacity keeps the length right if f panics
+            self.push(f());
+        }
+    }
+
+    /// Move all of `other`'s elements to the end of this vector.
+    pub fn append(&mut self, other: &mut Self) {
+        let count = other.len;
+        self.reserve(count);
+        // SAFETY: there is room for count more elements, and other gives up
+        // ownership of them by having its length zeroed
+        unsafe {
+            ptr::copy_nonoverlapping(other.as_ptr(), self.as_mut_ptr().add(self.len), count);
         }
+        other.len = 0;
+        self.len += count;
     }
 
-    pub fn drain_filter<F>(&mut self, filter: F) -> DrainFilter<'_, T, F>
+    /// Keep only the elements for which `f` returns true, in order.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.retain_mut(|x| f(x));
+    }
+
+    /// Keep only the elements for which `f` returns true, in order; `f` may
+    /// mutate the elements it keeps.
+    ///
+    /// If `f` panics, the elements not yet visited are kept.
+    pub fn retain_mut<F>(&mut self, mut f: F)
     where
         F: FnMut(&mut T) -> bool,
     {
-        DrainFilter {idx: 0, filter, vec: self}
+        self.drain_filter(|x| !f(x)).for_each(drop);
     }
-}
 
-impl<T> Drop for Vector<T> {
-    fn drop(&mut self) {
-        if self.capacity == 0 {
+    /// Remove consecutive elements that map to the same key.
+    pub fn dedup_by_key<K, F>(&mut self, mut key: F)
+    where
+        K: PartialEq,
+        F: FnMut(&mut T) -> K,
+    {
+        self.dedup_by(|a, b| key(a) == key(b));
+    }
+
+    /// Remove consecutive elements for which `same_bucket(element, previous)`
+    /// returns true, keeping the first of each run.
+    pub fn dedup_by<F>(&mut self, mut same_bucket: F)
+    where
+        F: FnMut(&mut T, &mut T) -> bool,
+    {
+        // Closes the gap between the kept and unvisited elements, whether or
+        // not same_bucket or a destructor panics.
+        struct FillGapOnDrop<'a, T, A: Allocator> {
+            // the next element to visit
+            read: usize,
+            // the next slot to move a kept element into
+            write: usize,
+            vec: &'a mut Vector<T, A>,
+        }
+
+        impl<'a, T, A: Allocator> Drop for FillGapOnDrop<'a, T, A> {
+            fn drop(&mut self) {
+                let len = self.vec.len;
+                // SAFETY: the elements read..len are initialized, and the
+                // slots from write up to read are vacant
+                unsafe {
+                    let ptr = self.vec.as_mut_ptr();
+                    ptr::copy(ptr.add(self.read), ptr.add(self.write), len - self.read);
+                }
+                self.vec.len = self.write + len - self.read;
+            }
+        }
+
+        let len = self.len;
+        if len <= 1 {
             return;
         }
 
+        let ptr = self.as_mut_ptr();
+        let mut gap = FillGapOnDrop { read: 1, write: 1, vec: self };
+        // SAFETY: write <= read < len, the elements before write are kept and
+        // those from read on are unvisited
         unsafe {
-            // drop all of the values in the vector
-            std::ptr::drop_in_place(std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len));
+            while gap.read < len {
+                let read_ptr = ptr.add(gap.read);
+                let prev_ptr = ptr.add(gap.write - 1);
+                if same_bucket(&mut *read_ptr, &mut *prev_ptr) {
+                    // step past it first, so a panicking drop doesn't drop it twice
+                    gap.read += 1;
+                    ptr::drop_in_place(read_ptr);
+                } else {
+                    ptr::copy(read_ptr, ptr.add(gap.write), 1);
+                    gap.write += 1;
+                    gap.read += 1;
+                }
+            }
+        }
+        // with read == len, dropping the guard just sets the length
+    }
 
-            // deallocate the memory used by the vector
-            let layout = alloc::Layout::from_size_align_unchecked(
-                mem::size_of::<T>() * self.capacity,
-                mem::align_of::<T>(),
-            );
-            alloc::dealloc(self.ptr.as_ptr() as *mut u8, layout);
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(self.as_slice())
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut::new(self.as_mut_slice())
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the first len elements are initialized, and the pointer is
+        // non-null and aligned even when nothing is allocated
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: as for as_slice, and the slice borrows the vector mutably
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), self.len) }
+    }
+}
+
+impl<T, A: Allocator + Clone> Vector<T, A> {
+    /// Split the vector at `at`, returning the elements from `at` on.
+    pub fn split_off(&mut self, at: usize) -> Self {
+        let len = self.len;
+        assert!(at <= len, "`at` split index (is {}) should be <= len (is {})", at, len);
+
+        let count = len - at;
+        let mut other = Self::with_capacity_in(count, self.allocator().clone());
+        // SAFETY: other has room for count elements, and this vector gives
+        // up ownership of them by being shortened
+        unsafe {
+            ptr::copy_nonoverlapping(self.as_ptr().add(at), other.as_mut_ptr(), count);
         }
+        self.len = at;
+        other.len = count;
+        other
     }
 }
 
-pub struct Iter<'a, T> {
-    vec: &'a Vector<T>,
-    idx: usize,
+impl<T: PartialEq, A: Allocator> Vector<T, A> {
+    /// Remove consecutive equal elements.
+    pub fn dedup(&mut self) {
+        self.dedup_by(|a, b| a == b);
+    }
 }
 
-impl<'a, T> Iter<'a, T> {
-    fn new(vec: &'a Vector<T>) -> Self {
-        Self { vec, idx: 0 }
+impl<T: Clone, A: Allocator> Vector<T, A> {
+    /// Resize to `new_len`, filling new slots with clones of `value`.
+    pub fn resize(&mut self, new_len: usize, value: T) {
+        if new_len <= self.len {
+            self.truncate(new_len);
+            return;
+        }
+
+        self.reserve(new_len - self.len);
+        while self.len + 1 < new_len {
+            self.push(value.clone());
+        }
+        self.push(value);
+    }
+
+    /// Append clones of every element of `other`.
+    pub fn extend_from_slice(&mut self, other: &[T]) {
+        self.reserve(other.len());
+        self.extend_from_slice_within_capacity(other);
+    }
+
+    /// Like extend_from_slice, but reporting failure to grow instead of
+    /// panicking or aborting. Nothing is appended on failure.
+    pub fn try_extend_from_slice(&mut self, other: &[T]) -> Result<(), TryReserveError> {
+        self.try_reserve(other.len())?;
+        self.extend_from_slice_within_capacity(other);
+        Ok(())
+    }
+
+    fn extend_from_slice_within_capacity(&mut self, other: &[T]) {
+        debug_assert!(self.capacity() - self.len >= other.len());
+        for value in other {
+            // pushing within capacity keeps the length right if clone panics
+            self.push(value.clone());
+        }
     }
 }
 
-impl<'a, T> Iterator for Iter<'a, T> {
-    type Item = &'a T;
-    fn next(&mut self) -> Option<Self::Item> {
-        let tmp = self.idx;
-        self.idx += 1;
-        self.vec.get(tmp)
+impl<T, A: Allocator + Default> Default for Vector<T, A> {
+    fn default() -> Self {
+        Self::new_in(A::default())
     }
 }
 
-pub struct Drain<'a, T> {
-    idx: usize,
-    cur: usize,
-    len: usize,
-    vec: &'a mut Vector<T>,
+impl<T, A: Allocator> Deref for Vector<T, A> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
 }
 
-impl<'a, T> Iterator for Drain<'a, T> {
-    type Item = T;
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.cur < self.len {
-            self.cur += 1;
-            Some(self.vec.remove(self.idx))
-        } else {
-            None
-        }
+impl<T, A: Allocator> DerefMut for Vector<T, A> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
     }
 }
 
-pub struct DrainFilter<'a, T, F> {
-    idx: usize,
-    filter: F,
-    vec: &'a mut Vector<T>,
+impl<T, A: Allocator> AsRef<[T]> for Vector<T, A> {
+    fn as_ref(&self) -> &[T] {
+        self
+    }
 }
 
-impl<'a, T, F> Iterator for DrainFilter<'a, T, F>
-where
-    F: FnMut(&mut T) -> bool,
-{
-    type Item = T;
-    fn next(&mut self) -> Option<Self::Item> {
-        let func = &mut self.filter;
-        while self.idx < self.vec.len() {
-            if func(self.vec.get_mut(self.idx).unwrap()) {
-                let tmp = self.idx;
-                self.idx += 1;
-                return Some(self.vec.remove(tmp));
-            }
-            self.idx += 1;
-        }
+impl<T, A: Allocator> AsMut<[T]> for Vector<T, A> {
+    fn as_mut(&mut self) -> &mut [T] {
+        self
+    }
+}
+
+impl<T, A: Allocator> Borrow<[T]> for Vector<T, A> {
+    fn borrow(&self) -> &[T] {
+        self
+    }
+}
+
+impl<T, A: Allocator> BorrowMut<[T]> for Vector<T, A> {
+    fn borrow_mut(&mut self) -> &mut [T] {
+        self
+    }
+}
+
+// Covers usize and every range type, as for slices.
+impl<T, I: SliceIndex<[T]>, A: Allocator> Index<I> for Vector<T, A> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        Index::index(self.as_slice(), index)
+    }
+}
+
+impl<T, I: SliceIndex<[T]>, A: Allocator> IndexMut<I> for Vector<T, A> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        IndexMut::index_mut(self.as_mut_slice(), index)
+    }
+}
 
-        None
+impl<T, A: Allocator> Drop for Vector<T, A> {
+    fn drop(&mut self) {
+        // SAFETY: the first len elements are initialized; the buffer frees
+        // the memory itself
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.as_mut_ptr(), self.len));
+        }
     }
 }
 
@@ -362,6 +598,107 @@ mod tests {
         assert_eq!(std, vec![0, 1, 2, 3, 4]);
     }
 
+    #[test]
+    fn iter3() {
+        let vec: Vector<i32> = (0..5).collect();
+        let mut iter = vec.iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next(), Some(&0));
+        assert_eq!(iter.next_back(), Some(&4));
+        assert_eq!(iter.as_slice(), [1, 2, 3]);
+        assert!(iter.clone().rev().eq(&[3, 2, 1]));
+        assert_eq!(iter.by_ref().count(), 3);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        let mut vec = vec;
+        for x in &mut vec {
+            *x *= 10;
+        }
+        vec.iter_mut().rev().take(2).for_each(|x| *x += 1);
+        assert_eq!(vec[..], [0, 10, 20, 31, 41]);
+        assert_eq!((&vec).into_iter().len(), 5);
+    }
+
+    #[test]
+    fn iter4() {
+        // zero-sized elements don't move the pointers
+        let mut vec = Vector::new();
+        vec.extend((0..10).map(|_| ()));
+        let mut iter = vec.iter_mut();
+        assert_eq!(iter.next(), Some(&mut ()));
+        assert_eq!(iter.next_back(), Some(&mut ()));
+        assert_eq!(iter.len(), 8);
+        assert_eq!(iter.into_slice().len(), 8);
+
+        let mut iter = vec.into_iter();
+        assert_eq!(iter.next_back(), Some(()));
+        assert_eq!(iter.as_slice().len(), 9);
+        assert_eq!(iter.count(), 9);
+    }
+
+    #[test]
+    fn into_iter1() {
+        let vec: Vector<String> = (0..6).map(|i| i.to_string()).collect();
+        let mut iter = vec.into_iter();
+        assert_eq!(iter.next().as_deref(), Some("0"));
+        assert_eq!(iter.next_back().as_deref(), Some("5"));
+        assert_eq!(iter.size_hint(), (4, Some(4)));
+        iter.as_mut_slice()[0].push('!');
+        assert_eq!(iter.as_slice(), ["1!", "2", "3", "4"]);
+
+        let rest: Vector<_> = iter.rev().collect();
+        assert_eq!(rest[..], ["4", "3", "2", "1!"]);
+    }
+
+    #[test]
+    fn into_iter2() {
+        // dropping the iterator drops the rest and frees the buffer
+        let counting = Counting::new(Global);
+        let token = std::rc::Rc::new(());
+        let mut vec = Vector::new_in(&counting);
+        vec.extend((0..5).map(|_| token.clone()));
+
+        let mut iter = vec.into_iter();
+        drop(iter.next());
+        drop(iter.next_back());
+        assert_eq!(std::rc::Rc::strong_count(&token), 4);
+        drop(iter);
+        assert_eq!(std::rc::Rc::strong_count(&token), 1);
+        assert_eq!(counting.live(), 0);
+    }
+
+    #[test]
+    fn into_iter3() {
+        // the iterators can cross threads whenever the vector can
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Vector<String>>();
+        assert_send_sync::<IntoIter<String>>();
+        assert_send_sync::<Iter<'_, String>>();
+        assert_send_sync::<IterMut<'_, String>>();
+
+        let vec: Vector<String> = (0..4).map(|i| i.to_string()).collect();
+        let iter = vec.into_iter();
+        let joined = std::thread::spawn(move || iter.collect::<Vec<_>>().concat());
+        assert_eq!(joined.join().unwrap(), "0123");
+    }
+
+    #[test]
+    fn extend1() {
+        let mut vec: Vector<i32> = std::iter::empty().collect();
+        assert_eq!(vec.capacity(), 0);
+
+        vec.extend(0..3);
+        vec.extend(&[3, 4]);
+        vec.extend(from_range(5..8));
+        assert_eq!(vec[..], [0, 1, 2, 3, 4, 5, 6, 7]);
+
+        // filter's lower bound is 0, so this grows by pushing
+        vec.extend((8..100).filter(|x| x % 2 == 0));
+        assert_eq!(vec.len(), 54);
+        assert_eq!(vec.last(), Some(&98));
+    }
+
     #[test]
     fn drain1() {
         let mut vec = Vector::<i32>::new();
@@ -408,4 +745,630 @@ mod tests {
         assert_eq!(drained, vec![0, 2, 4]);
         assert_eq!(remaining, vec![1, 3]);
     }
+
+    #[test]
+    fn zst1() {
+        let mut vec = Vector::<()>::new();
+        assert_eq!(vec.capacity(), usize::MAX);
+
+        for _ in 0..1000 {
+            vec.push(());
+        }
+
+        assert_eq!(vec.len(), 1000);
+        assert_eq!(vec.capacity(), usize::MAX);
+        assert_eq!(vec.iter().count(), 1000);
+        assert_eq!(vec.remove(500), ());
+        assert_eq!(vec.pop(), Some(()));
+        assert_eq!(vec.len(), 998);
+        assert_eq!(vec.drain(..10).count(), 10);
+        assert_eq!(vec.len(), 988);
+    }
+
+    #[test]
+    fn zst2() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct Marker;
+
+        impl Drop for Marker {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let mut vec = Vector::new();
+        for _ in 0..10 {
+            vec.push(Marker);
+        }
+        drop(vec.pop());
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+        drop(vec);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn align1() {
+        #[repr(align(64))]
+        #[derive(Debug, PartialEq)]
+        struct Aligned(u8);
+
+        let mut vec = Vector::new();
+        for i in 0..100 {
+            vec.push(Aligned(i));
+            // every reallocation keeps the alignment
+            assert_eq!(vec.as_ptr() as usize % 64, 0);
+            assert_eq!(&vec[i as usize] as *const Aligned as usize % 64, 0);
+        }
+
+        for i in 0..100 {
+            assert_eq!(vec.get(i as usize), Some(&Aligned(i)));
+        }
+        assert_eq!(vec.remove(0), Aligned(0));
+        assert_eq!(vec.pop(), Some(Aligned(99)));
+    }
+
+    fn from_range(range: std::ops::Range<i32>) -> Vector<i32> {
+        let mut vec = Vector::new();
+        for i in range {
+            vec.push(i);
+        }
+        vec
+    }
+
+    fn to_vec<T: Clone>(vec: &Vector<T>) -> Vec<T> {
+        vec.iter().cloned().collect()
+    }
+
+    #[test]
+    fn drain4() {
+        use std::ops::Bound;
+
+        let mut vec = from_range(0..10);
+        let res: Vec<_> = vec.drain(2..=4).collect();
+        assert_eq!(res, vec![2, 3, 4]);
+        assert_eq!(to_vec(&vec), vec![0, 1, 5, 6, 7, 8, 9]);
+
+        let res: Vec<_> = vec.drain((Bound::Excluded(0), Bound::Included(1))).collect();
+        assert_eq!(res, vec![1]);
+        assert_eq!(to_vec(&vec), vec![0, 5, 6, 7, 8, 9]);
+
+        let res: Vec<_> = vec.drain(..).rev().collect();
+        assert_eq!(res, vec![9, 8, 7, 6, 5, 0]);
+        assert!(vec.is_empty());
+        assert_eq!(vec.drain(0..0).len(), 0);
+    }
+
+    #[test]
+    fn drain5() {
+        // dropping a partly consumed drain drops the rest and moves the tail
+        let token = std::rc::Rc::new(());
+        let mut vec = Vector::new();
+        for _ in 0..10 {
+            vec.push(token.clone());
+        }
+
+        let mut drain = vec.drain(2..8);
+        assert_eq!(drain.len(), 6);
+        drop(drain.next());
+        drop(drain.next_back());
+        assert_eq!(drain.as_slice().len(), 4);
+        drop(drain);
+
+        assert_eq!(vec.len(), 4);
+        assert_eq!(std::rc::Rc::strong_count(&token), 5);
+    }
+
+    #[test]
+    fn drain6() {
+        // a leaked drain leaves the elements before the range
+        let mut vec = from_range(0..10);
+        std::mem::forget(vec.drain(3..5));
+        assert_eq!(to_vec(&vec), vec![0, 1, 2]);
+        vec.push(3);
+        assert_eq!(to_vec(&vec), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn drain7() {
+        use std::panic::{self, AssertUnwindSafe};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct Bomb(bool);
+
+        impl Drop for Bomb {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+                if self.0 {
+                    panic!("bomb");
+                }
+            }
+        }
+
+        let mut vec = Vector::new();
+        for i in 0..6 {
+            vec.push(Bomb(i == 2));
+        }
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| drop(vec.drain(1..4))));
+        assert!(res.is_err());
+        // the drained elements are all dropped, and the tail is moved back
+        assert_eq!(vec.len(), 3);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 3);
+        drop(vec);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    #[should_panic(expected = "range end index 6 out of range for slice of length 5")]
+    fn drain8() {
+        from_range(0..5).drain(2..=5);
+    }
+
+    #[test]
+    #[should_panic(expected = "slice index starts at 3 but ends at 2")]
+    fn drain9() {
+        #[allow(clippy::reversed_empty_ranges)]
+        from_range(0..5).drain(3..2);
+    }
+
+    #[test]
+    fn drain_filter2() {
+        // dropping the iterator filters the elements not yet visited
+        let mut vec = from_range(0..10);
+        let mut drain = vec.drain_filter(|x| *x % 3 == 0);
+        assert_eq!(drain.next(), Some(0));
+        drop(drain);
+        assert_eq!(to_vec(&vec), vec![1, 2, 4, 5, 7, 8]);
+
+        std::mem::forget(vec.drain_filter(|_| true));
+        assert!(vec.is_empty());
+    }
+
+    #[test]
+    fn drain_filter3() {
+        use std::panic::{self, AssertUnwindSafe};
+        use std::rc::Rc;
+
+        let token = Rc::new(());
+        let mut vec = Vector::new();
+        for i in 0..10 {
+            vec.push((i, token.clone()));
+        }
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            vec.drain_filter(|(i, _)| {
+                assert!(*i < 5, "filter panicked");
+                *i % 2 == 0
+            })
+            .for_each(drop);
+        }));
+        assert!(res.is_err());
+
+        // the evens before the panic are gone, everything else is kept
+        let remaining: Vec<_> = vec.iter().map(|(i, _)| *i).collect();
+        assert_eq!(remaining, vec![1, 3, 5, 6, 7, 8, 9]);
+        assert_eq!(Rc::strong_count(&token), 8);
+    }
+
+    #[test]
+    fn slice1() {
+        let mut vec = from_range(0..10);
+        vec.reverse();
+        assert_eq!(vec[0], 9);
+        assert_eq!(&vec[2..5], [7, 6, 5]);
+        assert_eq!(&vec[..=1], [9, 8]);
+        assert_eq!(vec[8..].len(), 2);
+
+        vec.sort_unstable();
+        assert_eq!(vec.binary_search(&4), Ok(4));
+        assert_eq!(vec.windows(2).filter(|w| w[0] < w[1]).count(), 9);
+        assert_eq!(vec.chunks(4).count(), 3);
+
+        vec[3] = 30;
+        vec[..2].copy_from_slice(&[10, 20]);
+        assert_eq!(&vec[..4], [10, 20, 2, 30]);
+        if let Some(x) = vec.get_mut(9) {
+            *x = 90;
+        }
+        assert_eq!(vec.last(), Some(&90));
+    }
+
+    #[test]
+    fn slice2() {
+        // the vector can be handed to anything taking a slice
+        fn sort<T: Ord>(slice: &mut [T]) {
+            slice.sort();
+        }
+
+        fn total<S: Borrow<[i32]>>(values: S) -> i32 {
+            values.borrow().iter().sum()
+        }
+
+        let mut vec = from_range(0..5);
+        vec.reverse();
+        sort(&mut vec);
+        sort(vec.as_mut());
+        assert_eq!(vec.as_ref(), [0, 1, 2, 3, 4]);
+        assert_eq!(total(vec), 10);
+
+        let empty = Vector::<String>::new();
+        assert!(empty.as_slice().is_empty());
+        assert_eq!(Vector::<()>::new().as_slice(), []);
+    }
+
+    #[test]
+    #[should_panic]
+    fn slice3() {
+        let vec = from_range(0..3);
+        let _ = vec[3];
+    }
+
+    #[test]
+    fn capacity1() {
+        let mut vec = Vector::<u64>::with_capacity(10);
+        assert_eq!(vec.capacity(), 10);
+        vec.reserve(5);
+        assert_eq!(vec.capacity(), 10);
+        vec.extend_from_slice(&[1; 10]);
+        vec.reserve(1);
+        assert_eq!(vec.capacity(), 20);
+        vec.reserve_exact(15);
+        assert_eq!(vec.capacity(), 25);
+
+        vec.shrink_to_fit();
+        assert_eq!(vec.capacity(), 10);
+        vec.clear();
+        vec.shrink_to_fit();
+        assert_eq!(vec.capacity(), 0);
+        vec.push(1);
+        assert_eq!(vec.capacity(), 4);
+        assert_eq!(Vector::<()>::with_capacity(3).capacity(), usize::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity overflow")]
+    fn capacity2() {
+        Vector::<u64>::new().reserve(usize::MAX / 4);
+    }
+
+    #[test]
+    fn insert1() {
+        let mut vec = from_range(0..3);
+        vec.insert(0, 10);
+        vec.insert(4, 20);
+        vec.insert(2, 30);
+        assert_eq!(to_vec(&vec), vec![10, 0, 30, 1, 2, 20]);
+
+        assert_eq!(vec.swap_remove(0), 10);
+        assert_eq!(vec.swap_remove(4), 2);
+        assert_eq!(to_vec(&vec), vec![20, 0, 30, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "insertion index (is 4) should be <= len (is 3)")]
+    fn insert2() {
+        from_range(0..3).insert(4, 0);
+    }
+
+    #[test]
+    fn truncate1() {
+        let token = std::rc::Rc::new(());
+        let mut vec = Vector::new();
+        vec.resize(10, token.clone());
+        assert_eq!(std::rc::Rc::strong_count(&token), 11);
+        vec.truncate(20);
+        vec.truncate(4);
+        assert_eq!(vec.len(), 4);
+        assert_eq!(std::rc::Rc::strong_count(&token), 5);
+        vec.clear();
+        assert!(vec.is_empty());
+        assert_eq!(std::rc::Rc::strong_count(&token), 1);
+    }
+
+    #[test]
+    fn resize1() {
+        let mut vec = from_range(0..3);
+        vec.resize(5, 7);
+        assert_eq!(to_vec(&vec), vec![0, 1, 2, 7, 7]);
+        vec.resize(2, 7);
+        assert_eq!(to_vec(&vec), vec![0, 1]);
+
+        let mut next = 10;
+        vec.resize_with(4, || {
+            next += 1;
+            next
+        });
+        assert_eq!(to_vec(&vec), vec![0, 1, 11, 12]);
+    }
+
+    // Clones until the countdown runs out, then panics.
+    struct Fuse(std::rc::Rc<std::cell::Cell<usize>>);
+
+    impl Clone for Fuse {
+        fn clone(&self) -> Self {
+            let left = self.0.get();
+            assert!(left > 0, "fuse blew");
+            self.0.set(left - 1);
+            Fuse(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn resize2() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let countdown = std::rc::Rc::new(std::cell::Cell::new(3));
+        let mut vec = Vector::new();
+        let res = panic::catch_unwind(AssertUnwindSafe(|| vec.resize(10, Fuse(countdown.clone()))));
+        assert!(res.is_err());
+        assert_eq!(vec.len(), 3);
+
+        countdown.set(2);
+        let source = [Fuse(countdown.clone()), Fuse(countdown.clone()), Fuse(countdown.clone())];
+        let res = panic::catch_unwind(AssertUnwindSafe(|| vec.extend_from_slice(&source)));
+        assert!(res.is_err());
+        assert_eq!(vec.len(), 5);
+        drop(vec);
+        drop(source);
+        assert_eq!(std::rc::Rc::strong_count(&countdown), 1);
+    }
+
+    #[test]
+    fn append1() {
+        let mut a = from_range(0..3);
+        let mut b = from_range(3..7);
+        a.append(&mut b);
+        assert!(b.is_empty());
+        assert_eq!(to_vec(&a), vec![0, 1, 2, 3, 4, 5, 6]);
+
+        let c = a.split_off(5);
+        assert_eq!(to_vec(&a), vec![0, 1, 2, 3, 4]);
+        assert_eq!(to_vec(&c), vec![5, 6]);
+        assert!(a.split_off(5).is_empty());
+        assert_eq!(a.split_off(0).len(), 5);
+        assert!(a.is_empty());
+    }
+
+    #[test]
+    fn retain1() {
+        let mut vec = from_range(0..10);
+        vec.retain(|x| x % 3 != 0);
+        assert_eq!(to_vec(&vec), vec![1, 2, 4, 5, 7, 8]);
+
+        vec.retain_mut(|x| {
+            *x *= 10;
+            *x < 60
+        });
+        assert_eq!(to_vec(&vec), vec![10, 20, 40, 50]);
+    }
+
+    #[test]
+    fn dedup1() {
+        let mut vec = Vector::new();
+        vec.extend_from_slice(&[1, 1, 2, 3, 3, 3, 1, 4, 4]);
+        vec.dedup();
+        assert_eq!(to_vec(&vec), vec![1, 2, 3, 1, 4]);
+
+        let mut vec = from_range(0..20);
+        vec.dedup_by_key(|x| *x / 5);
+        assert_eq!(to_vec(&vec), vec![0, 5, 10, 15]);
+    }
+
+    #[test]
+    fn dedup2() {
+        use std::panic::{self, AssertUnwindSafe};
+        use std::rc::Rc;
+
+        let token = Rc::new(());
+        let mut vec = Vector::new();
+        for i in 0..10 {
+            vec.push((i / 2, token.clone()));
+        }
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            vec.dedup_by(|a, b| {
+                assert!(a.0 < 3, "same_bucket panicked");
+                a.0 == b.0
+            })
+        }));
+        assert!(res.is_err());
+
+        // the duplicates before the panic are gone, everything else is kept
+        let remaining: Vec<_> = vec.iter().map(|(i, _)| *i).collect();
+        assert_eq!(remaining, vec![0, 1, 2, 3, 3, 4, 4]);
+        assert_eq!(Rc::strong_count(&token), 8);
+    }
+
+    #[test]
+    fn splice1() {
+        let mut vec = from_range(0..6);
+        let removed: Vec<_> = vec.splice(1..3, vec![10, 20, 30]).collect();
+        assert_eq!(removed, vec![1, 2]);
+        assert_eq!(to_vec(&vec), vec![0, 10, 20, 30, 3, 4, 5]);
+
+        // shorter replacement, left unconsumed
+        vec.splice(1..5, Some(40));
+        assert_eq!(to_vec(&vec), vec![0, 40, 4, 5]);
+
+        // no size_hint, at the end
+        vec.splice(4.., (0..5).filter(|x| x % 2 == 0));
+        assert_eq!(to_vec(&vec), vec![0, 40, 4, 5, 0, 2, 4]);
+
+        // an underestimating size_hint
+        vec.splice(..1, (0..5).chain((5..8).filter(|_| true)));
+        assert_eq!(to_vec(&vec), vec![0, 1, 2, 3, 4, 5, 6, 7, 40, 4, 5, 0, 2, 4]);
+    }
+
+    #[test]
+    fn splice2() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        // a panicking replacement leaves the tail in place after what was inserted
+        let mut vec = from_range(0..5);
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            vec.splice(1..2, (10..20).inspect(|&x| assert!(x < 12, "replacement panicked")));
+        }));
+        assert!(res.is_err());
+        assert_eq!(to_vec(&vec), vec![0, 10, 11, 2, 3, 4]);
+    }
+
+    #[test]
+    fn allocator1() {
+        let counting = Counting::new(Global);
+        {
+            let mut vec = Vector::new_in(&counting);
+            for i in 0..100 {
+                vec.push(i.to_string());
+            }
+            assert_eq!(counting.live(), 1);
+            assert_eq!(counting.bytes_in_use(), vec.capacity() * std::mem::size_of::<String>());
+
+            vec.remove(3);
+            vec.insert(0, "a".to_string());
+            vec.drain(10..20).take(3).for_each(drop);
+            vec.drain_filter(|s| s.len() == 1).for_each(drop);
+            vec.splice(..5, vec!["b".to_string(); 50]);
+            vec.shrink_to_fit();
+            assert_eq!(counting.bytes_in_use(), vec.len() * std::mem::size_of::<String>());
+
+            // the split-off half allocates from the same allocator
+            let other = vec.split_off(5);
+            assert_eq!(other.len(), 120);
+            assert_eq!(counting.live(), 2);
+        }
+        assert_eq!(counting.live(), 0);
+        assert_eq!(counting.bytes_in_use(), 0);
+
+        let mut vec = Vector::new_in(&counting);
+        for _ in 0..100 {
+            vec.push(());
+        }
+        vec.shrink_to_fit();
+        assert_eq!(counting.allocations(), 2);
+    }
+
+    #[test]
+    fn allocator2() {
+        let bump = Bump::with_capacity(4096);
+        let mut a = Vector::new_in(&bump);
+        for i in 0..100u32 {
+            a.push(i);
+        }
+        // the only allocation grows in place
+        assert_eq!(bump.used(), a.capacity() * 4);
+
+        let mut b = Vector::new_in(&bump);
+        b.extend_from_slice(&[1u32, 2, 3]);
+        for i in 100..200 {
+            a.push(i);
+        }
+        assert_eq!(a.len(), 200);
+        assert!(a.iter().copied().eq(0..200));
+        assert_eq!(b.as_slice(), [1, 2, 3]);
+
+        drop(a);
+        drop(b);
+        // an owned Bump moves with the vector
+        let mut vec = Vector::new_in(Bump::with_capacity(256));
+        vec.resize(10, 7u64);
+        assert_eq!(vec.allocator().used(), vec.capacity() * 8);
+    }
+
+    // Delegates to the global allocator until its budget of allocations
+    // and reallocations runs out.
+    struct Failing {
+        budget: std::cell::Cell<usize>,
+    }
+
+    impl Failing {
+        fn new(budget: usize) -> Self {
+            Self { budget: std::cell::Cell::new(budget) }
+        }
+
+        fn spend(&self) -> Result<(), AllocError> {
+            match self.budget.get() {
+                0 => Err(AllocError),
+                n => {
+                    self.budget.set(n - 1);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    unsafe impl Allocator for Failing {
+        fn allocate(&self, layout: std::alloc::Layout) -> Result<std::ptr::NonNull<u8>, AllocError> {
+            self.spend()?;
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: std::ptr::NonNull<u8>, layout: std::alloc::Layout) {
+            Global.deallocate(ptr, layout)
+        }
+
+        unsafe fn reallocate(
+            &self,
+            ptr: std::ptr::NonNull<u8>,
+            old_layout: std::alloc::Layout,
+            new_layout: std::alloc::Layout,
+        ) -> Result<std::ptr::NonNull<u8>, AllocError> {
+            self.spend()?;
+            Global.reallocate(ptr, old_layout, new_layout)
+        }
+    }
+
+    #[test]
+    fn try1() {
+        let failing = Failing::new(2);
+        let mut vec = Vector::new_in(&failing);
+        for i in 0..8 {
+            assert_eq!(vec.try_push(i), Ok(()));
+        }
+        assert_eq!(vec.capacity(), 8);
+
+        // out of memory: the vector is left as it was, and the value returned
+        let (value, err) = vec.try_push(8).unwrap_err();
+        assert_eq!(value, 8);
+        assert_eq!(
+            err,
+            TryReserveError::AllocError {
+                layout: std::alloc::Layout::array::<i32>(16).unwrap()
+            }
+        );
+        assert_eq!(err.to_string(), "failed to allocate 64 bytes");
+        assert!(vec.try_reserve_exact(1).is_err());
+        assert!(vec.try_extend_from_slice(&[1, 2, 3]).is_err());
+        assert!(vec.iter().copied().eq(0..8));
+        assert_eq!(vec.capacity(), 8);
+
+        // reserving within capacity needs no memory
+        vec.truncate(4);
+        assert_eq!(vec.try_reserve(4), Ok(()));
+        assert_eq!(vec.try_extend_from_slice(&[4, 5, 6, 7]), Ok(()));
+        assert!(vec.iter().copied().eq(0..8));
+
+        assert!(Vector::<u8, _>::try_with_capacity_in(1, &failing).is_err());
+        assert!(Vector::<(), _>::try_with_capacity_in(1, &failing).is_ok());
+    }
+
+    #[test]
+    fn try2() {
+        let mut vec = Vector::<u64>::try_with_capacity(4).unwrap();
+        assert_eq!(vec.try_reserve(usize::MAX / 4), Err(TryReserveError::CapacityOverflow));
+        assert_eq!(vec.try_reserve_exact(usize::MAX), Err(TryReserveError::CapacityOverflow));
+        assert_eq!(vec.try_push(1), Ok(()));
+        assert!(Vector::<u64>::try_with_capacity(usize::MAX / 4).is_err());
+
+        // only overflowing the length can fail for a ZST
+        let mut vec = Vector::new();
+        vec.push(());
+        assert_eq!(vec.try_reserve(usize::MAX - 1), Ok(()));
+        assert_eq!(vec.try_reserve(usize::MAX), Err(TryReserveError::CapacityOverflow));
+        assert_eq!(TryReserveError::CapacityOverflow.to_string(), "capacity overflow");
+    }
 }
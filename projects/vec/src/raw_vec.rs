@@ -0,0 +1,190 @@
+// raw_vec.rs
+// The allocation behind a Vector: a pointer and a capacity, with no notion
+// of which slots hold initialized values.
+//
+// Zero-sized types never allocate. Their buffer is a dangling pointer with
+// capacity usize::MAX, since any number of them fits in no memory at all.
+
+use std::alloc::{self, Layout};
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr::NonNull;
+
+use crate::allocator::{Allocator, Global};
+
+/// The error returned when reserving capacity fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TryReserveError {
+    /// The requested capacity exceeds what a vector can address.
+    CapacityOverflow,
+    /// The allocator could not provide the memory.
+    AllocError { layout: Layout },
+}
+
+impl fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryReserveError::CapacityOverflow => write!(f, "capacity overflow"),
+            TryReserveError::AllocError { layout } => {
+                write!(f, "failed to allocate {} bytes", layout.size())
+            }
+        }
+    }
+}
+
+impl Error for TryReserveError {}
+
+// Turn a failure to reserve into a panic or, for allocator failure, an abort.
+fn handle_reserve<T>(result: Result<T, TryReserveError>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(TryReserveError::CapacityOverflow) => panic!("capacity overflow"),
+        Err(TryReserveError::AllocError { layout }) => alloc::handle_alloc_error(layout),
+    }
+}
+
+// the smallest allocation reserve() makes
+const MIN_CAPACITY: usize = 4;
+
+/// The capacity to grow a buffer of `cap` slots to when it needs room for
+/// `required`, at least doubling it to keep repeated growth cheap.
+pub(crate) fn grown_capacity(cap: usize, required: usize) -> usize {
+    // cap * 2 can't overflow, as the allocation is at most isize::MAX bytes
+    required.max(cap * 2).max(MIN_CAPACITY)
+}
+
+pub(crate) struct RawVec<T, A: Allocator = Global> {
+    ptr: NonNull<T>,
+    cap: usize,
+    alloc: A,
+    // the buffer owns its Ts, as far as drop checking is concerned
+    _marker: PhantomData<T>,
+}
+
+// SAFETY: the buffer is uniquely owned, like a Box<[T]>, so it can move
+// between or be shared by threads whenever its contents and allocator can
+unsafe impl<T: Send, A: Allocator + Send> Send for RawVec<T, A> {}
+unsafe impl<T: Sync, A: Allocator + Sync> Sync for RawVec<T, A> {}
+
+impl<T, A: Allocator> RawVec<T, A> {
+    pub(crate) const IS_ZST: bool = mem::size_of::<T>() == 0;
+
+    pub(crate) fn new_in(alloc: A) -> Self {
+        Self {
+            ptr: NonNull::dangling(),
+            cap: if Self::IS_ZST { usize::MAX } else { 0 },
+            alloc,
+            _marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn ptr(&self) -> *mut T {
+        self.ptr.as_ptr()
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    pub(crate) fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    pub(crate) fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        handle_reserve(Self::try_with_capacity_in(capacity, alloc))
+    }
+
+    pub(crate) fn try_with_capacity_in(capacity: usize, alloc: A) -> Result<Self, TryReserveError> {
+        let mut buf = Self::new_in(alloc);
+        if !Self::IS_ZST && capacity > 0 {
+            buf.reallocate(capacity)?;
+        }
+        Ok(buf)
+    }
+
+    /// Make room for at least `additional` slots past the first `len`,
+    /// at least doubling the capacity if it has to grow.
+    pub(crate) fn reserve(&mut self, len: usize, additional: usize) {
+        handle_reserve(self.try_reserve(len, additional));
+    }
+
+    pub(crate) fn try_reserve(&mut self, len: usize, additional: usize) -> Result<(), TryReserveError> {
+        if self.cap - len >= additional {
+            return Ok(());
+        }
+
+        // only reachable for ZSTs once len + additional overflows
+        let required = len.checked_add(additional).ok_or(TryReserveError::CapacityOverflow)?;
+        self.reallocate(grown_capacity(self.cap, required))
+    }
+
+    /// Make room for exactly `additional` slots past the first `len`.
+    pub(crate) fn reserve_exact(&mut self, len: usize, additional: usize) {
+        handle_reserve(self.try_reserve_exact(len, additional));
+    }
+
+    pub(crate) fn try_reserve_exact(&mut self, len: usize, additional: usize) -> Result<(), TryReserveError> {
+        if self.cap - len >= additional {
+            return Ok(());
+        }
+
+        let required = len.checked_add(additional).ok_or(TryReserveError::CapacityOverflow)?;
+        self.reallocate(required)
+    }
+
+    /// Shrink the allocation to `cap` slots, freeing it if cap is zero.
+    pub(crate) fn shrink_to(&mut self, cap: usize) {
+        assert!(cap <= self.cap, "tried to shrink to a larger capacity");
+        if Self::IS_ZST || cap == self.cap {
+            return;
+        }
+
+        if cap == 0 {
+            // SAFETY: ptr was allocated with the layout of self.cap slots
+            unsafe { self.alloc.deallocate(self.ptr.cast(), Self::layout(self.cap)) };
+            self.ptr = NonNull::dangling();
+            self.cap = 0;
+        } else {
+            handle_reserve(self.reallocate(cap));
+        }
+    }
+
+    // Move the buffer to an allocation of exactly `new_cap` slots, leaving
+    // it untouched on failure. T must not be zero-sized, and new_cap must be
+    // nonzero.
+    fn reallocate(&mut self, new_cap: usize) -> Result<(), TryReserveError> {
+        debug_assert!(!Self::IS_ZST && new_cap > 0);
+
+        // Layout::array rejects sizes past isize::MAX, which alloc requires
+        let new_layout = Layout::array::<T>(new_cap).map_err(|_| TryReserveError::CapacityOverflow)?;
+        let ptr = if self.cap == 0 {
+            // new_layout has a nonzero size, as T is not a ZST
+            self.alloc.allocate(new_layout)
+        } else {
+            // SAFETY: ptr was allocated with the layout of cap slots, which
+            // has the same alignment as new_layout
+            unsafe { self.alloc.reallocate(self.ptr.cast(), Self::layout(self.cap), new_layout) }
+        };
+
+        let ptr = ptr.map_err(|_| TryReserveError::AllocError { layout: new_layout })?;
+        self.ptr = ptr.cast();
+        self.cap = new_cap;
+        Ok(())
+    }
+
+    // The layout of an existing allocation, which was checked when it was made.
+    fn layout(cap: usize) -> Layout {
+        Layout::array::<T>(cap).expect("layout of an existing allocation")
+    }
+}
+
+impl<T, A: Allocator> Drop for RawVec<T, A> {
+    fn drop(&mut self) {
+        if !Self::IS_ZST && self.cap != 0 {
+            // SAFETY: ptr was allocated with the layout of cap slots
+            unsafe { self.alloc.deallocate(self.ptr.cast(), Self::layout(self.cap)) };
+        }
+    }
+}
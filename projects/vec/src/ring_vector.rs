@@ -0,0 +1,722 @@
+// ring_vector.rs
+// A double-ended queue over a growable ring buffer, like std's VecDeque.
+//
+// The elements occupy `len` slots of the buffer starting at `head` and
+// wrapping around its end, so logical index i lives in physical slot
+// (head + i) % capacity. Pushing and popping at either end just moves
+// head or len; nothing else has to be shifted until the buffer grows.
+
+use std::iter::FusedIterator;
+use std::ops::{Index, IndexMut, Range, RangeBounds};
+use std::ptr;
+use std::slice;
+
+use crate::drain::slice_range;
+use crate::raw_vec::RawVec;
+
+pub struct RingVector<T> {
+    buf: RawVec<T>,
+    // the physical slot of the first element
+    head: usize,
+    len: usize,
+}
+
+impl<T> RingVector<T> {
+    pub fn new() -> Self {
+        Self {
+            buf: RawVec::new_in(crate::Global),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: RawVec::with_capacity_in(capacity, crate::Global),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    fn ptr(&self) -> *mut T {
+        self.buf.ptr()
+    }
+
+    // The physical slot `addend` past physical slot `idx`, for an addend no
+    // larger than the capacity.
+    fn wrap_add(&self, idx: usize, addend: usize) -> usize {
+        let cap = self.capacity();
+        if addend >= cap - idx {
+            addend - (cap - idx)
+        } else {
+            idx + addend
+        }
+    }
+
+    // The physical slot `subtrahend` before physical slot `idx`.
+    fn wrap_sub(&self, idx: usize, subtrahend: usize) -> usize {
+        if subtrahend <= idx {
+            idx - subtrahend
+        } else {
+            self.capacity() - (subtrahend - idx)
+        }
+    }
+
+    // The physical slot of logical index `idx`.
+    fn to_physical(&self, idx: usize) -> usize {
+        self.wrap_add(self.head, idx)
+    }
+
+    // Move `count` elements from logical index `src` to logical index `dst`,
+    // one at a time in whichever order keeps overlapping ranges intact.
+    unsafe fn move_elements(&mut self, src: usize, dst: usize, count: usize) {
+        let ptr = self.ptr();
+        let mut copy = |i: usize| {
+            let from = self.wrap_add(self.to_physical(src), i);
+            let to = self.wrap_add(self.to_physical(dst), i);
+            ptr::copy(ptr.add(from), ptr.add(to), 1);
+        };
+        if dst <= src {
+            (0..count).for_each(&mut copy);
+        } else {
+            (0..count).rev().for_each(&mut copy);
+        }
+    }
+
+    // Make room for one more element, keeping the elements in order across
+    // the wrap point of the larger buffer.
+    fn grow_if_full(&mut self) {
+        if self.len < self.capacity() {
+            return;
+        }
+
+        let old_cap = self.capacity();
+        self.buf.reserve(self.len, 1);
+        let new_cap = self.capacity();
+
+        // the elements from head to the old end stay put when reallocating,
+        // but the old end is no longer where the buffer wraps
+        if self.head <= old_cap - self.len {
+            return;
+        }
+        let head_len = old_cap - self.head;
+        let tail_len = self.len - head_len;
+        // SAFETY: the new slots past old_cap are unused, and both copies
+        // land within them
+        unsafe {
+            if tail_len < head_len && tail_len <= new_cap - old_cap {
+                // move the wrapped part to follow the old end
+                ptr::copy_nonoverlapping(self.ptr(), self.ptr().add(old_cap), tail_len);
+            } else {
+                // move the part before the old end to the new end
+                let new_head = new_cap - head_len;
+                ptr::copy(self.ptr().add(self.head), self.ptr().add(new_head), head_len);
+                self.head = new_head;
+            }
+        }
+    }
+
+    pub fn push_back(&mut self, value: T) {
+        self.grow_if_full();
+        // SAFETY: len < capacity, so the slot after the last element is unused
+        unsafe { ptr::write(self.ptr().add(self.to_physical(self.len)), value) };
+        self.len += 1;
+    }
+
+    pub fn push_front(&mut self, value: T) {
+        self.grow_if_full();
+        self.head = self.wrap_sub(self.head, 1);
+        // SAFETY: len < capacity, so the slot before the first element is unused
+        unsafe { ptr::write(self.ptr().add(self.head), value) };
+        self.len += 1;
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        // SAFETY: the last element is initialized, and forgotten by
+        // shortening the queue
+        Some(unsafe { ptr::read(self.ptr().add(self.to_physical(self.len))) })
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let head = self.head;
+        self.head = self.wrap_add(head, 1);
+        self.len -= 1;
+        // SAFETY: the first element is initialized, and forgotten by
+        // moving head past it
+        Some(unsafe { ptr::read(self.ptr().add(head)) })
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+        // SAFETY: index is in bounds, so its slot is initialized
+        unsafe { Some(&*self.ptr().add(self.to_physical(index))) }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len {
+            return None;
+        }
+        // SAFETY: index is in bounds, so its slot is initialized
+        unsafe { Some(&mut *self.ptr().add(self.to_physical(index))) }
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.len.checked_sub(1).and_then(|i| self.get(i))
+    }
+
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        self.get_mut(0)
+    }
+
+    pub fn back_mut(&mut self) -> Option<&mut T> {
+        self.len.checked_sub(1).and_then(move |i| self.get_mut(i))
+    }
+
+    // The physical ranges holding the logical range start..end, in order.
+    fn physical_ranges(&self, start: usize, end: usize) -> (Range<usize>, Range<usize>) {
+        let first = self.to_physical(start);
+        let len = end - start;
+        let room = self.capacity() - first;
+        if len <= room {
+            (first..first + len, 0..0)
+        } else {
+            (first..self.capacity(), 0..len - room)
+        }
+    }
+
+    fn slice_ranges(&self) -> (Range<usize>, Range<usize>) {
+        self.physical_ranges(0, self.len)
+    }
+
+    /// The elements as two slices, the second empty unless they wrap
+    /// around the end of the buffer.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let (front, back) = self.slice_ranges();
+        // SAFETY: both ranges hold initialized elements, and don't overlap
+        unsafe {
+            (
+                slice::from_raw_parts(self.ptr().add(front.start), front.len()),
+                slice::from_raw_parts(self.ptr().add(back.start), back.len()),
+            )
+        }
+    }
+
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        let (front, back) = self.slice_ranges();
+        // SAFETY: both ranges hold initialized elements, and don't overlap
+        unsafe {
+            (
+                slice::from_raw_parts_mut(self.ptr().add(front.start), front.len()),
+                slice::from_raw_parts_mut(self.ptr().add(back.start), back.len()),
+            )
+        }
+    }
+
+    /// Rearrange the buffer so the elements don't wrap, returning them as
+    /// one slice.
+    pub fn make_contiguous(&mut self) -> &mut [T] {
+        let (_, back) = self.slice_ranges();
+        if !back.is_empty() {
+            let free = self.capacity() - self.len;
+            // SAFETY: shifting the wrapped part up by the free space makes
+            // it abut the part at the end of the buffer, leaving every slot
+            // from `free` on initialized; a rotation then puts them in order
+            unsafe {
+                ptr::copy(self.ptr(), self.ptr().add(free), back.len());
+                let all = slice::from_raw_parts_mut(self.ptr().add(free), self.len);
+                all.rotate_left(back.len());
+            }
+            self.head = free;
+        }
+        self.as_mut_slices().0
+    }
+
+    /// Rotate the queue `n` places left, so the element at index n comes
+    /// first. Takes time proportional to the smaller of n and len - n.
+    pub fn rotate_left(&mut self, n: usize) {
+        assert!(n <= self.len, "rotation by {} is out of bounds for length {}", n, self.len);
+
+        let k = self.len - n;
+        if n <= k {
+            // move the first n elements past the last, in order, as once
+            // the free space runs out they land on slots already moved from
+            let dst = self.to_physical(self.len);
+            let ptr = self.ptr();
+            for i in 0..n {
+                let from = self.to_physical(i);
+                let to = self.wrap_add(dst, i);
+                // SAFETY: the n slots after the last element are free or are
+                // those of elements already moved
+                unsafe { ptr::copy(ptr.add(from), ptr.add(to), 1) };
+            }
+            self.head = self.to_physical(n);
+        } else {
+            self.rotate_right(k);
+        }
+    }
+
+    /// Rotate the queue `n` places right, so the last n elements come
+    /// first. Takes time proportional to the smaller of n and len - n.
+    pub fn rotate_right(&mut self, n: usize) {
+        assert!(n <= self.len, "rotation by {} is out of bounds for length {}", n, self.len);
+
+        let k = self.len - n;
+        if n <= k {
+            // move the last n elements before the first, from the back, as
+            // for rotate_left
+            let new_head = self.wrap_sub(self.head, n);
+            let ptr = self.ptr();
+            for i in (0..n).rev() {
+                let from = self.to_physical(k + i);
+                let to = self.wrap_add(new_head, i);
+                // SAFETY: the n slots before the first element are free or
+                // are those of elements already moved
+                unsafe { ptr::copy(ptr.add(from), ptr.add(to), 1) };
+            }
+            self.head = new_head;
+        } else {
+            self.rotate_left(k);
+        }
+    }
+
+    /// Drop the elements past the first `len`; a no-op if there are none.
+    pub fn truncate(&mut self, len: usize) {
+        while self.len > len {
+            // popping one at a time keeps the queue consistent if a
+            // destructor panics
+            drop(self.pop_back());
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+        self.head = 0;
+    }
+
+    /// Remove the elements in `range`, returning them through an iterator.
+    /// The range may span the wrap point of the buffer.
+    ///
+    /// As with Vector::drain, elements not consumed are dropped with the
+    /// iterator, and leaking it leaks them without breaking the queue.
+    pub fn drain<R>(&mut self, range: R) -> Drain<'_, T>
+    where
+        R: RangeBounds<usize>,
+    {
+        let len = self.len;
+        let Range { start, end } = slice_range(range, len);
+
+        // elements from start on are now owned by the Drain
+        self.len = start;
+        Drain {
+            idx: start,
+            end,
+            drain_start: start,
+            drain_len: end - start,
+            tail_len: len - end,
+            ring: self,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        let (front, back) = self.as_slices();
+        Iter { front: front.iter(), back: back.iter() }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        let (front, back) = self.as_mut_slices();
+        IterMut { front: front.iter_mut(), back: back.iter_mut() }
+    }
+}
+
+impl<T> Default for RingVector<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Clone for RingVector<T> {
+    fn clone(&self) -> Self {
+        let mut clone = Self::with_capacity(self.len);
+        for value in self.iter() {
+            clone.push_back(value.clone());
+        }
+        clone
+    }
+}
+
+impl<T> Index<usize> for RingVector<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("RingVector index out of bounds")
+    }
+}
+
+impl<T> IndexMut<usize> for RingVector<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("RingVector index out of bounds")
+    }
+}
+
+impl<T> Drop for RingVector<T> {
+    fn drop(&mut self) {
+        let (front, back) = self.as_mut_slices();
+        // SAFETY: both slices hold initialized elements; the buffer frees
+        // the memory itself
+        unsafe {
+            ptr::drop_in_place(front);
+            ptr::drop_in_place(back);
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a RingVector<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut RingVector<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+// Generates an iterator over the two slices of a RingVector, in order.
+macro_rules! iterator {
+    ($name:ident, $item:ty, $slice_iter:ty) => {
+        pub struct $name<'a, T> {
+            front: $slice_iter,
+            back: $slice_iter,
+        }
+
+        impl<'a, T> Iterator for $name<'a, T> {
+            type Item = $item;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.front.next().or_else(|| self.back.next())
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                let len = self.front.len() + self.back.len();
+                (len, Some(len))
+            }
+        }
+
+        impl<'a, T> DoubleEndedIterator for $name<'a, T> {
+            fn next_back(&mut self) -> Option<Self::Item> {
+                self.back.next_back().or_else(|| self.front.next_back())
+            }
+        }
+
+        impl<'a, T> ExactSizeIterator for $name<'a, T> {}
+
+        impl<'a, T> FusedIterator for $name<'a, T> {}
+    };
+}
+
+iterator!(Iter, &'a T, slice::Iter<'a, T>);
+iterator!(IterMut, &'a mut T, slice::IterMut<'a, T>);
+
+pub struct Drain<'a, T> {
+    ring: &'a mut RingVector<T>,
+    // the logical indices idx..end have yet to be yielded
+    idx: usize,
+    end: usize,
+    // the drained range, and the number of elements after it
+    drain_start: usize,
+    drain_len: usize,
+    tail_len: usize,
+}
+
+impl<'a, T> Iterator for Drain<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.idx == self.end {
+            return None;
+        }
+        let slot = self.ring.to_physical(self.idx);
+        self.idx += 1;
+        // SAFETY: the element at idx had not been yielded, and now won't be again
+        Some(unsafe { ptr::read(self.ring.ptr().add(slot)) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.idx;
+        (len, Some(len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Drain<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.idx == self.end {
+            return None;
+        }
+        self.end -= 1;
+        let slot = self.ring.to_physical(self.end);
+        // SAFETY: the element at end had not been yielded, and now won't be again
+        Some(unsafe { ptr::read(self.ring.ptr().add(slot)) })
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Drain<'a, T> {}
+
+impl<'a, T> FusedIterator for Drain<'a, T> {}
+
+impl<'a, T> Drop for Drain<'a, T> {
+    fn drop(&mut self) {
+        // Closes the gap left by the drained range, by moving whichever of
+        // the elements before or after it are fewer, even if dropping one of
+        // the remaining elements panics.
+        struct CloseGap<'r, 'a, T>(&'r mut Drain<'a, T>);
+
+        impl<'r, 'a, T> Drop for CloseGap<'r, 'a, T> {
+            fn drop(&mut self) {
+                let drain = &mut *self.0;
+                let ring = &mut *drain.ring;
+                let (head_len, drain_len, tail_len) = (drain.drain_start, drain.drain_len, drain.tail_len);
+                // SAFETY: the drained slots are vacant, and the elements on
+                // either side are initialized
+                unsafe {
+                    if head_len < tail_len {
+                        ring.move_elements(0, drain_len, head_len);
+                        ring.head = ring.to_physical(drain_len);
+                    } else {
+                        ring.move_elements(head_len + drain_len, head_len, tail_len);
+                    }
+                }
+                ring.len = head_len + tail_len;
+            }
+        }
+
+        let (front, back) = self.ring.physical_ranges(self.idx, self.end);
+        self.idx = self.end;
+        let ptr = self.ring.ptr();
+        let _guard = CloseGap(self);
+        // SAFETY: the elements idx..end were never yielded, and are
+        // forgotten by the Drain before being dropped
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(ptr.add(front.start), front.len()));
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(ptr.add(back.start), back.len()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    fn contents<T: Clone>(ring: &RingVector<T>) -> Vec<T> {
+        ring.iter().cloned().collect()
+    }
+
+    // A full ring of capacity 8 holding 0..8, with its head at slot 5.
+    fn wrapped() -> RingVector<i32> {
+        let mut ring = RingVector::with_capacity(8);
+        for i in 3..8 {
+            ring.push_back(i);
+        }
+        for i in (0..3).rev() {
+            ring.push_front(i);
+        }
+        assert_eq!(ring.head, 5);
+        ring
+    }
+
+    #[test]
+    fn push_pop1() {
+        let mut ring = RingVector::new();
+        for i in 0..5 {
+            ring.push_back(i);
+            ring.push_front(-i);
+        }
+        assert_eq!(ring.len(), 10);
+        assert_eq!(contents(&ring), vec![-4, -3, -2, -1, 0, 0, 1, 2, 3, 4]);
+        assert_eq!(ring.front(), Some(&-4));
+        assert_eq!(ring.back(), Some(&4));
+        assert_eq!(ring[2], -2);
+        ring[2] = 20;
+
+        assert_eq!(ring.pop_front(), Some(-4));
+        assert_eq!(ring.pop_back(), Some(4));
+        assert!(ring.iter().rev().copied().eq(vec![3, 2, 1, 0, 0, -1, 20, -3]));
+        while ring.pop_front().is_some() {}
+        assert!(ring.is_empty());
+        assert_eq!(ring.pop_back(), None);
+    }
+
+    #[test]
+    fn grow1() {
+        // growing keeps the order whichever part of a wrapped buffer is shorter
+        for front in 0..8 {
+            let mut ring = RingVector::with_capacity(8);
+            for i in front..8 {
+                ring.push_back(i);
+            }
+            for i in (0..front).rev() {
+                ring.push_front(i);
+            }
+            assert_eq!(ring.capacity(), 8);
+            ring.push_back(8);
+            ring.push_front(-1);
+            assert!(ring.iter().copied().eq(-1..9), "front {}", front);
+        }
+    }
+
+    #[test]
+    fn slices1() {
+        let mut ring = wrapped();
+        let (a, b) = ring.as_slices();
+        assert_eq!(a, [0, 1, 2]);
+        assert_eq!(b, [3, 4, 5, 6, 7]);
+
+        assert_eq!(ring.make_contiguous(), [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(ring.as_slices().1, []);
+
+        // with free space to spare
+        let mut ring = wrapped();
+        ring.pop_back();
+        ring.pop_back();
+        ring.push_front(-1);
+        ring.make_contiguous().reverse();
+        assert_eq!(contents(&ring), vec![5, 4, 3, 2, 1, 0, -1]);
+    }
+
+    #[test]
+    fn rotate1() {
+        for n in 0..=8 {
+            let mut ring = wrapped();
+            ring.rotate_left(n);
+            let mut expected: Vec<_> = (0..8).collect();
+            expected.rotate_left(n);
+            assert_eq!(contents(&ring), expected);
+            ring.rotate_right(n);
+            assert!(ring.iter().copied().eq(0..8));
+
+            // with less free space than is being moved
+            for free in 1..3 {
+                let mut ring = wrapped();
+                let mut expected: Vec<_> = (free..8).collect();
+                for _ in 0..free {
+                    ring.pop_front();
+                }
+                let n = n.min(expected.len());
+                ring.rotate_right(n);
+                expected.rotate_right(n);
+                assert_eq!(contents(&ring), expected);
+                ring.rotate_left(n);
+                expected.rotate_left(n);
+                assert_eq!(contents(&ring), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn drain1() {
+        // every range of a wrapped ring, consumed or not
+        for start in 0..=8 {
+            for end in start..=8 {
+                let mut ring = wrapped();
+                let drained: Vec<_> = ring.drain(start..end).collect();
+                assert!(drained.iter().copied().eq(start as i32..end as i32));
+                let expected: Vec<_> = (0..start as i32).chain(end as i32..8).collect();
+                assert_eq!(contents(&ring), expected);
+
+                let mut ring = wrapped();
+                drop(ring.drain(start..end));
+                assert_eq!(contents(&ring), expected);
+                ring.push_back(100);
+                assert_eq!(ring.back(), Some(&100));
+            }
+        }
+    }
+
+    #[test]
+    fn drain2() {
+        let token = Rc::new(());
+        let mut ring = RingVector::new();
+        for _ in 0..6 {
+            ring.push_back(token.clone());
+            ring.push_front(token.clone());
+        }
+        let mut drain = ring.drain(2..=9);
+        drop(drain.next());
+        drop(drain.next_back());
+        drop(drain);
+        assert_eq!(ring.len(), 4);
+        assert_eq!(Rc::strong_count(&token), 5);
+
+        std::mem::forget(ring.drain(1..));
+        assert_eq!(ring.len(), 1);
+        drop(ring);
+    }
+
+    #[test]
+    fn send1() {
+        // so a queue can sit behind a Mutex shared between threads
+        fn assert_send<T: Send>() {}
+        fn assert_sync<T: Sync>() {}
+        assert_send::<RingVector<i32>>();
+        assert_sync::<RingVector<String>>();
+        assert_send::<crate::Vector<i32>>();
+        assert_send::<crate::SmallVector<String, 4>>();
+
+        let queue = std::sync::Arc::new(std::sync::Mutex::new(RingVector::new()));
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let queue = queue.clone();
+                std::thread::spawn(move || queue.lock().unwrap().push_back(t))
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(queue.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn zst1() {
+        let mut ring = RingVector::new();
+        for _ in 0..10 {
+            ring.push_front(());
+            ring.push_back(());
+        }
+        ring.rotate_left(3);
+        assert_eq!(ring.drain(5..15).count(), 10);
+        assert_eq!(ring.len(), 10);
+        assert_eq!(ring.make_contiguous().len(), 10);
+    }
+}
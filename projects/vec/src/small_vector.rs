@@ -0,0 +1,474 @@
+// small_vector.rs
+// A vector that keeps up to N elements inline, only moving them into a
+// heap-allocated Vector once it outgrows that.
+//
+// Everything is written against a pointer and a length, whichever storage
+// is in use, so the inline and spilled cases share one implementation.
+
+use std::iter::FusedIterator;
+use std::mem::{self, MaybeUninit};
+use std::ops::{Deref, DerefMut, Range, RangeBounds};
+use std::ptr;
+use std::slice;
+
+use crate::drain::slice_range;
+use crate::raw_vec::grown_capacity;
+use crate::{Iter, IterMut, Vector};
+
+pub struct SmallVector<T, const N: usize> {
+    data: Data<T, N>,
+}
+
+enum Data<T, const N: usize> {
+    // the first len items are initialized
+    Inline { len: usize, items: [MaybeUninit<T>; N] },
+    Heap(Vector<T>),
+}
+
+impl<T, const N: usize> SmallVector<T, N> {
+    // ZSTs always fit inline
+    const INLINE_CAPACITY: usize = if mem::size_of::<T>() == 0 { usize::MAX } else { N };
+
+    pub fn new() -> Self {
+        Self {
+            data: Data::Inline {
+                len: 0,
+                // SAFETY: an array of MaybeUninit needs no initialization
+                items: unsafe { MaybeUninit::<[MaybeUninit<T>; N]>::uninit().assume_init() },
+            },
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut vec = Self::new();
+        vec.reserve(capacity);
+        vec
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.data {
+            Data::Inline { len, .. } => *len,
+            Data::Heap(heap) => heap.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        match &self.data {
+            Data::Inline { .. } => Self::INLINE_CAPACITY,
+            Data::Heap(heap) => heap.capacity(),
+        }
+    }
+
+    /// Whether the elements have moved to the heap.
+    pub fn spilled(&self) -> bool {
+        matches!(self.data, Data::Heap(_))
+    }
+
+    pub fn as_ptr(&self) -> *const T {
+        match &self.data {
+            Data::Inline { items, .. } => items.as_ptr() as *const T,
+            Data::Heap(heap) => heap.as_ptr(),
+        }
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        match &mut self.data {
+            Data::Inline { items, .. } => items.as_mut_ptr() as *mut T,
+            Data::Heap(heap) => heap.as_mut_ptr(),
+        }
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the first len elements are initialized
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.len()) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: as for as_slice, and the slice borrows the vector mutably
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), self.len()) }
+    }
+
+    // The first new_len elements must be initialized, and new_len must not
+    // exceed the capacity.
+    unsafe fn set_len(&mut self, new_len: usize) {
+        match &mut self.data {
+            Data::Inline { len, .. } => *len = new_len,
+            Data::Heap(heap) => heap.len = new_len,
+        }
+    }
+
+    /// Make room for at least `additional` more elements, spilling to the
+    /// heap if they won't fit inline.
+    pub fn reserve(&mut self, additional: usize) {
+        let len = match &mut self.data {
+            Data::Inline { len, .. } => *len,
+            Data::Heap(heap) => return heap.reserve(additional),
+        };
+        if Self::INLINE_CAPACITY - len >= additional {
+            return;
+        }
+
+        let required = len.checked_add(additional).expect("capacity overflow");
+        let mut heap = Vector::with_capacity(grown_capacity(N, required));
+        // SAFETY: the heap has room for the len inline elements, which are
+        // forgotten along with the inline array
+        unsafe {
+            ptr::copy_nonoverlapping(self.as_ptr(), heap.as_mut_ptr(), len);
+            heap.len = len;
+        }
+        self.data = Data::Heap(heap);
+    }
+
+    /// Free unused capacity, moving the elements back inline if they fit.
+    pub fn shrink_to_fit(&mut self) {
+        let heap = match &mut self.data {
+            Data::Heap(heap) if heap.len() <= N => heap,
+            Data::Heap(heap) => return heap.shrink_to_fit(),
+            Data::Inline { .. } => return,
+        };
+
+        let len = heap.len();
+        // SAFETY: the heap gives up ownership of its elements by having its
+        // length zeroed, after they are copied inline
+        unsafe {
+            heap.len = 0;
+            let src = heap.as_ptr();
+            let mut inline = Self::new();
+            ptr::copy_nonoverlapping(src, inline.as_mut_ptr(), len);
+            inline.set_len(len);
+            *self = inline;
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        if let Data::Heap(heap) = &mut self.data {
+            return heap.push(value);
+        }
+
+        let len = self.len();
+        if len == self.capacity() {
+            self.reserve(1);
+        }
+        // SAFETY: len < capacity, so the slot at len is unused
+        unsafe {
+            ptr::write(self.as_mut_ptr().add(len), value);
+            self.set_len(len + 1);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let len = self.len().checked_sub(1)?;
+        // SAFETY: the element at len - 1 is initialized, and forgotten by
+        // shortening the vector
+        unsafe {
+            self.set_len(len);
+            Some(ptr::read(self.as_ptr().add(len)))
+        }
+    }
+
+    /// Insert `value` at `index`, shifting the elements after it up.
+    pub fn insert(&mut self, index: usize, value: T) {
+        let len = self.len();
+        assert!(index <= len, "insertion index (is {}) should be <= len (is {})", index, len);
+
+        self.reserve(1);
+        // SAFETY: there is room for len + 1 elements, and the slot at index
+        // is overwritten once the elements from it on are moved up
+        unsafe {
+            let ptr = self.as_mut_ptr().add(index);
+            ptr::copy(ptr, ptr.add(1), len - index);
+            ptr::write(ptr, value);
+            self.set_len(len + 1);
+        }
+    }
+
+    /// Remove the element at `index`, shifting the elements after it down.
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        assert!(index < len, "removal index (is {}) should be < len (is {})", index, len);
+
+        // SAFETY: index is in bounds, and the hole left by reading it out is
+        // closed by moving the later elements down
+        unsafe {
+            let ptr = self.as_mut_ptr().add(index);
+            let value = ptr::read(ptr);
+            ptr::copy(ptr.add(1), ptr, len - index - 1);
+            self.set_len(len - 1);
+            value
+        }
+    }
+
+    /// Remove the element at `index`, replacing it with the last element.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        assert!(index < len, "swap_remove index (is {}) should be < len (is {})", index, len);
+
+        // SAFETY: index and len - 1 are in bounds, and the last slot is
+        // vacated by shortening the vector
+        unsafe {
+            let ptr = self.as_mut_ptr();
+            let value = ptr::read(ptr.add(index));
+            ptr::copy(ptr.add(len - 1), ptr.add(index), 1);
+            self.set_len(len - 1);
+            value
+        }
+    }
+
+    /// Drop the elements past the first `len`; a no-op if there are none.
+    pub fn truncate(&mut self, len: usize) {
+        let old_len = self.len();
+        if len >= old_len {
+            return;
+        }
+
+        // SAFETY: shortening first means a panicking destructor can't cause
+        // a double drop
+        unsafe {
+            self.set_len(len);
+            let tail = ptr::slice_from_raw_parts_mut(self.as_mut_ptr().add(len), old_len - len);
+            ptr::drop_in_place(tail);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Remove the elements in `range`, returning them through an iterator.
+    ///
+    /// As with Vector::drain, elements not consumed are dropped with the
+    /// iterator, and leaking it leaks them without breaking the vector.
+    pub fn drain<R>(&mut self, range: R) -> Drain<'_, T, N>
+    where
+        R: RangeBounds<usize>,
+    {
+        let len = self.len();
+        let Range { start, end } = slice_range(range, len);
+
+        // SAFETY: elements from start on are now owned by the Drain
+        unsafe { self.set_len(start) };
+        Drain {
+            idx: start,
+            end,
+            tail_start: end,
+            tail_len: len - end,
+            vec: self,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(self.as_slice())
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut::new(self.as_mut_slice())
+    }
+}
+
+impl<T, const N: usize> Default for SmallVector<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for SmallVector<T, N> {
+    fn clone(&self) -> Self {
+        let mut clone = Self::with_capacity(self.len());
+        for value in self.iter() {
+            // pushing within capacity keeps the length right if clone panics
+            clone.push(value.clone());
+        }
+        clone
+    }
+}
+
+impl<T, const N: usize> Deref for SmallVector<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> DerefMut for SmallVector<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T, const N: usize> Drop for SmallVector<T, N> {
+    fn drop(&mut self) {
+        // a spilled vector's elements are dropped by its Vector
+        if let Data::Inline { .. } = self.data {
+            // SAFETY: the first len elements are initialized
+            unsafe { ptr::drop_in_place(self.as_mut_slice()) };
+        }
+    }
+}
+
+pub struct Drain<'a, T, const N: usize> {
+    vec: &'a mut SmallVector<T, N>,
+    // the elements idx..end have yet to be yielded
+    idx: usize,
+    end: usize,
+    // the elements after the drained range, moved back on drop
+    tail_start: usize,
+    tail_len: usize,
+}
+
+impl<'a, T, const N: usize> Iterator for Drain<'a, T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.idx == self.end {
+            return None;
+        }
+        self.idx += 1;
+        // SAFETY: the element at idx had not been yielded, and now won't be again
+        Some(unsafe { ptr::read(self.vec.as_ptr().add(self.idx - 1)) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.idx;
+        (len, Some(len))
+    }
+}
+
+impl<'a, T, const N: usize> DoubleEndedIterator for Drain<'a, T, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.idx == self.end {
+            return None;
+        }
+        self.end -= 1;
+        // SAFETY: the element at end had not been yielded, and now won't be again
+        Some(unsafe { ptr::read(self.vec.as_ptr().add(self.end)) })
+    }
+}
+
+impl<'a, T, const N: usize> ExactSizeIterator for Drain<'a, T, N> {}
+
+impl<'a, T, const N: usize> FusedIterator for Drain<'a, T, N> {}
+
+impl<'a, T, const N: usize> Drop for Drain<'a, T, N> {
+    fn drop(&mut self) {
+        // Moves the tail back into place even if dropping one of the
+        // remaining elements panics.
+        struct MoveTail<'r, 'a, T, const N: usize>(&'r mut Drain<'a, T, N>);
+
+        impl<'r, 'a, T, const N: usize> Drop for MoveTail<'r, 'a, T, N> {
+            fn drop(&mut self) {
+                let drain = &mut *self.0;
+                let start = drain.vec.len();
+                // SAFETY: the tail is initialized, and the space before it
+                // was vacated by the drained elements
+                unsafe {
+                    if drain.tail_len > 0 && drain.tail_start != start {
+                        let ptr = drain.vec.as_mut_ptr();
+                        ptr::copy(ptr.add(drain.tail_start), ptr.add(start), drain.tail_len);
+                    }
+                    drain.vec.set_len(start + drain.tail_len);
+                }
+            }
+        }
+
+        let (idx, end) = (self.idx, self.end);
+        self.idx = end;
+        let ptr = self.vec.as_mut_ptr();
+        let _guard = MoveTail(self);
+        // SAFETY: the elements idx..end were never yielded, and are
+        // forgotten by the Drain before being dropped
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(ptr.add(idx), end - idx));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn inline1() {
+        let mut vec = SmallVector::<i32, 4>::new();
+        assert_eq!(vec.capacity(), 4);
+        for i in 0..4 {
+            vec.push(i);
+        }
+        assert!(!vec.spilled());
+        assert_eq!(vec.as_slice(), [0, 1, 2, 3]);
+
+        vec.insert(1, 10);
+        assert!(vec.spilled());
+        assert_eq!(vec.capacity(), 8);
+        assert_eq!(vec.as_slice(), [0, 10, 1, 2, 3]);
+
+        assert_eq!(vec.remove(0), 0);
+        assert_eq!(vec.swap_remove(0), 10);
+        assert_eq!(vec.pop(), Some(2));
+        vec.shrink_to_fit();
+        assert!(!vec.spilled());
+        assert_eq!(vec.as_slice(), [3, 1]);
+        assert!(vec.iter().copied().eq(vec![3, 1]));
+    }
+
+    #[test]
+    fn inline2() {
+        // nothing is dropped twice or leaked across spilling and shrinking
+        let token = Rc::new(());
+        let mut vec = SmallVector::<_, 2>::with_capacity(1);
+        assert!(!vec.spilled());
+        for _ in 0..5 {
+            vec.push(token.clone());
+        }
+        vec.truncate(2);
+        assert_eq!(Rc::strong_count(&token), 3);
+        vec.shrink_to_fit();
+        let clone = vec.clone();
+        assert_eq!(Rc::strong_count(&token), 5);
+        drop(vec);
+        drop(clone);
+        assert_eq!(Rc::strong_count(&token), 1);
+
+        assert!(SmallVector::<u8, 2>::with_capacity(3).spilled());
+    }
+
+    #[test]
+    fn drain1() {
+        let mut vec = SmallVector::<i32, 8>::new();
+        for i in 0..6 {
+            vec.push(i);
+        }
+        let res: Vec<_> = vec.drain(1..=2).collect();
+        assert_eq!(res, vec![1, 2]);
+        assert_eq!(vec.as_slice(), [0, 3, 4, 5]);
+
+        let mut drain = vec.drain(1..);
+        assert_eq!(drain.next_back(), Some(5));
+        drop(drain);
+        assert_eq!(vec.as_slice(), [0]);
+
+        for i in 0..10 {
+            vec.push(i);
+        }
+        assert!(vec.spilled());
+        std::mem::forget(vec.drain(2..));
+        assert_eq!(vec.as_slice(), [0, 0]);
+    }
+
+    #[test]
+    fn zst1() {
+        let mut vec = SmallVector::<(), 0>::new();
+        for _ in 0..100 {
+            vec.push(());
+        }
+        assert!(!vec.spilled());
+        assert_eq!(vec.len(), 100);
+        assert_eq!(vec.drain(..50).count(), 50);
+        assert_eq!(vec.len(), 50);
+    }
+}
@@ -0,0 +1,84 @@
+// splice.rs
+// Replacing a range of a Vector with the contents of an iterator.
+
+use std::iter::FusedIterator;
+use std::ops::RangeBounds;
+
+use crate::{Allocator, Drain, Global, Vector};
+
+impl<T, A: Allocator> Vector<T, A> {
+    /// Replace the elements in `range` with those of `replace_with`,
+    /// returning the removed elements through an iterator.
+    ///
+    /// The replacement happens when the iterator is dropped, whether or not
+    /// it was consumed. Its cost is smallest when `replace_with` reports an
+    /// exact size_hint.
+    pub fn splice<R, I>(&mut self, range: R, replace_with: I) -> Splice<'_, I::IntoIter, A>
+    where
+        R: RangeBounds<usize>,
+        I: IntoIterator<Item = T>,
+    {
+        Splice {
+            drain: self.drain(range),
+            replace_with: replace_with.into_iter(),
+        }
+    }
+}
+
+pub struct Splice<'a, I: Iterator, A: Allocator = Global> {
+    drain: Drain<'a, I::Item, A>,
+    replace_with: I,
+}
+
+impl<'a, I: Iterator, A: Allocator> Iterator for Splice<'a, I, A> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.drain.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.drain.size_hint()
+    }
+}
+
+impl<'a, I: Iterator, A: Allocator> DoubleEndedIterator for Splice<'a, I, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.drain.next_back()
+    }
+}
+
+impl<'a, I: Iterator, A: Allocator> ExactSizeIterator for Splice<'a, I, A> {}
+
+impl<'a, I: Iterator, A: Allocator> FusedIterator for Splice<'a, I, A> {}
+
+impl<'a, I: Iterator, A: Allocator> Drop for Splice<'a, I, A> {
+    fn drop(&mut self) {
+        self.drain.by_ref().for_each(drop);
+
+        // SAFETY: every drained element has been yielded, and dropping the
+        // Drain moves the tail back to the end of what was filled in
+        unsafe {
+            if !self.drain.fill(&mut self.replace_with) {
+                return;
+            }
+
+            // the lower bound is a safe guess at how much more is coming
+            let (lower, _) = self.replace_with.size_hint();
+            if lower > 0 {
+                self.drain.move_tail(lower);
+                if !self.drain.fill(&mut self.replace_with) {
+                    return;
+                }
+            }
+
+            // collect the rest to learn exactly how much room it needs
+            let mut collected = self.replace_with.by_ref().collect::<Vec<_>>().into_iter();
+            if collected.len() > 0 {
+                self.drain.move_tail(collected.len());
+                let filled = self.drain.fill(&mut collected);
+                debug_assert!(filled && collected.len() == 0);
+            }
+        }
+    }
+}